@@ -0,0 +1,895 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Result};
+use byte_unit::{Byte, UnitType};
+use chrono::{DateTime, Local, NaiveDateTime};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Row, TableState};
+
+use crate::worker::{Pending, Worker};
+
+/// How many archives are read at the same time, to list them or preview their members.
+const ARCHIVE_THREADS: usize = 2;
+
+/// Formats of archives which can be previewed, browsed and extracted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+}
+
+/// File name extensions of archives, the longer ones before their suffixes.
+const EXTENSIONS: [(&str, ArchiveFormat); 8] = [
+    (".tar.gz", ArchiveFormat::TarGz),
+    (".tgz", ArchiveFormat::TarGz),
+    (".tar.xz", ArchiveFormat::TarXz),
+    (".txz", ArchiveFormat::TarXz),
+    (".tar.zst", ArchiveFormat::TarZst),
+    (".tzst", ArchiveFormat::TarZst),
+    (".tar", ArchiveFormat::Tar),
+    (".zip", ArchiveFormat::Zip),
+];
+
+impl ArchiveFormat {
+    /// Recognize an archive by its file name.
+    pub(crate) fn detect(path: &Path) -> Option<Self> {
+        split_extension(&path.file_name()?.to_string_lossy()).map(|(_, format)| format)
+    }
+
+    /// The file name of an archive without its extension, e.g. `src` for `src.tar.gz`.
+    pub(crate) fn stem(path: &Path) -> Option<String> {
+        split_extension(&path.file_name()?.to_string_lossy()).map(|(stem, _)| stem.to_string())
+    }
+}
+
+/// Split an archive name into its stem and format, ignoring the case of the extension.
+fn split_extension(name: &str) -> Option<(&str, ArchiveFormat)> {
+    EXTENSIONS
+        .iter()
+        .find_map(|(extension, format)| {
+            let split = name.len().checked_sub(extension.len()).filter(|&i| i > 0)?;
+            let (stem, suffix) = (name.get(..split)?, name.get(split..)?);
+            suffix.eq_ignore_ascii_case(extension).then_some((stem, *format))
+        })
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let output = match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::TarXz => "tar.xz",
+            ArchiveFormat::TarZst => "tar.zst",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+/// A file, directory or symlink stored in an archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveEntry {
+    /// The path inside the archive, without a trailing slash.
+    pub path: String,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    pub size: u64,
+    pub modified: Option<NaiveDateTime>,
+    /// Unix permission bits, if the archive has them.
+    pub mode: Option<u32>,
+}
+
+impl ArchiveEntry {
+    /// `size  date  path`, as a line of the preview of an archive.
+    pub(crate) fn summary(&self) -> String {
+        let size = match self.is_dir {
+            true => String::new(),
+            false => format!("{:.1}", Byte::from(self.size).get_appropriate_unit(UnitType::Decimal)),
+        };
+        let suffix = if self.is_dir { "/" } else { "" };
+        format!("{:>10}  {:19}  {}{}", size, self.modified_str(), self.path, suffix)
+    }
+
+    fn modified_str(&self) -> String {
+        self.modified.map(|dt| dt.format("%Y-%m-%d %T").to_string()).unwrap_or_default()
+    }
+}
+
+/// Call `f` with every entry of the archive and a reader of its contents, until it returns
+/// `false`.
+fn for_each_entry(file: impl Read + Seek, format: ArchiveFormat, f: &mut dyn FnMut(ArchiveEntry, &mut dyn Read) -> bool) -> Result<()> {
+    let file = BufReader::new(file);
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(file)?;
+            for i in 0..zip.len() {
+                let mut member = zip.by_index(i)?;
+                let entry = ArchiveEntry {
+                    path: member.name().trim_end_matches('/').to_string(),
+                    is_dir: member.is_dir(),
+                    is_symlink: member.is_symlink(),
+                    size: member.size(),
+                    modified: member.last_modified().and_then(|dt| NaiveDateTime::try_from(dt).ok()),
+                    mode: member.unix_mode(),
+                };
+                if !f(entry, &mut member) {
+                    break;
+                }
+            }
+            Ok(())
+        },
+        ArchiveFormat::Tar => for_each_tar_entry(file, f),
+        ArchiveFormat::TarGz => for_each_tar_entry(flate2::read::MultiGzDecoder::new(file), f),
+        ArchiveFormat::TarXz => for_each_tar_entry(liblzma::read::XzDecoder::new(file), f),
+        ArchiveFormat::TarZst => for_each_tar_entry(zstd::stream::read::Decoder::with_buffer(file)?, f),
+    }
+}
+
+fn for_each_tar_entry(reader: impl Read, f: &mut dyn FnMut(ArchiveEntry, &mut dyn Read) -> bool) -> Result<()> {
+    let mut tar = tar::Archive::new(reader);
+    for member in tar.entries()? {
+        let mut member = member?;
+        let header = member.header();
+        let entry_type = header.entry_type();
+        let modified = header.mtime().ok()
+            .and_then(|secs| DateTime::from_timestamp(secs as i64, 0))
+            .map(|dt| dt.with_timezone(&Local).naive_local());
+        let entry = ArchiveEntry {
+            path: member.path()?.to_string_lossy().trim_end_matches('/').to_string(),
+            is_dir: entry_type.is_dir(),
+            is_symlink: entry_type.is_symlink() || entry_type.is_hard_link(),
+            size: member.size(),
+            modified,
+            mode: header.mode().ok(),
+        };
+        // pax headers and the like aren't entries of their own
+        if !(entry_type.is_file() || entry.is_dir || entry.is_symlink) {
+            continue;
+        }
+        if !f(entry, &mut member) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// The first `limit` entries of the archive. Also returns whether there are more.
+pub(crate) fn list_entries(path: &Path, format: ArchiveFormat, limit: usize) -> Result<(Vec<ArchiveEntry>, bool)> {
+    let mut entries = vec![];
+    let mut more = false;
+    for_each_entry(File::open(path)?, format, &mut |entry, _| {
+        if entries.len() == limit {
+            more = true;
+            return false;
+        }
+        entries.push(entry);
+        true
+    })?;
+    Ok((entries, more))
+}
+
+/// List the first `limit` entries of the archive in a worker thread, like `list_entries()`.
+/// Dropping the pending entries skips the archive, unless reading it started already.
+pub(crate) fn load_entries(path: &Path, format: ArchiveFormat, limit: usize) -> Pending<(Vec<ArchiveEntry>, bool)> {
+    let path = path.to_path_buf();
+    worker().run(move || list_entries(&path, format, limit))
+}
+
+/// Read up to `limit` bytes of the member at `member_path`.
+pub(crate) fn read_member(path: &Path, format: ArchiveFormat, member_path: &str, limit: u64) -> Result<Vec<u8>> {
+    let mut contents = None;
+    let mut error = None;
+    for_each_entry(File::open(path)?, format, &mut |entry, reader| {
+        if entry.path != member_path {
+            return true;
+        }
+        let mut buf = vec![];
+        match reader.take(limit).read_to_end(&mut buf) {
+            Ok(_) => contents = Some(buf),
+            Err(e) => error = Some(e),
+        }
+        false
+    })?;
+    if let Some(e) = error {
+        return Err(e.into());
+    }
+    contents.ok_or_else(|| anyhow!("{} not found in the archive", member_path))
+}
+
+/// Read a member in a worker thread, like `read_member()`, as the archive may have to be
+/// decompressed up to it.
+pub(crate) fn load_member(path: &Path, format: ArchiveFormat, member_path: &str, limit: u64) -> Pending<Vec<u8>> {
+    let (path, member_path) = (path.to_path_buf(), member_path.to_string());
+    worker().run(move || read_member(&path, format, &member_path, limit))
+}
+
+fn worker() -> &'static Worker {
+    static WORKER: OnceLock<Worker> = OnceLock::new();
+    WORKER.get_or_init(|| Worker::new("archive", ARCHIVE_THREADS))
+}
+
+/// What extracting members of an archive did.
+#[derive(Debug, Default)]
+pub struct ExtractReport {
+    /// The files and directories created directly in the destination.
+    pub created: Vec<PathBuf>,
+    /// One per entry which could not be extracted.
+    pub errors: Vec<String>,
+}
+
+/// Extract the `members` (files or directories, with everything below them; all entries if
+/// empty) into `dest`, dropping `prefix` (the archive directory they are in) from their paths.
+/// Existing files are not replaced. `progress` is called with the number of bytes of the archive
+/// read so far.
+pub(crate) fn extract_members(path: &Path, format: ArchiveFormat, members: &[String], prefix: &str, dest: &Path,
+                              progress: &mut dyn FnMut(u64)) -> Result<ExtractReport> {
+    let mut report = ExtractReport::default();
+    let mut created: HashSet<PathBuf> = HashSet::new();
+    let file = ProgressReader { inner: File::open(path)?, count: 0, progress };
+    for_each_entry(file, format, &mut |entry, reader| {
+        let wanted = members.is_empty() || members
+            .iter()
+            .any(|m| entry.path == *m || entry.path.starts_with(format!("{}/", m).as_str()));
+        if !wanted {
+            return true;
+        }
+        let Some(relative) = entry.path.strip_prefix(prefix).and_then(safe_relative_path) else {
+            report.errors.push(format!("{}: unsafe path, skipped", entry.path));
+            return true;
+        };
+        if let Some(link) = link_on_the_way(dest, &relative) {
+            report.errors.push(format!("{}: {} is a link, skipped", entry.path, link.display()));
+            return true;
+        }
+        let target = dest.join(&relative);
+        // remember what is new directly in `dest`, to be able to undo the extraction
+        if let Some(Component::Normal(top)) = relative.components().next() {
+            let top = dest.join(top);
+            if !created.contains(&top) && fs::symlink_metadata(&top).is_err() {
+                created.insert(top.clone());
+                report.created.push(top);
+            }
+        }
+        if let Err(e) = extract_entry(&entry, reader, &target) {
+            report.errors.push(format!("{}: {}", entry.path, e));
+        }
+        true
+    })?;
+    Ok(report)
+}
+
+fn extract_entry(entry: &ArchiveEntry, reader: &mut dyn Read, target: &Path) -> Result<()> {
+    if entry.is_dir {
+        fs::create_dir_all(target)?;
+        return Ok(());
+    }
+    if entry.is_symlink {
+        return Err(anyhow!("links are not extracted"));
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::symlink_metadata(target).is_ok() {
+        return Err(anyhow!("{} already exists", target.display()));
+    }
+    let mut file = fs::OpenOptions::new().write(true).create_new(true).open(target)?;
+    io::copy(reader, &mut file)?;
+    if let Some(mode) = entry.mode {
+        fs::set_permissions(target, fs::Permissions::from_mode(mode & 0o777))?;
+    }
+    Ok(())
+}
+
+/// The path of an archive member relative to where it is extracted to, unless it is absolute
+/// or leads out of there (e.g. `../../.bashrc`).
+fn safe_relative_path(path: &str) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(name) => relative.push(name),
+            Component::CurDir => {},
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!relative.as_os_str().is_empty()).then_some(relative)
+}
+
+/// The first path on the way from `dest` down to `dest/relative` which is a symlink, as
+/// creating directories or files through it may write outside of `dest`.
+fn link_on_the_way(dest: &Path, relative: &Path) -> Option<PathBuf> {
+    let mut path = dest.to_path_buf();
+    for component in relative.components() {
+        path.push(component);
+        if fs::symlink_metadata(&path).is_ok_and(|meta| meta.file_type().is_symlink()) {
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// A file, directory or symlink to be stored in a new archive.
+#[derive(Debug)]
+struct SourceItem {
+    path: PathBuf,
+    /// The path inside the archive.
+    name: String,
+    meta: fs::Metadata,
+}
+
+/// Create the archive `dest` with the `sources` (files or directories, with everything below
+/// them), stored by their names. Items which can't be read are skipped and reported as errors
+/// one by one. `progress` is called with the number of bytes of the sources read so far.
+pub(crate) fn create_archive(dest: &Path, format: ArchiveFormat, sources: &[PathBuf],
+                             progress: &mut dyn FnMut(u64)) -> Result<Vec<String>> {
+    let mut errors = vec![];
+    let mut items = vec![];
+    for source in sources {
+        match source.file_name() {
+            Some(name) => collect_items(source, name.to_string_lossy().to_string(), dest, &mut items, &mut errors),
+            None => errors.push(format!("{}: invalid file name", source.display())),
+        }
+    }
+    let file = fs::OpenOptions::new().write(true).create_new(true).open(dest)?;
+    let result = match format {
+        ArchiveFormat::Zip => write_zip(file, &items, progress, &mut errors),
+        ArchiveFormat::Tar => write_tar(file, &items, progress, &mut errors).map(drop),
+        ArchiveFormat::TarGz => write_tar(flate2::write::GzEncoder::new(file, flate2::Compression::default()),
+                                          &items, progress, &mut errors)
+            .and_then(|encoder| Ok(encoder.finish().map(drop)?)),
+        ArchiveFormat::TarXz => write_tar(liblzma::write::XzEncoder::new(file, 6), &items, progress, &mut errors)
+            .and_then(|encoder| Ok(encoder.finish().map(drop)?)),
+        ArchiveFormat::TarZst => zstd::stream::write::Encoder::new(file, 0)
+            .map_err(anyhow::Error::from)
+            .and_then(|encoder| write_tar(encoder, &items, progress, &mut errors))
+            .and_then(|encoder| Ok(encoder.finish().map(drop)?)),
+    };
+    if let Err(e) = result {
+        // don't leave a broken archive behind
+        let _result = fs::remove_file(dest);
+        return Err(e);
+    }
+    Ok(errors)
+}
+
+/// Add the item at `path` and, for a directory, everything below it, skipping the archive being
+/// written.
+fn collect_items(path: &Path, name: String, dest: &Path, items: &mut Vec<SourceItem>, errors: &mut Vec<String>) {
+    if path == dest {
+        return;
+    }
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(e) => {
+            errors.push(format!("{}: {}", path.display(), e));
+            return;
+        },
+    };
+    let is_dir = meta.is_dir();
+    items.push(SourceItem { path: path.to_path_buf(), name: name.clone(), meta });
+    if !is_dir {
+        return;
+    }
+    match fs::read_dir(path) {
+        Ok(entries) => {
+            let mut children: Vec<_> = entries.filter_map(|e| e.ok()).map(|e| e.file_name()).collect();
+            children.sort();
+            for child in children {
+                let child_name = format!("{}/{}", name, child.to_string_lossy());
+                collect_items(&path.join(&child), child_name, dest, items, errors);
+            }
+        },
+        Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+    }
+}
+
+fn write_zip(file: File, items: &[SourceItem], progress: &mut dyn FnMut(u64), errors: &mut Vec<String>) -> Result<()> {
+    let mut zip = zip::ZipWriter::new(file);
+    let mut bytes_read = 0;
+    for item in items {
+        let mut options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .unix_permissions(item.meta.permissions().mode() & 0o777)
+            .large_file(item.meta.len() >= u32::MAX as u64);
+        let modified = item.meta.modified().ok()
+            .map(|time| DateTime::<Local>::from(time).naive_local())
+            .and_then(|dt| zip::DateTime::try_from(dt).ok());
+        if let Some(modified) = modified {
+            options = options.last_modified_time(modified);
+        }
+        let file_type = item.meta.file_type();
+        if file_type.is_dir() {
+            zip.add_directory(format!("{}/", item.name), options)?;
+        } else if file_type.is_symlink() {
+            match fs::read_link(&item.path) {
+                Ok(target) => zip.add_symlink(&item.name, target.to_string_lossy(), options)?,
+                Err(e) => errors.push(format!("{}: {}", item.path.display(), e)),
+            }
+        } else if file_type.is_file() {
+            match File::open(&item.path) {
+                Ok(file) => {
+                    zip.start_file(&item.name, options)?;
+                    let mut reader = ProgressReader { inner: file, count: bytes_read, progress: &mut *progress };
+                    io::copy(&mut reader, &mut zip)?;
+                    bytes_read = reader.count;
+                },
+                Err(e) => errors.push(format!("{}: {}", item.path.display(), e)),
+            }
+        } else {
+            errors.push(format!("{}: not a regular file, skipped", item.path.display()));
+        }
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// Write the items as a tar archive to `writer`, which is returned to finish compressing.
+fn write_tar<W: Write>(writer: W, items: &[SourceItem], progress: &mut dyn FnMut(u64), errors: &mut Vec<String>) -> Result<W> {
+    let mut tar = tar::Builder::new(writer);
+    let mut bytes_read = 0;
+    for item in items {
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&item.meta);
+        let file_type = item.meta.file_type();
+        if file_type.is_dir() {
+            header.set_size(0);
+            tar.append_data(&mut header, &item.name, io::empty())?;
+        } else if file_type.is_symlink() {
+            match fs::read_link(&item.path) {
+                Ok(target) => {
+                    header.set_size(0);
+                    tar.append_link(&mut header, &item.name, target)?;
+                },
+                Err(e) => errors.push(format!("{}: {}", item.path.display(), e)),
+            }
+        } else if file_type.is_file() {
+            match File::open(&item.path) {
+                Ok(file) => {
+                    // the size in the header must match, even if the file changes meanwhile
+                    let file = file.take(item.meta.len());
+                    let mut reader = ProgressReader { inner: file, count: bytes_read, progress: &mut *progress };
+                    tar.append_data(&mut header, &item.name, &mut reader)?;
+                    bytes_read = reader.count;
+                },
+                Err(e) => errors.push(format!("{}: {}", item.path.display(), e)),
+            }
+        } else {
+            errors.push(format!("{}: not a regular file, skipped", item.path.display()));
+        }
+    }
+    Ok(tar.into_inner()?)
+}
+
+/// An entry listed while browsing an archive directory.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArchiveItem {
+    /// Goes up to the parent directory of the archive.
+    Parent,
+    /// `entry.path` is the full path; directories may exist only implied by the paths below them.
+    Entry(ArchiveEntry),
+}
+
+/// Browsing the directories of an archive like the file system, read-only.
+#[derive(Debug)]
+pub struct ArchiveView {
+    pub path: PathBuf,
+    pub format: ArchiveFormat,
+    entries: Vec<ArchiveEntry>,
+    /// The archive directory being browsed: "" or a path with a trailing slash.
+    pub prefix: String,
+    pub items: Vec<ArchiveItem>,
+    pub state: TableState,
+    /// Full paths of the marked entries.
+    pub marked: HashSet<String>,
+    /// The entries, while they are read in a worker thread.
+    loading: Option<Pending<(Vec<ArchiveEntry>, bool)>>,
+}
+
+impl ArchiveView {
+    /// Start reading the entries of the archive; `poll()` picks them up.
+    pub(crate) fn open(path: &Path, format: ArchiveFormat) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            format,
+            entries: vec![],
+            prefix: String::new(),
+            items: vec![],
+            state: TableState::default(),
+            marked: HashSet::new(),
+            loading: Some(load_entries(path, format, usize::MAX)),
+        }
+    }
+
+    pub(crate) fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    /// Pick up the entries once they are read, and list the top directory. Returns `None` while
+    /// they are still being read.
+    pub(crate) fn poll(&mut self) -> Option<Result<()>> {
+        let result = self.loading.as_ref()?.try_recv()?;
+        self.loading = None;
+        Some(result.map(|(entries, _)| {
+            self.entries = entries;
+            self.list(None);
+        }))
+    }
+
+    /// List the children of `prefix`, selecting `select` (a full path) if given.
+    fn list(&mut self, select: Option<&str>) {
+        let mut items = vec![];
+        if !self.prefix.is_empty() {
+            items.push(ArchiveItem::Parent);
+        }
+        // directories are implied by the paths below them, too, so they are keyed by path
+        let mut dirs: HashMap<String, ArchiveEntry> = HashMap::new();
+        let mut files: Vec<ArchiveEntry> = vec![];
+        for entry in &self.entries {
+            let Some(rest) = entry.path.strip_prefix(self.prefix.as_str()) else {
+                continue;
+            };
+            match rest.split_once('/') {
+                Some((dir, _)) => {
+                    let dir_path = format!("{}{}", self.prefix, dir);
+                    dirs.entry(dir_path).or_insert_with_key(|path| {
+                        ArchiveEntry { path: path.clone(), is_dir: true, is_symlink: false, size: 0, modified: None, mode: None }
+                    });
+                },
+                None if rest.is_empty() => {},
+                None if entry.is_dir => {
+                    dirs.entry(entry.path.clone())
+                        .and_modify(|dir| dir.modified = entry.modified)
+                        .or_insert_with(|| entry.clone());
+                },
+                None => files.push(entry.clone()),
+            }
+        }
+        let mut dirs: Vec<ArchiveEntry> = dirs.into_values().collect();
+        dirs.sort_by(|a, b| a.path.cmp(&b.path));
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        items.extend(dirs.into_iter().chain(files).map(ArchiveItem::Entry));
+        let selected = select
+            .and_then(|path| items.iter().position(|item| matches!(item, ArchiveItem::Entry(e) if e.path == path)))
+            .unwrap_or(0);
+        self.state.select((!items.is_empty()).then_some(selected));
+        self.items = items;
+    }
+
+    /// The rows of the entries in the directory being browsed: name, size and date.
+    pub(crate) fn rows(&self) -> Vec<Row<'static>> {
+        let default_style = Style::default().fg(Color::White);
+        let dir_style = default_style.fg(Color::LightGreen);
+        let link_style = default_style.add_modifier(Modifier::ITALIC);
+        let marked_style = default_style.fg(Color::LightYellow).add_modifier(Modifier::BOLD);
+        self.items
+            .iter()
+            .map(|item| match item {
+                ArchiveItem::Parent => Row::new(vec![".."]).style(dir_style),
+                ArchiveItem::Entry(entry) => {
+                    let mut style = default_style;
+                    let mut size = format!("{:.1}", Byte::from(entry.size).get_appropriate_unit(UnitType::Decimal));
+                    if entry.is_dir {
+                        style = dir_style;
+                        size.clear();
+                    }
+                    if entry.is_symlink {
+                        style = link_style;
+                    }
+                    if self.marked.contains(&entry.path) {
+                        style = marked_style;
+                    }
+                    Row::new(vec![self.name(entry).to_string(), size, entry.modified_str()]).style(style)
+                },
+            })
+            .collect()
+    }
+
+    /// The name of an entry in the directory being browsed.
+    pub(crate) fn name<'a>(&self, entry: &'a ArchiveEntry) -> &'a str {
+        entry.path.strip_prefix(self.prefix.as_str()).unwrap_or(&entry.path)
+    }
+
+    pub(crate) fn selected_item(&self) -> Option<&ArchiveItem> {
+        self.state.selected().and_then(|i| self.items.get(i))
+    }
+
+    /// Go into the selected directory, or up for the parent item. Returns `false` if the
+    /// selection is a file, or the parent of the archive itself.
+    pub(crate) fn enter(&mut self) -> bool {
+        match self.selected_item().cloned() {
+            Some(ArchiveItem::Parent) => self.leave(),
+            Some(ArchiveItem::Entry(entry)) if entry.is_dir => {
+                self.prefix = format!("{}/", entry.path);
+                self.list(None);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Go up to the parent directory. Returns `false` at the top of the archive.
+    pub(crate) fn leave(&mut self) -> bool {
+        let Some(dir) = self.prefix.strip_suffix('/').map(str::to_string) else {
+            return false;
+        };
+        self.prefix = match dir.rsplit_once('/') {
+            Some((parent, _)) => format!("{}/", parent),
+            None => String::new(),
+        };
+        self.list(Some(dir.as_str()));
+        true
+    }
+
+    pub(crate) fn select_next(&mut self) {
+        let last = self.items.len().saturating_sub(1);
+        self.state.select(self.state.selected().map(|i| (i + 1).min(last)));
+    }
+
+    pub(crate) fn select_previous(&mut self) {
+        self.state.select(self.state.selected().map(|i| i.saturating_sub(1)));
+    }
+
+    pub(crate) fn toggle_mark(&mut self) {
+        if let Some(ArchiveItem::Entry(entry)) = self.selected_item() {
+            let path = entry.path.clone();
+            if !self.marked.remove(&path) {
+                self.marked.insert(path);
+            }
+        }
+    }
+
+    /// The full paths of the marked entries or, if nothing is marked, of the selected one.
+    pub(crate) fn selected_members(&self) -> Vec<String> {
+        if !self.marked.is_empty() {
+            let mut marked: Vec<String> = self.marked.iter().cloned().collect();
+            marked.sort();
+            return marked;
+        }
+        match self.selected_item() {
+            Some(ArchiveItem::Entry(entry)) => vec![entry.path.clone()],
+            _ => vec![],
+        }
+    }
+
+    /// The entries below the archive directory at `path`.
+    pub(crate) fn children(&self, path: &str) -> impl Iterator<Item = &ArchiveEntry> {
+        let prefix = format!("{}/", path);
+        self.entries.iter().filter(move |e| e.path.starts_with(prefix.as_str()))
+    }
+}
+
+/// Counts the bytes read through it, and reports the total so far with every read.
+struct ProgressReader<'a, R> {
+    inner: R,
+    count: u64,
+    progress: &'a mut dyn FnMut(u64),
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        (self.progress)(self.count);
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for ProgressReader<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_test_tar_gz(path: &Path) {
+        let file = File::create(path).unwrap();
+        let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        let mut tar = tar::Builder::new(encoder);
+        for (name, contents) in [("top.txt", "top\n"), ("dir/a.txt", "hello\n"), ("dir/sub/b.rs", "fn main() {}\n")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o640);
+            header.set_mtime(1_700_000_000);
+            header.set_cksum();
+            tar.append_data(&mut header, name, contents.as_bytes()).unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn write_test_zip(path: &Path) {
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.add_directory("dir/", options).unwrap();
+        zip.start_file("dir/a.txt", options).unwrap();
+        zip.write_all(b"hello\n").unwrap();
+        zip.start_file("top.txt", options).unwrap();
+        zip.write_all(b"top\n").unwrap();
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Some(ArchiveFormat::TarGz), ArchiveFormat::detect(Path::new("a.tar.gz")));
+        assert_eq!(Some(ArchiveFormat::TarZst), ArchiveFormat::detect(Path::new("a.TZST")));
+        assert_eq!(Some(ArchiveFormat::Zip), ArchiveFormat::detect(Path::new("/x/a.zip")));
+        assert_eq!(None, ArchiveFormat::detect(Path::new("a.gz")));
+        assert_eq!(None, ArchiveFormat::detect(Path::new(".zip")));
+        assert_eq!(Some("src".to_string()), ArchiveFormat::stem(Path::new("/x/src.TAR.GZ")));
+        assert_eq!(Some("a.b".to_string()), ArchiveFormat::stem(Path::new("a.b.tar")));
+        assert_eq!(None, ArchiveFormat::stem(Path::new("a.txt")));
+    }
+
+    #[test]
+    fn test_list_and_read() {
+        let tmp = tempfile::tempdir().unwrap();
+        let tgz = tmp.path().join("a.tar.gz");
+        write_test_tar_gz(&tgz);
+        let (entries, more) = list_entries(&tgz, ArchiveFormat::TarGz, 2).unwrap();
+        assert!(more);
+        assert_eq!(vec!["top.txt", "dir/a.txt"], entries.iter().map(|e| e.path.as_str()).collect::<Vec<&str>>());
+        assert_eq!(4, entries[0].size);
+        assert!(entries[0].modified.is_some());
+        assert_eq!(b"hello\n".to_vec(), read_member(&tgz, ArchiveFormat::TarGz, "dir/a.txt", 100).unwrap());
+        assert_eq!(b"hel".to_vec(), read_member(&tgz, ArchiveFormat::TarGz, "dir/a.txt", 3).unwrap());
+        assert!(read_member(&tgz, ArchiveFormat::TarGz, "nope", 3).is_err());
+
+        let zip = tmp.path().join("a.zip");
+        write_test_zip(&zip);
+        let (entries, more) = list_entries(&zip, ArchiveFormat::Zip, 10).unwrap();
+        assert!(!more);
+        assert_eq!(vec!["dir", "dir/a.txt", "top.txt"], entries.iter().map(|e| e.path.as_str()).collect::<Vec<&str>>());
+        assert!(entries[0].is_dir);
+        assert_eq!(b"top\n".to_vec(), read_member(&zip, ArchiveFormat::Zip, "top.txt", 100).unwrap());
+    }
+
+    #[test]
+    fn test_browse() {
+        let tmp = tempfile::tempdir().unwrap();
+        let tgz = tmp.path().join("a.tar.gz");
+        write_test_tar_gz(&tgz);
+        let mut view = ArchiveView::open(&tgz, ArchiveFormat::TarGz);
+        assert!(view.is_loading() && view.items.is_empty());
+        let loaded = loop {
+            match view.poll() {
+                Some(result) => break result,
+                None => std::thread::sleep(std::time::Duration::from_millis(10)),
+            }
+        };
+        assert!(loaded.is_ok() && !view.is_loading());
+        let names = |view: &ArchiveView| -> Vec<String> {
+            view.items
+                .iter()
+                .map(|item| match item {
+                    ArchiveItem::Parent => "..".to_string(),
+                    ArchiveItem::Entry(e) => view.name(e).to_string(),
+                })
+                .collect()
+        };
+        assert_eq!(vec!["dir", "top.txt"], names(&view));
+        assert!(view.enter());
+        assert_eq!("dir/", view.prefix);
+        assert_eq!(vec!["..", "sub", "a.txt"], names(&view));
+        assert_eq!(2, view.children("dir").count());
+        view.select_next();
+        assert!(view.enter());
+        assert_eq!(vec!["..", "b.rs"], names(&view));
+        view.select_next();
+        assert!(!view.enter());
+        assert!(view.leave());
+        assert_eq!(Some(&ArchiveItem::Entry(ArchiveEntry {
+            path: "dir/sub".to_string(), is_dir: true, is_symlink: false, size: 0, modified: None, mode: None,
+        })), view.selected_item());
+        assert!(view.leave());
+        assert!(!view.leave());
+        assert_eq!(vec!["dir".to_string()], view.selected_members());
+    }
+
+    #[test]
+    fn test_extract_members() {
+        let tmp = tempfile::tempdir().unwrap();
+        let tgz = tmp.path().join("a.tar.gz");
+        write_test_tar_gz(&tgz);
+        let dest = tmp.path().join("dest");
+        fs::create_dir(&dest).unwrap();
+        fs::write(dest.join("a.txt"), "old\n").unwrap();
+
+        let mut last_progress = 0;
+        let report = extract_members(&tgz, ArchiveFormat::TarGz, &["dir/sub".to_string(), "dir/a.txt".to_string()],
+                                     "dir/", &dest, &mut |bytes| last_progress = bytes).unwrap();
+        assert_eq!("fn main() {}\n", fs::read_to_string(dest.join("sub/b.rs")).unwrap());
+        assert_eq!(0o640, fs::metadata(dest.join("sub/b.rs")).unwrap().permissions().mode() & 0o777);
+        // existing files are kept
+        assert_eq!("old\n", fs::read_to_string(dest.join("a.txt")).unwrap());
+        assert_eq!(1, report.errors.len());
+        assert_eq!(vec![dest.join("sub")], report.created);
+        assert!(last_progress > 0 && last_progress <= fs::metadata(&tgz).unwrap().len());
+    }
+
+    #[test]
+    fn test_extract_unsafe_paths() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("evil.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        for name in ["../evil.txt", "/tmp/evil.txt", "ok.txt"] {
+            zip.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+            zip.write_all(b"evil\n").unwrap();
+        }
+        zip.finish().unwrap();
+        let dest = tmp.path().join("dest");
+        fs::create_dir(&dest).unwrap();
+
+        // the other entries are still extracted
+        let report = extract_members(&path, ArchiveFormat::Zip, &[], "", &dest, &mut |_| {}).unwrap();
+        assert_eq!(2, report.errors.len());
+        assert!(report.errors[0].contains("unsafe path"));
+        assert_eq!(vec![dest.join("ok.txt")], report.created);
+        assert!(!tmp.path().join("evil.txt").exists());
+    }
+
+    #[test]
+    fn test_extract_through_links() {
+        let tmp = tempfile::tempdir().unwrap();
+        let tgz = tmp.path().join("a.tar.gz");
+        write_test_tar_gz(&tgz);
+        let (dest, outside) = (tmp.path().join("dest"), tmp.path().join("outside"));
+        fs::create_dir(&dest).unwrap();
+        fs::create_dir(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, dest.join("dir")).unwrap();
+
+        let report = extract_members(&tgz, ArchiveFormat::TarGz, &[], "", &dest, &mut |_| {}).unwrap();
+        assert_eq!(2, report.errors.len());
+        assert!(report.errors.iter().all(|e| e.ends_with("dest/dir is a link, skipped")), "{:?}", report.errors);
+        assert_eq!("top\n", fs::read_to_string(dest.join("top.txt")).unwrap());
+        assert_eq!(0, fs::read_dir(&outside).unwrap().count());
+    }
+
+    #[test]
+    fn test_create_archive() {
+        let tmp = tempfile::tempdir().unwrap();
+        let src = tmp.path().join("src");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("sub/a.txt"), "hello\n").unwrap();
+        fs::set_permissions(src.join("sub/a.txt"), fs::Permissions::from_mode(0o600)).unwrap();
+        std::os::unix::fs::symlink("sub/a.txt", src.join("link")).unwrap();
+        fs::write(tmp.path().join("top.txt"), "top\n").unwrap();
+        let sources = vec![src.clone(), tmp.path().join("top.txt"), tmp.path().join("missing")];
+
+        for name in ["a.zip", "a.tar", "a.tar.gz", "a.tar.xz", "a.tar.zst"] {
+            let path = tmp.path().join(name);
+            let format = ArchiveFormat::detect(&path).unwrap();
+            let mut last_progress = 0;
+            let errors = create_archive(&path, format, &sources, &mut |bytes| last_progress = bytes).unwrap();
+            assert_eq!(1, errors.len(), "{}", name);
+            assert_eq!(10, last_progress, "{}", name);
+
+            let (entries, _) = list_entries(&path, format, 10).unwrap();
+            let paths: Vec<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+            assert_eq!(vec!["src", "src/link", "src/sub", "src/sub/a.txt", "top.txt"], paths, "{}", name);
+            assert!(entries[0].is_dir && entries[1].is_symlink);
+            assert_eq!(Some(0o600), entries[3].mode.map(|m| m & 0o777), "{}", name);
+            assert_eq!(b"hello\n".to_vec(), read_member(&path, format, "src/sub/a.txt", 100).unwrap());
+        }
+        // existing files aren't replaced
+        assert!(create_archive(&tmp.path().join("a.zip"), ArchiveFormat::Zip, &sources, &mut |_| {}).is_err());
+    }
+
+    #[test]
+    fn test_safe_relative_path() {
+        assert_eq!(Some(PathBuf::from("a/b")), safe_relative_path("./a/b"));
+        assert_eq!(None, safe_relative_path("../a"));
+        assert_eq!(None, safe_relative_path("a/../../b"));
+        assert_eq!(None, safe_relative_path("/etc/passwd"));
+        assert_eq!(None, safe_relative_path(""));
+    }
+}
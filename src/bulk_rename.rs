@@ -0,0 +1,297 @@
+use std::collections::{HashMap, HashSet};
+use std::{env, fs, io, process};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use log::{debug, error};
+
+/// A single `fs::rename()` within the directory of a `RenamePlan`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenameStep {
+    pub from: String,
+    pub to: String,
+}
+
+/// The renames which result from editing a list of names.
+#[derive(Debug, Clone)]
+pub struct RenamePlan {
+    pub dir: PathBuf,
+    /// The renames the user asked for.
+    pub renames: Vec<RenameStep>,
+    /// The renames to perform, in order. Cycles (e.g. a->b, b->a) go through temporary names.
+    pub steps: Vec<RenameStep>,
+    /// The number of cycles in `renames`.
+    pub cycles: usize,
+    /// Anything preventing the plan from being applied (collisions, invalid names, ...).
+    pub problems: Vec<String>,
+}
+
+impl RenamePlan {
+    /// Compare the `edited` file contents (one name per line) against the `originals`.
+    pub(crate) fn new(dir: &Path, originals: &[String], edited: &str) -> Self {
+        let mut plan = RenamePlan {
+            dir: dir.to_path_buf(),
+            renames: vec![],
+            steps: vec![],
+            cycles: 0,
+            problems: vec![],
+        };
+        let edited: Vec<&str> = edited.lines().collect();
+        if edited.len() != originals.len() {
+            plan.problems.push(format!("expected {} names, found {} (lines must not be added or removed)",
+                                       originals.len(), edited.len()));
+            return plan;
+        }
+
+        for (from, to) in originals.iter().zip(edited) {
+            if from == to {
+                continue;
+            }
+            if to.is_empty() || to == "." || to == ".." || to.contains('/') || to.contains('\0') {
+                plan.problems.push(format!("invalid name for {}: \"{}\"", from, to));
+                continue;
+            }
+            plan.renames.push(RenameStep { from: from.clone(), to: to.to_string() });
+        }
+
+        // two entries must not end up with the same name
+        let mut targets: HashSet<&str> = HashSet::new();
+        for rename in &plan.renames {
+            if !targets.insert(rename.to.as_str()) {
+                plan.problems.push(format!("more than one entry renamed to {}", rename.to));
+            }
+        }
+        // an existing entry must not be overwritten, unless it is renamed away itself
+        let sources: HashSet<&str> = plan.renames.iter().map(|r| r.from.as_str()).collect();
+        let kept: HashSet<&str> = originals.iter().map(|n| n.as_str()).filter(|n| !sources.contains(n)).collect();
+        for rename in &plan.renames {
+            let exists = fs::symlink_metadata(dir.join(&rename.to)).is_ok();
+            if kept.contains(rename.to.as_str()) || (exists && !sources.contains(rename.to.as_str())) {
+                plan.problems.push(format!("{} already exists", rename.to));
+            }
+        }
+
+        if plan.problems.is_empty() {
+            plan.order_steps();
+        }
+        plan
+    }
+
+    /// Order the renames so no entry is overwritten before it was moved out of the way.
+    fn order_steps(&mut self) {
+        let mut pending: HashMap<String, String> = self.renames
+            .iter()
+            .map(|r| (r.from.clone(), r.to.clone()))
+            .collect();
+        let mut temp_count = 0;
+        while !pending.is_empty() {
+            // a rename whose target is no longer needed by another pending rename can go now
+            let ready: Vec<String> = pending
+                .iter()
+                .filter(|(_, to)| !pending.contains_key(*to))
+                .map(|(from, _)| from.clone())
+                .collect();
+            if !ready.is_empty() {
+                for from in ready {
+                    let to = pending.remove(&from).expect("pending rename vanished");
+                    self.steps.push(RenameStep { from, to });
+                }
+                continue;
+            }
+            // only cycles are left: break one up by moving an entry to a temporary name
+            let from = pending.keys().min().expect("no pending renames").clone();
+            let to = pending.remove(&from).expect("pending rename vanished");
+            let temp = self.temp_name(&from, &mut temp_count);
+            self.steps.push(RenameStep { from, to: temp.clone() });
+            pending.insert(temp, to);
+            self.cycles += 1;
+        }
+    }
+
+    fn temp_name(&self, name: &str, temp_count: &mut usize) -> String {
+        loop {
+            *temp_count += 1;
+            let temp = format!(".lsls-rename-{}-{}", temp_count, name);
+            if fs::symlink_metadata(self.dir.join(&temp)).is_err() {
+                return temp;
+            }
+        }
+    }
+
+    /// Perform the renames. If one fails, the renames done so far are rolled back.
+    pub(crate) fn apply(&self) -> Result<()> {
+        if !self.problems.is_empty() {
+            return Err(anyhow!("the rename plan has problems"));
+        }
+        let mut done: Vec<&RenameStep> = vec![];
+        for step in &self.steps {
+            let from = self.dir.join(&step.from);
+            let to = self.dir.join(&step.to);
+            // `fs::rename()` silently replaces files, so double check
+            let result = match fs::symlink_metadata(&to) {
+                Ok(_) => Err(anyhow!("{} already exists", step.to)),
+                Err(_) => fs::rename(&from, &to).map_err(|e| anyhow!("unable to rename {}: {}", step.from, e)),
+            };
+            if let Err(e) = result {
+                for step in done.iter().rev() {
+                    if let Err(e) = fs::rename(self.dir.join(&step.to), self.dir.join(&step.from)) {
+                        error!("unable to roll back rename of {}: {}", step.from, e);
+                    }
+                }
+                return Err(e);
+            }
+            debug!("renamed {} -> {}", step.from, step.to);
+            done.push(step);
+        }
+        Ok(())
+    }
+}
+
+/// Names written to a temporary file, waiting to be edited by the user.
+#[derive(Debug)]
+pub struct RenameSession {
+    pub dir: PathBuf,
+    pub originals: Vec<String>,
+    pub file: PathBuf,
+}
+
+impl RenameSession {
+    pub(crate) fn start(dir: &Path, originals: Vec<String>) -> Result<Self> {
+        if let Some(name) = originals.iter().find(|n| n.contains('\n')) {
+            return Err(anyhow!("unable to edit a name with a line break: {:?}", name));
+        }
+        let (file, mut output) = create_buffer()?;
+        let mut contents = originals.join("\n");
+        contents.push('\n');
+        if let Err(e) = output.write_all(contents.as_bytes()) {
+            let _result = fs::remove_file(&file);
+            return Err(e.into());
+        }
+        Ok(Self { dir: dir.to_path_buf(), originals, file })
+    }
+
+    /// Read back the edited names and remove the temporary file.
+    pub(crate) fn finish(self) -> Result<RenamePlan> {
+        let edited = fs::read_to_string(&self.file);
+        let _result = fs::remove_file(&self.file);
+        Ok(RenamePlan::new(&self.dir, &self.originals, edited?.as_str()))
+    }
+}
+
+/// Create a new file in the temp directory, readable only by the user. An existing file (or a
+/// symlink planted by someone else) is never opened, another name is tried instead.
+fn create_buffer() -> io::Result<(PathBuf, File)> {
+    let mut attempt = 0;
+    loop {
+        let file = env::temp_dir().join(format!("lsls-rename-{}-{}.txt", process::id(), attempt));
+        match OpenOptions::new().write(true).create_new(true).mode(0o600).open(&file) {
+            Ok(output) => return Ok((file, output)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn dir_with_files(names: &[&str]) -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        for name in names {
+            fs::write(tmp.path().join(name), name).unwrap();
+        }
+        tmp
+    }
+
+    #[test]
+    fn test_plan_chain_and_cycle() {
+        let tmp = dir_with_files(&["a", "b", "c", "d"]);
+
+        // a->b, b->a is a cycle; c->x, d->c is a chain
+        let plan = RenamePlan::new(tmp.path(), &names(&["a", "b", "c", "d"]), "b\na\nx\nc\n");
+        assert!(plan.problems.is_empty());
+        assert_eq!(4, plan.renames.len());
+        assert_eq!(1, plan.cycles);
+        assert_eq!(5, plan.steps.len());
+
+        plan.apply().unwrap();
+        assert_eq!("a", fs::read_to_string(tmp.path().join("b")).unwrap());
+        assert_eq!("b", fs::read_to_string(tmp.path().join("a")).unwrap());
+        assert_eq!("c", fs::read_to_string(tmp.path().join("x")).unwrap());
+        assert_eq!("d", fs::read_to_string(tmp.path().join("c")).unwrap());
+        assert!(!tmp.path().join("d").exists());
+    }
+
+    #[test]
+    fn test_plan_problems() {
+        let tmp = dir_with_files(&["a", "b", "c"]);
+        let originals = names(&["a", "b"]);
+
+        let plan = RenamePlan::new(tmp.path(), &originals, "a\n");
+        assert_eq!(1, plan.problems.len());
+
+        let plan = RenamePlan::new(tmp.path(), &originals, "x\nx\n");
+        assert!(plan.problems[0].contains("more than one"));
+
+        // c exists and isn't being renamed
+        let plan = RenamePlan::new(tmp.path(), &originals, "c\nb\n");
+        assert!(plan.problems[0].contains("already exists"));
+
+        let plan = RenamePlan::new(tmp.path(), &originals, "sub/a\n\n");
+        assert_eq!(2, plan.problems.len());
+        assert!(plan.apply().is_err());
+
+        // nothing changed
+        let plan = RenamePlan::new(tmp.path(), &originals, "a\nb\n");
+        assert!(plan.problems.is_empty() && plan.steps.is_empty());
+    }
+
+    #[test]
+    fn test_session() {
+        let tmp = dir_with_files(&["a", "b"]);
+        let session = RenameSession::start(tmp.path(), names(&["a", "b"])).unwrap();
+        assert_eq!("a\nb\n", fs::read_to_string(&session.file).unwrap());
+        fs::write(&session.file, "a\nc\n").unwrap();
+        let file = session.file.clone();
+
+        let plan = session.finish().unwrap();
+        assert_eq!(vec![RenameStep { from: "b".to_string(), to: "c".to_string() }], plan.renames);
+        assert!(!file.exists());
+    }
+
+    #[test]
+    fn test_session_buffer() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = dir_with_files(&["a"]);
+        let first = RenameSession::start(tmp.path(), names(&["a"])).unwrap();
+        // an existing file is left alone
+        let second = RenameSession::start(tmp.path(), names(&["a"])).unwrap();
+        assert_ne!(first.file, second.file);
+        assert_eq!("a\n", fs::read_to_string(&first.file).unwrap());
+        let mode = fs::metadata(&second.file).unwrap().permissions().mode();
+        assert_eq!(0o600, mode & 0o777);
+        first.finish().unwrap();
+        second.finish().unwrap();
+    }
+
+    #[test]
+    fn test_apply_rolls_back() {
+        let tmp = dir_with_files(&["a", "b"]);
+        let plan = RenamePlan::new(tmp.path(), &names(&["a", "b"]), "x\ny\n");
+        // something else grabs a target name after planning
+        fs::write(tmp.path().join(&plan.steps[1].to), "other").unwrap();
+
+        assert!(plan.apply().is_err());
+        assert!(tmp.path().join("a").exists());
+        assert!(tmp.path().join("b").exists());
+    }
+}
@@ -0,0 +1,213 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use liblzma::read::XzDecoder;
+
+/// How much decompressed data the mime type of the contents is guessed from.
+const SAMPLE_BYTES: u64 = 4096;
+
+/// Single-file compression formats (e.g. of rotated logs), previewed decompressed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Bzip2,
+    Zstd,
+}
+
+impl Compression {
+    /// Recognize a compressed file by its first bytes.
+    pub(crate) fn detect(path: &Path) -> Option<Self> {
+        let mut header = vec![];
+        File::open(path).ok()?.take(6).read_to_end(&mut header).ok()?;
+        match header.as_slice() {
+            [0x1f, 0x8b, ..] => Some(Compression::Gzip),
+            [0xfd, b'7', b'z', b'X', b'Z', 0x00] => Some(Compression::Xz),
+            [b'B', b'Z', b'h', b'1'..=b'9', ..] => Some(Compression::Bzip2),
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Xz => "xz",
+            Compression::Bzip2 => "bz2",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /// The path of the file before it was compressed, e.g. `app.log` for `app.log.gz`, to pick
+    /// the syntax of its contents.
+    pub(crate) fn stem(&self, path: &Path) -> PathBuf {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case(self.extension()) => path.with_extension(""),
+            _ => path.to_path_buf(),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let output = match self {
+            Compression::Gzip => "gzip",
+            Compression::Xz => "xz",
+            Compression::Bzip2 => "bzip2",
+            Compression::Zstd => "zstd",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+/// Open the compressed file at `path`, to read it decompressed as a stream. Returns the mime type
+/// of its contents, guessed from their start, and the reader.
+pub(crate) fn open_decompressed(path: &Path, compression: Compression) -> io::Result<(String, Box<dyn BufRead>)> {
+    let file = BufReader::new(File::open(path)?);
+    let mut reader: Box<dyn Read> = match compression {
+        // concatenated streams (e.g. appended to a log) are read one after another
+        Compression::Gzip => Box::new(MultiGzDecoder::new(file)),
+        Compression::Xz => Box::new(XzDecoder::new_multi_decoder(file)),
+        Compression::Bzip2 => Box::new(MultiBzDecoder::new(file)),
+        Compression::Zstd => Box::new(zstd::Decoder::with_buffer(file)?),
+    };
+    let mut sample = vec![];
+    reader.by_ref().take(SAMPLE_BYTES).read_to_end(&mut sample)?;
+    let mime_type = tree_magic_mini::from_u8(&sample).to_string();
+    Ok((mime_type, Box::new(BufReader::new(Cursor::new(sample).chain(reader)))))
+}
+
+/// The size of the contents of the compressed file at `path`, if its format records it: gzip in
+/// its trailer (modulo 4 GiB), xz in its index, zstd in the frame header (if it was written in
+/// one go). Only the last stream (or the first zstd frame) counts.
+pub(crate) fn uncompressed_size(path: &Path, compression: Compression) -> Option<u64> {
+    let mut file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+    match compression {
+        Compression::Gzip => {
+            let mut trailer = [0; 4];
+            file.seek(SeekFrom::End(-4)).ok()?;
+            file.read_exact(&mut trailer).ok()?;
+            let size = u64::from(u32::from_le_bytes(trailer));
+            // the size wraps around for contents of 4 GiB or more, which gives it away when it
+            // ends up smaller than the file itself
+            (size >= len).then_some(size)
+        },
+        Compression::Xz => xz_uncompressed_size(&mut file),
+        Compression::Bzip2 => None,
+        Compression::Zstd => {
+            // the frame header is at most 18 bytes
+            let mut header = vec![];
+            file.take(18).read_to_end(&mut header).ok()?;
+            zstd::zstd_safe::get_frame_content_size(&header).ok()?
+        },
+    }
+}
+
+/// Sum up the uncompressed sizes of the blocks in the index of an xz stream, which ends with a
+/// 12 byte footer giving the size of the index.
+fn xz_uncompressed_size(file: &mut File) -> Option<u64> {
+    let mut footer = [0; 12];
+    file.seek(SeekFrom::End(-12)).ok()?;
+    file.read_exact(&mut footer).ok()?;
+    if &footer[10..] != b"YZ" {
+        return None;
+    }
+    let index_size = (u64::from(u32::from_le_bytes(footer[4..8].try_into().ok()?)) + 1) * 4;
+    file.seek(SeekFrom::End(-12 - i64::try_from(index_size).ok()?)).ok()?;
+    let mut index = vec![];
+    file.take(index_size).read_to_end(&mut index).ok()?;
+    let (&indicator, mut rest) = index.split_first()?;
+    if indicator != 0 {
+        return None;
+    }
+    let records = read_varint(&mut rest)?;
+    let mut size = 0u64;
+    for _ in 0..records {
+        let _unpadded_size = read_varint(&mut rest)?;
+        size = size.checked_add(read_varint(&mut rest)?)?;
+    }
+    Some(size)
+}
+
+/// Read a number of the xz format, 7 bits per byte, least significant first.
+fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for i in 0..9 {
+        let (&byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+
+    #[test]
+    fn test_decompress() {
+        let tmp = tempfile::tempdir().unwrap();
+        let text: String = (1..=1000).map(|i| format!("line {}\n", i)).collect();
+        let compressed = [
+            (Compression::Gzip, "app.log.gz", {
+                let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(text.as_bytes()).unwrap();
+                encoder.finish().unwrap()
+            }),
+            (Compression::Xz, "app.log.xz", {
+                let mut encoder = liblzma::write::XzEncoder::new(vec![], 6);
+                encoder.write_all(text.as_bytes()).unwrap();
+                encoder.finish().unwrap()
+            }),
+            (Compression::Bzip2, "app.log.bz2", {
+                let mut encoder = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+                encoder.write_all(text.as_bytes()).unwrap();
+                encoder.finish().unwrap()
+            }),
+            (Compression::Zstd, "app.log.zst", zstd::bulk::compress(text.as_bytes(), 0).unwrap()),
+        ];
+        for (compression, name, bytes) in compressed {
+            let path = tmp.path().join(name);
+            fs::write(&path, bytes).unwrap();
+            assert_eq!(Some(compression), Compression::detect(&path));
+            assert_eq!(tmp.path().join("app.log"), compression.stem(&path));
+
+            let (mime_type, reader) = open_decompressed(&path, compression).unwrap();
+            assert_eq!("text/plain", mime_type);
+            let lines: Vec<String> = reader.lines().map(Result::unwrap).collect();
+            assert_eq!(1000, lines.len(), "{}", compression);
+            assert_eq!("line 1000", lines[999]);
+
+            let expected = match compression {
+                Compression::Bzip2 => None,
+                _ => Some(text.len() as u64),
+            };
+            assert_eq!(expected, uncompressed_size(&path, compression), "{}", compression);
+        }
+
+        // a gzip trailer which wrapped around at 4 GiB
+        let path = tmp.path().join("huge.log.gz");
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        let mut bytes = encoder.finish().unwrap();
+        let at = bytes.len() - 4;
+        bytes[at..].copy_from_slice(&7u32.to_le_bytes());
+        fs::write(&path, bytes).unwrap();
+        assert_eq!(None, uncompressed_size(&path, Compression::Gzip));
+
+        let path = tmp.path().join("plain.gz");
+        fs::write(&path, "not compressed").unwrap();
+        assert_eq!(None, Compression::detect(&path));
+        assert_eq!(path, Compression::Xz.stem(&path));
+    }
+}
@@ -1,5 +1,6 @@
 use std::{fmt, fs, thread};
 use std::cmp::Ordering;
+use std::collections::HashSet;
 use std::fs::{DirEntry, FileType, Permissions};
 #[cfg(target_os = "linux")]
 use std::os::linux::fs::MetadataExt;
@@ -12,6 +13,7 @@ use std::time::{Instant, SystemTime};
 use anyhow::Result;
 use byte_unit::{Byte, UnitType};
 use fs_extra::dir::get_size;
+use globset::Glob;
 use itertools::Itertools;
 use notify::{Watcher};
 use ratatui::{widgets::*};
@@ -25,18 +27,17 @@ use ratatui::style::{Color, Modifier, Style};
 use retry::delay::Fixed;
 use retry::retry;
 
-#[derive(Debug, Clone)]
+use crate::filter::Filter;
+use crate::git::{GitRepoStatus, GitStatus};
+use crate::ignore_rules::{is_ignore_file, IgnoreMode, IgnoreRules};
+
+#[derive(Debug, Clone, Default)]
 pub enum SortByDirection {
+    #[default]
     Asc,
     Dec,
 }
 
-impl Default for SortByDirection {
-    fn default() -> Self {
-        SortByDirection::Asc
-    }
-}
-
 #[derive(Debug, Clone)]
 pub enum SortBy {
     TypeAndName(SortByDirection),
@@ -86,6 +87,11 @@ pub struct DirEntryData {
     pub permissions: Permissions,
     pub created: SystemTime,
     pub modified: SystemTime,
+    pub marked: bool,
+    /// Matched by `.gitignore` / `.ignore` rules (only evaluated when they are not shown).
+    pub ignored: bool,
+    /// Only set while the directory is inside a git work tree.
+    pub git_status: Option<GitStatus>,
 }
 
 #[derive(Debug)]
@@ -94,6 +100,15 @@ pub struct SizeNotification {
     pub size: u64,
 }
 
+#[derive(Debug)]
+pub struct GitStatusNotification {
+    /// The directory the status was computed for.
+    pub dir: String,
+    /// Requests are numbered in the order they were made, as they may complete in any order.
+    pub request: u64,
+    pub status: Option<GitRepoStatus>,
+}
+
 impl From<&PathBuf> for DirEntryData {
     fn from(path: &PathBuf) -> Self {
         let file_name = path.file_name()
@@ -102,7 +117,7 @@ impl From<&PathBuf> for DirEntryData {
             .expect("unable to get file_name as str")
             .to_string();
         let meta = path.metadata()
-            .expect(format!("unable to get metadata for {}", file_name).as_str());
+            .unwrap_or_else(|_| panic!("unable to get metadata for {}", file_name));
         let file_type = meta.file_type();
         let mut file_size: Option<u64> = None;
         if file_type.is_file() {
@@ -111,10 +126,11 @@ impl From<&PathBuf> for DirEntryData {
         let uid = meta.st_uid();
         let gid = meta.st_gid();
         let permissions = meta.permissions();
-        let created = meta.created()
-            .expect("Unable to get created from DirEntry");
         let modified = meta.modified()
             .expect("Unable to get modified from DirEntry");
+        // not every filesystem records a creation (birth) time
+        let created = meta.created()
+            .unwrap_or(modified);
         DirEntryData {
             name: file_name,
             file_type,
@@ -124,6 +140,9 @@ impl From<&PathBuf> for DirEntryData {
             permissions,
             created,
             modified,
+            marked: false,
+            ignored: false,
+            git_status: None,
         }
     }
 }
@@ -144,10 +163,11 @@ impl From<DirEntry> for DirEntryData {
         let uid = meta.st_uid();
         let gid = meta.st_gid();
         let permissions = meta.permissions();
-        let created = meta.created()
-            .expect("Unable to get created from DirEntry");
         let modified = meta.modified()
             .expect("Unable to get modified from DirEntry");
+        // not every filesystem records a creation (birth) time
+        let created = meta.created()
+            .unwrap_or(modified);
         DirEntryData {
             name: file_name,
             file_type,
@@ -157,72 +177,16 @@ impl From<DirEntry> for DirEntryData {
             permissions,
             created,
             modified,
+            marked: false,
+            ignored: false,
+            git_status: None,
         }
     }
 }
 
 impl From<DirectoryListItem> for Row<'_> {
     fn from(item: DirectoryListItem) -> Self {
-        let default_style = Style::default().fg(Color::White);
-        let dir_style = default_style.fg(Color::LightGreen);
-        let link_style = default_style.add_modifier(Modifier::ITALIC);
-
-        match item {
-            DirectoryListItem::ParentDir(item) => {
-                let file_name = item;
-                //Row::new(vec![file_name.as_str()]).style(dir_style)
-                Row::new(vec![file_name]).style(dir_style)
-            }
-            DirectoryListItem::Entry(item) => {
-                let file_name = item.name.clone();
-                // determine the type of file (directory, symlink, etc.)
-                let mut style = default_style;
-                if item.file_type.is_dir() {
-                    style = dir_style;
-                }
-                if item.file_type.is_symlink() {
-                    style = link_style;
-                };
-                let datetime_str: String = {
-                    let datetime: DateTime<Local> = item.modified.into();
-                    datetime.format("%Y-%m-%d %T").to_string()
-                };
-                let filesize_str = {
-                    if let Some(size) = item.size {
-                        //let byte = Byte::from_bytes(size.into());
-                        //let byte = Byte::from(size);
-                        //let adjusted_byte = byte.get_appropriate_unit(UnitType::Decimal);
-                        let adjusted_byte = Byte::from(size).get_appropriate_unit(UnitType::Decimal);
-                        format!("{adjusted_byte:.1}")
-                    } else {
-                        "...".to_string()
-                    }
-                };
-                let mut user = item.uid.to_string();
-                if let Some(uname) = get_user_by_uid(item.uid) {
-                    user = uname.name().to_os_string().into_string()
-                        .expect("unable to convert username to string");
-                }
-                let gid = item.gid.to_string();
-                let perms = item.permissions.clone();
-                let perms_str = perms.stringify();
-                let user_perms = perms_str[0..3].to_string();
-                let group_perms = perms_str[3..6].to_string();
-                let other_perms = perms_str[6..9].to_string();
-
-                Row::new(vec![
-                    file_name,
-                    filesize_str,
-                    datetime_str,
-                    user,
-                    gid,
-                    user_perms,
-                    group_perms,
-                    other_perms,
-                ])
-                    .style(style)
-            }
-        }
+        Row::from(&item)
     }
 }
 
@@ -231,6 +195,8 @@ impl <'a> From<&'a DirectoryListItem> for Row<'_> {
         let default_style = Style::default().fg(Color::White);
         let dir_style = default_style.fg(Color::LightGreen);
         let link_style = default_style.add_modifier(Modifier::ITALIC);
+        let marked_style = default_style.fg(Color::LightYellow).add_modifier(Modifier::BOLD);
+        let ignored_style = default_style.fg(Color::DarkGray);
 
         match item {
             DirectoryListItem::ParentDir(item) => {
@@ -248,6 +214,12 @@ impl <'a> From<&'a DirectoryListItem> for Row<'_> {
                 if item.file_type.is_symlink() {
                     style = link_style;
                 };
+                if item.ignored {
+                    style = ignored_style;
+                }
+                if item.marked {
+                    style = marked_style;
+                }
                 let datetime_str: String = {
                     let datetime: DateTime<Local> = item.modified.into();
                     datetime.format("%Y-%m-%d %T").to_string()
@@ -275,16 +247,20 @@ impl <'a> From<&'a DirectoryListItem> for Row<'_> {
                 let group_perms = perms_str[3..6].to_string();
                 let other_perms = perms_str[6..9].to_string();
 
-                Row::new(vec![
-                    file_name,
-                    filesize_str,
-                    datetime_str,
-                    user,
-                    gid,
-                    user_perms,
-                    group_perms,
-                    other_perms,
-                ])
+                let mut cells = vec![
+                    Cell::from(file_name),
+                    Cell::from(filesize_str),
+                    Cell::from(datetime_str),
+                    Cell::from(user),
+                    Cell::from(gid),
+                    Cell::from(user_perms),
+                    Cell::from(group_perms),
+                    Cell::from(other_perms),
+                ];
+                if let Some(git_status) = item.git_status {
+                    cells.insert(1, Cell::from(git_status.to_string()).style(git_status.style()));
+                }
+                Row::new(cells)
                     .style(style)
             }
         }
@@ -308,6 +284,17 @@ pub struct DirectoryList {
     pub state: TableState,
     pub items: Vec<DirectoryListItem>,
     pub selection_changed: bool,
+    /// List dotfiles.
+    pub show_hidden: bool,
+    /// How entries matched by `.gitignore` / `.ignore` rules are listed.
+    pub ignore_mode: IgnoreMode,
+    /// The rules for `dir`; rebuilt on `refresh()` unless ignored entries are shown.
+    ignore_rules: Option<IgnoreRules>,
+    /// Narrows down the listing while typing.
+    pub filter: Option<Filter>,
+    /// Entries hidden by the filter. They are kept (and kept up to date) so that changing
+    /// the filter doesn't require re-reading the directory.
+    filtered: Vec<DirEntryData>,
     // changing directory
     pub dir_change_tx: Option<Sender<String>>,
     // watched directory
@@ -316,6 +303,17 @@ pub struct DirectoryList {
     // dir size computed
     pub dir_size_tx: Option<Sender<SizeNotification>>,
     pub dir_size_rx: Option<Receiver<SizeNotification>>,
+    /// The git status of `dir`, if it is inside a work tree.
+    pub git: Option<GitRepoStatus>,
+    // git status computed
+    pub git_status_tx: Option<Sender<GitStatusNotification>>,
+    pub git_status_rx: Option<Receiver<GitStatusNotification>>,
+    git_status_requests: u64,
+    git_status_applied: u64,
+    /// Only one git status is computed at a time; requests made meanwhile are queued up, to be
+    /// covered by a single run once it is done.
+    git_status_running: bool,
+    git_status_queued: bool,
 }
 
 impl DirectoryList {
@@ -333,9 +331,21 @@ impl DirectoryList {
                 state
             },
             selection_changed: false,
+            show_hidden: false,
+            ignore_mode: IgnoreMode::default(),
+            ignore_rules: None,
+            filter: None,
+            filtered: vec![],
             dir_size_rx: None,
             dir_size_tx: None,
             dir_watch_rx: None,
+            git: None,
+            git_status_tx: None,
+            git_status_rx: None,
+            git_status_requests: 0,
+            git_status_applied: 0,
+            git_status_running: false,
+            git_status_queued: false,
         }
     }
 
@@ -360,11 +370,8 @@ impl DirectoryList {
                 // setup the watcher thread, which will listen for directory changes
                 thread::spawn(move || {
                     let mut watcher = notify::recommended_watcher(move |res| {
-                        match res {
-                            Ok(event) => {
-                                let _result = dir_watch_tx.send(event);
-                            }
-                            Err(_) => {}
+                        if let Ok(event) = res {
+                            let _result = dir_watch_tx.send(event);
                         }
                     }).expect("unable to create recommended_watcher");
                     watcher.watch(Path::new(dir.as_str()), notify::RecursiveMode::NonRecursive)
@@ -432,6 +439,75 @@ impl DirectoryList {
         }
     }
 
+    /// Ask for the git status of the current directory, e.g. after files changed. Returns the
+    /// number of the request.
+    pub(crate) fn request_git_status(&mut self) -> u64 {
+        self.git_status_requests += 1;
+        if self.git_status_running {
+            // a busy directory would keep starting git otherwise
+            self.git_status_queued = true;
+        } else {
+            self.spawn_git_status();
+        }
+        self.git_status_requests
+    }
+
+    /// Creates a thread that computes the git status of the current directory, covering the
+    /// requests made so far.
+    fn spawn_git_status(&mut self) {
+        if self.git_status_rx.is_none() {
+            let (tx, rx): (Sender<GitStatusNotification>, Receiver<GitStatusNotification>) = channel();
+            self.git_status_tx = Some(tx);
+            self.git_status_rx = Some(rx);
+        }
+
+        self.git_status_running = true;
+        self.git_status_queued = false;
+        let request = self.git_status_requests;
+        if let Some(git_status_tx) = &self.git_status_tx {
+            let git_status_tx = git_status_tx.clone();
+            let dir = self.dir.clone();
+            thread::spawn(move || {
+                let start = Instant::now();
+                let status = GitRepoStatus::load(Path::new(&dir)).unwrap_or_else(|e| {
+                    debug!("unable to get git status for {}: {}", dir, e);
+                    None
+                });
+                debug!("Git status for {} in {:?}", dir, start.elapsed());
+                // the receiver is gone when the app is shutting down
+                let _result = git_status_tx.send(GitStatusNotification { dir, request, status });
+            });
+        }
+    }
+
+    /// Record a computed git status, unless the directory changed in the meantime or a more
+    /// recent status was recorded already. Starts the queued up request, if there is one.
+    pub(crate) fn set_git_status(&mut self, notify: GitStatusNotification) {
+        self.git_status_running = false;
+        if self.git_status_queued {
+            self.spawn_git_status();
+        }
+        if notify.dir != self.dir || notify.request < self.git_status_applied {
+            return;
+        }
+        self.git_status_applied = notify.request;
+        let status = notify.status;
+        let listed = self.items.iter_mut().filter_map(|item| match item {
+            DirectoryListItem::Entry(e) => Some(e),
+            DirectoryListItem::ParentDir(_) => None,
+        });
+        for entry in listed.chain(self.filtered.iter_mut()) {
+            entry.git_status = status.as_ref().map(|s| s.status_of(&entry.name, entry.file_type.is_dir()));
+        }
+        self.git = status;
+    }
+
+    /// Whether the status of the given request (or a later one) was recorded.
+    #[cfg(test)]
+    pub(crate) fn has_git_status(&self, request: u64) -> bool {
+        self.git_status_applied >= request
+    }
+
     pub(crate) fn smart_refresh(&mut self, fs_events: Vec<notify::Event>) -> Result<()> {
         // Bug: `rm file1` generates both Create(File) and Remove(File) events.
         info!("smart_refresh() called with {} events", fs_events.len());
@@ -467,7 +543,7 @@ impl DirectoryList {
         let create_files: Vec<&PathBuf> = create_files
             .into_iter()
             .filter(|create_path| {
-                !(*remove_files).contains(&create_path)
+                !(*remove_files).contains(create_path)
             })
             .unique()
             .collect();
@@ -478,7 +554,7 @@ impl DirectoryList {
         let modify_files: Vec<&PathBuf> = modify_files
             .into_iter()
             .filter(|modify_path| {
-                !(*remove_files).contains(&modify_path)
+                !(*remove_files).contains(modify_path)
             })
             .unique()
             .collect();
@@ -512,68 +588,90 @@ impl DirectoryList {
                     }
                 }
             });
+            self.filtered.retain(|e| e.name != file_name);
         }
 
         // process create files
         for create_path in create_files {
-            let data: DirEntryData = create_path.into();
+            let mut data: DirEntryData = create_path.into();
+            if !self.admit(&mut data) {
+                continue;
+            }
+            // the entry may already be listed (e.g. after an explicit `refresh()`)
+            self.items.retain(|x| {
+                match x {
+                    DirectoryListItem::ParentDir(_) => true,
+                    DirectoryListItem::Entry(e) => e.name != data.name,
+                }
+            });
+            self.filtered.retain(|e| e.name != data.name);
             if data.file_type.is_dir() || data.file_type.is_symlink() {
                 self.register_size_calculator(&data);
             }
             let filename = &data.name;
             debug!("Adding file {}", filename);
-            let data_item = DirectoryListItem::Entry(data);
-            self.items.push(data_item);
+            self.insert_entry(data);
         }
 
         // process modified files
         let mut heavy_refresh_needed = false;
+        // changed ignore rules may affect every entry
+        let ignore_files_changed = fs_events
+            .iter()
+            .flat_map(|e| e.paths.iter())
+            .filter_map(|p| p.file_name())
+            .any(|name| is_ignore_file(&name.to_string_lossy()));
+        if ignore_files_changed && self.ignore_mode != IgnoreMode::Show {
+            debug!("ignore rules changed: requires heavy refresh");
+            heavy_refresh_needed = true;
+        }
         for fs_event in fs_events {
-            match &fs_event.kind {
-                notify::EventKind::Modify(modify_kind) => {
-                    match modify_kind {
-                        notify::event::ModifyKind::Name(_name_change) => {
-                            // notify breaks name changes into 2 separate events
-                            debug!("name changed: requires heavy refresh");
-                            heavy_refresh_needed = true;
-                            break;
-                        },
-                        _ => {
-                            // file permissions (etc.) may have changed
-                            for path in &fs_event.paths {
-                                let file_name = path.file_name()
-                                    .expect("unable to extract file_name")
-                                    .to_str()
-                                    .expect("unable to convert file_name to str")
-                                    .to_string();
-                                debug!("changing file {}", file_name);
-                                // remove this item from the list
-                                self.items.retain(|item| {
-                                    match item {
-                                        DirectoryListItem::ParentDir(_) => true,
-                                        DirectoryListItem::Entry(item) => {
-                                            if item.name == file_name {
-                                                false
-                                            } else {
-                                                true
-                                            }
+            if let notify::EventKind::Modify(modify_kind) = &fs_event.kind {
+                match modify_kind {
+                    notify::event::ModifyKind::Name(_name_change) => {
+                        // notify breaks name changes into 2 separate events
+                        debug!("name changed: requires heavy refresh");
+                        heavy_refresh_needed = true;
+                        break;
+                    },
+                    _ => {
+                        // file permissions (etc.) may have changed
+                        for path in &fs_event.paths {
+                            let file_name = path.file_name()
+                                .expect("unable to extract file_name")
+                                .to_str()
+                                .expect("unable to convert file_name to str")
+                                .to_string();
+                            debug!("changing file {}", file_name);
+                            // remove this item from the list
+                            let mut was_marked = false;
+                            self.items.retain(|item| {
+                                match item {
+                                    DirectoryListItem::ParentDir(_) => true,
+                                    DirectoryListItem::Entry(item) => {
+                                        if item.name == file_name {
+                                            was_marked = item.marked;
                                         }
+                                        item.name != file_name
                                     }
-                                });
-                                // refresh the modified file
-                                let data: DirEntryData = path.into();
-                                if data.file_type.is_dir() || data.file_type.is_symlink() {
-                                    self.register_size_calculator(&data);
                                 }
-                                let filename = &data.name;
-                                debug!("Refreshing file {}", filename);
-                                let data_item = DirectoryListItem::Entry(data);
-                                self.items.push(data_item);
+                            });
+                            self.filtered.retain(|e| e.name != file_name);
+                            // refresh the modified file
+                            let mut data: DirEntryData = path.into();
+                            if !self.admit(&mut data) {
+                                continue;
                             }
+                            data.marked = was_marked;
+                            if data.file_type.is_dir() || data.file_type.is_symlink() {
+                                self.register_size_calculator(&data);
+                            }
+                            let filename = &data.name;
+                            debug!("Refreshing file {}", filename);
+                            self.insert_entry(data);
                         }
                     }
-                },
-                _ => {}
+                }
             }
         }
 
@@ -582,6 +680,8 @@ impl DirectoryList {
             self.refresh().expect("refresh() errored");
         } else {
             self.sort();
+            self.request_git_status();
+            self.clamp_selection();
         }
 
         Ok(())
@@ -593,32 +693,127 @@ impl DirectoryList {
     }
 
     pub(crate) fn refresh(&mut self) -> Result<()> {
+        // remember the marks, so they survive the refresh
+        let marked_names: HashSet<String> = self.marked_entries()
+            .iter()
+            .map(|e| e.name.clone())
+            .collect();
         self.items.clear();
+        self.ignore_rules = match self.ignore_mode {
+            IgnoreMode::Show => None,
+            _ => Some(IgnoreRules::for_dir(Path::new(&self.dir))),
+        };
         // read all the items in the directory
-        self.items = fs::read_dir(&self.dir)?
-            .into_iter()
+        let entries: Vec<DirEntryData> = fs::read_dir(&self.dir)?
             .map(|x| x.expect("unable to get DirEntry from iterator"))
-            .map(|x| {
-                let data: DirEntryData = x.into();
-                if data.file_type.is_dir() || data.file_type.is_symlink() {
-                    self.register_size_calculator(&data);
-                }
-                data
-            })
-            .map(|x| DirectoryListItem::Entry(x))
+            .map(DirEntryData::from)
             .collect();
-        self.items
-            .insert(0, DirectoryListItem::ParentDir("..".to_string()));
+        let mut admitted = vec![];
+        for mut data in entries {
+            if !self.admit(&mut data) {
+                continue;
+            }
+            data.marked = marked_names.contains(&data.name);
+            if data.file_type.is_dir() || data.file_type.is_symlink() {
+                self.register_size_calculator(&data);
+            }
+            admitted.push(data);
+        }
+        let entries = admitted;
+        self.items = vec![DirectoryListItem::ParentDir("..".to_string())];
+        self.filtered.clear();
+        for data in entries {
+            self.insert_entry(data);
+        }
         self.sort();
+        self.request_git_status();
 
-        if self.state.selected() == None {
+        if self.state.selected().is_none() {
             self.state.select(Some(0));
             self.selection_changed = true;
         }
+        self.clamp_selection();
 
         Ok(())
     }
 
+    /// Move the selection back into the list, after entries were removed from its end.
+    fn clamp_selection(&mut self) {
+        if let Some(i) = self.state.selected() {
+            if i >= self.items.len() {
+                self.state.select(self.items.len().checked_sub(1));
+                self.selection_changed = true;
+            }
+        }
+    }
+
+    /// Add an entry to the listing, or to the entries hidden by the filter.
+    fn insert_entry(&mut self, mut data: DirEntryData) {
+        if self.filter.as_ref().is_none_or(|filter| filter.is_match(&data.name)) {
+            self.items.push(DirectoryListItem::Entry(data));
+        } else {
+            // entries which can't be seen must not be acted on
+            data.marked = false;
+            self.filtered.push(data);
+        }
+    }
+
+    /// Replace the filter, keeping the selected entry selected if it still matches.
+    pub(crate) fn set_filter(&mut self, filter: Option<Filter>) {
+        let selected = match self.get_selected_item() {
+            Some(DirectoryListItem::Entry(entry)) => Some(entry.name.clone()),
+            _ => None,
+        };
+        self.filter = filter;
+        let mut entries = std::mem::take(&mut self.filtered);
+        for item in std::mem::take(&mut self.items) {
+            match item {
+                DirectoryListItem::ParentDir(_) => self.items.push(item),
+                DirectoryListItem::Entry(data) => entries.push(data),
+            }
+        }
+        for data in entries {
+            self.insert_entry(data);
+        }
+        self.sort();
+        if let Some(name) = selected {
+            self.select_by_name(&name);
+        }
+        if self.state.selected().is_none() {
+            self.select_first();
+        }
+        self.selection_changed = true;
+    }
+
+    /// Record the computed size of a directory, whether it is listed or filtered out.
+    pub(crate) fn set_size(&mut self, name: &str, size: u64) {
+        let listed = self.items.iter_mut().filter_map(|item| match item {
+            DirectoryListItem::Entry(e) => Some(e),
+            DirectoryListItem::ParentDir(_) => None,
+        });
+        if let Some(entry) = listed.chain(self.filtered.iter_mut()).find(|e| e.name == name) {
+            entry.size = Some(size);
+        }
+    }
+
+    /// Whether a new entry belongs in the listing at all, flagging it if it is ignored.
+    fn admit(&self, data: &mut DirEntryData) -> bool {
+        if !self.is_visible(&data.name) {
+            return false;
+        }
+        data.ignored = self.ignore_rules
+            .as_ref()
+            .is_some_and(|rules| rules.is_ignored(&Path::new(&self.dir).join(&data.name), data.file_type.is_dir()));
+        // until the next git status comes in
+        data.git_status = self.git.as_ref().map(|git| git.status_of(&data.name, data.file_type.is_dir()));
+        !(data.ignored && self.ignore_mode == IgnoreMode::Hide)
+    }
+
+    /// Whether an entry with the given name belongs in the listing.
+    pub(crate) fn is_visible(&self, name: &str) -> bool {
+        self.show_hidden || !is_hidden(name)
+    }
+
     /// Sort the DirectoryListItems based on the `sort_by` parameter.
     fn compare_dir_items(a: &DirectoryListItem, b: &DirectoryListItem, sort_by: &SortBy) -> Ordering {
         match (a, b) {
@@ -682,16 +877,13 @@ impl DirectoryList {
     pub(crate) fn select_by_name(&mut self, name: &str) {
         self.unselect();
         for (i, x) in self.items.iter().enumerate() {
-            match x {
-                DirectoryListItem::Entry(entry) => {
-                    let fname = entry.name.as_str();
-                    if name.eq(fname) {
-                        self.state.select(Some(i));
-                        self.selection_changed = true;
-                        break;
-                    }
+            if let DirectoryListItem::Entry(entry) = x {
+                let fname = entry.name.as_str();
+                if name.eq(fname) {
+                    self.state.select(Some(i));
+                    self.selection_changed = true;
+                    break;
                 }
-                _ => {}
             }
         }
     }
@@ -761,33 +953,87 @@ impl DirectoryList {
     /// Select the previous item in the list, without wrapping.
     pub(crate) fn select_previous(&mut self) {
         let i = match self.state.selected() {
-            Some(i) => {
-                if i > 0 {
-                    i - 1
-                } else {
-                    0
-                }
-            }
-            None => 0,
+            Some(i) if i > 0 => i - 1,
+            _ => 0,
         };
         self.state.select(Some(i));
         self.selection_changed = true;
     }
 
     pub(crate) fn select_last(&mut self) {
-        if self.items.len() > 0 {
+        if !self.items.is_empty() {
             self.state.select(Some(self.items.len() - 1));
             self.selection_changed = true;
         }
     }
 
     pub(crate) fn select_first(&mut self) {
-        if self.items.len() > 0 {
+        if !self.items.is_empty() {
             self.state.select(Some(0));
             self.selection_changed = true;
         }
     }
 
+    /// All entries which are currently marked.
+    pub(crate) fn marked_entries(&self) -> Vec<&DirEntryData> {
+        self.items
+            .iter()
+            .filter_map(|item| match item {
+                DirectoryListItem::Entry(e) if e.marked => Some(e),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Toggle the mark on the selected entry. Returns `true` if an entry was toggled.
+    pub(crate) fn toggle_mark(&mut self) -> bool {
+        let Some(i) = self.state.selected() else {
+            return false;
+        };
+        match self.items.get_mut(i) {
+            Some(DirectoryListItem::Entry(e)) => {
+                e.marked = !e.marked;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub(crate) fn mark_all(&mut self) {
+        self.entries_mut().for_each(|e| e.marked = true);
+    }
+
+    pub(crate) fn invert_marks(&mut self) {
+        self.entries_mut().for_each(|e| e.marked = !e.marked);
+    }
+
+    pub(crate) fn clear_marks(&mut self) {
+        self.entries_mut().for_each(|e| e.marked = false);
+    }
+
+    /// Mark every entry whose name matches the glob `pattern` (e.g. `*.rs`).
+    /// Returns the number of newly marked entries.
+    pub(crate) fn mark_by_glob(&mut self, pattern: &str) -> Result<usize> {
+        let matcher = Glob::new(pattern)?.compile_matcher();
+        let mut count = 0;
+        for e in self.entries_mut() {
+            if !e.marked && matcher.is_match(&e.name) {
+                e.marked = true;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    fn entries_mut(&mut self) -> impl Iterator<Item = &mut DirEntryData> {
+        self.items
+            .iter_mut()
+            .filter_map(|item| match item {
+                DirectoryListItem::Entry(e) => Some(e),
+                DirectoryListItem::ParentDir(_) => None,
+            })
+    }
+
     /// Unselect any previously selected item in the list.
     fn unselect(&mut self) {
         self.state.select(None);
@@ -795,3 +1041,210 @@ impl DirectoryList {
     }
 }
 
+/// Dotfiles are hidden by convention.
+pub(crate) fn is_hidden(name: &str) -> bool {
+    name.starts_with('.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterMode;
+    use crate::ignore_rules::IgnoreMode;
+
+    fn dir_list_with_files(names: &[&str]) -> (tempfile::TempDir, DirectoryList) {
+        let tmp = tempfile::tempdir().unwrap();
+        for name in names {
+            fs::write(tmp.path().join(name), name).unwrap();
+        }
+        let mut dir_list = DirectoryList::new(tmp.path().to_str().unwrap().to_string());
+        dir_list.refresh().unwrap();
+        (tmp, dir_list)
+    }
+
+    fn marked_names(dir_list: &DirectoryList) -> Vec<String> {
+        dir_list.marked_entries().iter().map(|e| e.name.clone()).collect()
+    }
+
+    #[test]
+    fn test_marking() {
+        let (_tmp, mut dir_list) = dir_list_with_files(&["a.rs", "b.rs", "c.txt"]);
+
+        // `..` can't be marked
+        dir_list.select_first();
+        assert!(!dir_list.toggle_mark());
+        dir_list.select_by_name("c.txt");
+        assert!(dir_list.toggle_mark());
+        assert_eq!(vec!["c.txt"], marked_names(&dir_list));
+
+        assert_eq!(2, dir_list.mark_by_glob("*.rs").unwrap());
+        assert_eq!(3, dir_list.marked_entries().len());
+
+        dir_list.invert_marks();
+        assert!(dir_list.marked_entries().is_empty());
+        dir_list.mark_all();
+        assert_eq!(3, dir_list.marked_entries().len());
+        dir_list.clear_marks();
+        assert!(dir_list.marked_entries().is_empty());
+    }
+
+    #[test]
+    fn test_marks_survive_refresh() {
+        let (_tmp, mut dir_list) = dir_list_with_files(&["a.rs", "b.rs"]);
+
+        dir_list.mark_by_glob("a.*").unwrap();
+        dir_list.refresh().unwrap();
+        assert_eq!(vec!["a.rs"], marked_names(&dir_list));
+    }
+
+    fn entry_names(dir_list: &DirectoryList) -> Vec<String> {
+        dir_list.items
+            .iter()
+            .filter_map(|item| match item {
+                DirectoryListItem::Entry(e) => Some(e.name.clone()),
+                DirectoryListItem::ParentDir(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hidden_files() {
+        let (tmp, mut dir_list) = dir_list_with_files(&[".hidden", "shown"]);
+        assert_eq!(vec!["shown"], entry_names(&dir_list));
+
+        // files created while hidden files are hidden must not show up either
+        for name in [".new", "new"] {
+            let path = tmp.path().join(name);
+            fs::write(&path, "").unwrap();
+            let event = notify::Event::new(notify::EventKind::Create(notify::event::CreateKind::File)).add_path(path);
+            dir_list.smart_refresh(vec![event]).unwrap();
+        }
+        assert_eq!(vec!["new", "shown"], entry_names(&dir_list));
+
+        dir_list.show_hidden = true;
+        dir_list.refresh().unwrap();
+        assert_eq!(vec![".hidden", ".new", "new", "shown"], entry_names(&dir_list));
+    }
+
+    #[test]
+    fn test_selection_clamped_after_removal() {
+        let (tmp, mut dir_list) = dir_list_with_files(&["a", "b", "c"]);
+        dir_list.select_last();
+
+        let path = tmp.path().join("c");
+        fs::remove_file(&path).unwrap();
+        let event = notify::Event::new(notify::EventKind::Remove(notify::event::RemoveKind::File)).add_path(path);
+        dir_list.selection_changed = false;
+        dir_list.smart_refresh(vec![event]).unwrap();
+        assert_eq!(Some(2), dir_list.state.selected());
+        assert!(dir_list.selection_changed);
+
+        fs::remove_file(tmp.path().join("b")).unwrap();
+        dir_list.refresh().unwrap();
+        assert!(matches!(dir_list.get_selected_item(), Some(DirectoryListItem::Entry(e)) if e.name == "a"));
+    }
+
+    #[test]
+    fn test_filter() {
+        let (tmp, mut dir_list) = dir_list_with_files(&["a.rs", "b.rs", "c.txt"]);
+        dir_list.select_by_name("c.txt");
+        dir_list.toggle_mark();
+
+        dir_list.set_filter(Some(Filter::new(FilterMode::Glob, "*.rs").unwrap()));
+        assert_eq!(vec!["a.rs", "b.rs"], entry_names(&dir_list));
+        assert!(matches!(dir_list.items[0], DirectoryListItem::ParentDir(_)));
+        // hidden entries lose their mark
+        assert!(dir_list.marked_entries().is_empty());
+
+        // new files which don't match stay hidden, but aren't forgotten
+        for name in ["d.rs", "e.txt"] {
+            let path = tmp.path().join(name);
+            fs::write(&path, "").unwrap();
+            let event = notify::Event::new(notify::EventKind::Create(notify::event::CreateKind::File)).add_path(path);
+            dir_list.smart_refresh(vec![event]).unwrap();
+        }
+        assert_eq!(vec!["a.rs", "b.rs", "d.rs"], entry_names(&dir_list));
+        dir_list.refresh().unwrap();
+        assert_eq!(vec!["a.rs", "b.rs", "d.rs"], entry_names(&dir_list));
+
+        dir_list.set_filter(None);
+        assert_eq!(vec!["a.rs", "b.rs", "c.txt", "d.rs", "e.txt"], entry_names(&dir_list));
+    }
+
+    #[test]
+    fn test_ignore_modes() {
+        let (tmp, mut dir_list) = dir_list_with_files(&["a.log", "b.rs"]);
+        fs::write(tmp.path().join(".ignore"), "*.log\n").unwrap();
+        dir_list.refresh().unwrap();
+        assert_eq!(vec!["a.log", "b.rs"], entry_names(&dir_list));
+
+        dir_list.ignore_mode = IgnoreMode::Dim;
+        dir_list.refresh().unwrap();
+        let ignored: Vec<bool> = dir_list.items
+            .iter()
+            .filter_map(|item| match item {
+                DirectoryListItem::Entry(e) => Some(e.ignored),
+                DirectoryListItem::ParentDir(_) => None,
+            })
+            .collect();
+        assert_eq!(vec![true, false], ignored);
+
+        dir_list.ignore_mode = IgnoreMode::Hide;
+        dir_list.refresh().unwrap();
+        assert_eq!(vec!["b.rs"], entry_names(&dir_list));
+        let path = tmp.path().join("c.log");
+        fs::write(&path, "").unwrap();
+        let event = notify::Event::new(notify::EventKind::Create(notify::event::CreateKind::File)).add_path(path);
+        dir_list.smart_refresh(vec![event]).unwrap();
+        assert_eq!(vec!["b.rs"], entry_names(&dir_list));
+
+        // changing the rules re-evaluates every entry
+        let path = tmp.path().join(".ignore");
+        fs::write(&path, "*.rs\n").unwrap();
+        let event = notify::Event::new(notify::EventKind::Modify(notify::event::ModifyKind::Any)).add_path(path);
+        dir_list.smart_refresh(vec![event]).unwrap();
+        assert_eq!(vec!["a.log", "c.log"], entry_names(&dir_list));
+    }
+
+    #[test]
+    fn test_git_status() {
+        let (tmp, mut dir_list) = dir_list_with_files(&["tracked", "new"]);
+        let notify = dir_list.git_status_rx.as_ref().unwrap().recv().unwrap();
+        assert_eq!(None, notify.status);
+        dir_list.set_git_status(notify);
+
+        for args in [&["init", "-q"][..], &["add", "tracked"]] {
+            let status = std::process::Command::new("git").args(args).current_dir(tmp.path()).status().unwrap();
+            assert!(status.success());
+        }
+        // requests made while git runs are covered by one more run
+        dir_list.refresh().unwrap();
+        let first = dir_list.git_status_requests;
+        dir_list.refresh().unwrap();
+        dir_list.refresh().unwrap();
+        assert!(dir_list.git_status_running && dir_list.git_status_queued);
+        let notify = dir_list.git_status_rx.as_ref().unwrap().recv().unwrap();
+        assert_eq!(first, notify.request);
+        dir_list.set_git_status(notify);
+        assert!(dir_list.git_status_running && !dir_list.git_status_queued);
+        let notify = dir_list.git_status_rx.as_ref().unwrap().recv().unwrap();
+        let request = notify.request;
+        assert_eq!(first + 2, request);
+        dir_list.set_git_status(notify);
+        assert!(dir_list.has_git_status(request));
+        // results which are older than the recorded one are dropped
+        dir_list.set_git_status(GitStatusNotification { dir: dir_list.dir.clone(), request: request - 1, status: None });
+        assert!(dir_list.git.is_some());
+        // and so are results for another directory
+        dir_list.set_git_status(GitStatusNotification { dir: "/elsewhere".to_string(), request: request + 1, status: None });
+        assert!(dir_list.git.is_some());
+        let statuses: Vec<String> = dir_list.items
+            .iter()
+            .filter_map(|item| match item {
+                DirectoryListItem::Entry(e) => e.git_status.map(|s| s.to_string()),
+                DirectoryListItem::ParentDir(_) => None,
+            })
+            .collect();
+        assert_eq!(vec!["?", "S"], statuses);
+    }
+}
@@ -0,0 +1,491 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use num_format::{Locale, ToFormattedString};
+
+/// Tables bigger than this (program and section headers, the dynamic section and its strings)
+/// are taken as a sign of a broken file.
+const MAX_TABLE_BYTES: u64 = 4 * 1024 * 1024;
+/// How much of a script is looked at for a `#!` line.
+const MAX_SHEBANG_LEN: u64 = 256;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+const SHT_SYMTAB: u32 = 2;
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_STRSZ: u64 = 10;
+const DT_SONAME: u64 = 14;
+const DT_RPATH: u64 = 15;
+const DT_RUNPATH: u64 = 29;
+const DT_FLAGS_1: u64 = 0x6fff_fffb;
+const DF_1_PIE: u64 = 0x0800_0000;
+
+/// The type of an ELF file, `e_type`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ElfKind {
+    Relocatable,
+    Executable,
+    /// A `DYN` file with an interpreter, or flagged as a PIE.
+    PositionIndependent,
+    SharedObject,
+    Core,
+    #[default]
+    Unknown,
+}
+
+impl fmt::Display for ElfKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let output = match self {
+            ElfKind::Relocatable => "REL (relocatable object)",
+            ElfKind::Executable => "EXEC (executable)",
+            ElfKind::PositionIndependent => "DYN (position-independent executable)",
+            ElfKind::SharedObject => "DYN (shared object)",
+            ElfKind::Core => "CORE (core dump)",
+            ElfKind::Unknown => "unknown",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+/// What an ELF file (an executable, shared object or object file) says about itself.
+#[derive(Debug, Default, PartialEq)]
+pub struct ElfInfo {
+    /// 32 or 64.
+    pub bits: u8,
+    pub little_endian: bool,
+    pub kind: ElfKind,
+    pub machine: String,
+    pub entry: u64,
+    pub interpreter: Option<String>,
+    /// Whether the file is linked at runtime.
+    pub dynamic: bool,
+    pub soname: Option<String>,
+    /// The libraries it links against, `DT_NEEDED`.
+    pub needed: Vec<String>,
+    pub rpath: Option<String>,
+    pub runpath: Option<String>,
+    /// It has no symbol table.
+    pub stripped: bool,
+    pub debug_info: bool,
+    /// The names and sizes of the sections.
+    pub sections: Vec<(String, u64)>,
+}
+
+impl ElfInfo {
+    /// The facts about the file as `Key: value` lines, without the sections.
+    pub(crate) fn summary(&self) -> Vec<String> {
+        let endian = if self.little_endian { "little endian" } else { "big endian" };
+        let mut lines = vec![
+            format!("Class: ELF{}, {}", self.bits, endian),
+            format!("Type: {}", self.kind),
+            format!("Machine: {}", self.machine),
+        ];
+        if self.entry != 0 {
+            lines.push(format!("Entry point: {:#x}", self.entry));
+        }
+        if let Some(interpreter) = &self.interpreter {
+            lines.push(format!("Interpreter: {}", interpreter));
+        }
+        if !self.dynamic && matches!(self.kind, ElfKind::Executable | ElfKind::PositionIndependent) {
+            lines.push("Linking: static".to_string());
+        }
+        if let Some(soname) = &self.soname {
+            lines.push(format!("SONAME: {}", soname));
+        }
+        if !self.needed.is_empty() {
+            lines.push("Needed:".to_string());
+            lines.extend(self.needed.iter().map(|library| format!("  {}", library)));
+        }
+        if let Some(rpath) = &self.rpath {
+            lines.push(format!("RPATH: {}", rpath));
+        }
+        if let Some(runpath) = &self.runpath {
+            lines.push(format!("RUNPATH: {}", runpath));
+        }
+        let stripped = if self.stripped { "stripped" } else { "not stripped" };
+        let debug_info = if self.debug_info { "with debug info" } else { "no debug info" };
+        lines.push(format!("Symbols: {}, {}", stripped, debug_info));
+        lines
+    }
+
+    /// The summary, followed by the sizes of the sections.
+    pub(crate) fn describe(&self) -> Vec<String> {
+        let mut lines = self.summary();
+        if !self.sections.is_empty() {
+            lines.push(String::new());
+            lines.push("Sections:".to_string());
+            let width = self.sections.iter().map(|(name, _)| name.chars().count()).max().unwrap_or_default();
+            for (name, size) in &self.sections {
+                lines.push(format!("  {:<width$}  {:>13}", name, size.to_formatted_string(&Locale::en)));
+            }
+        }
+        lines
+    }
+}
+
+/// Recognize an ELF file by its first bytes.
+pub(crate) fn is_elf(path: &Path) -> bool {
+    let mut magic = [0; 4];
+    File::open(path).and_then(|mut file| file.read_exact(&mut magic)).is_ok() && magic == *b"\x7fELF"
+}
+
+/// The interpreter named by the `#!` line of a script, with its arguments.
+pub(crate) fn shebang(path: &Path) -> Option<String> {
+    let mut line = vec![];
+    BufReader::new(File::open(path).ok()?.take(MAX_SHEBANG_LEN)).read_until(b'\n', &mut line).ok()?;
+    let interpreter = String::from_utf8(line.strip_prefix(b"#!")?.to_vec()).ok()?;
+    let interpreter = interpreter.trim();
+    (!interpreter.is_empty()).then(|| interpreter.to_string())
+}
+
+/// Read the headers of the ELF file at `path`; only the tables describing it are read, not the
+/// code or data.
+pub(crate) fn read_elf(path: &Path) -> Result<ElfInfo> {
+    let file = File::open(path)?;
+    let mut ident = [0; 64];
+    let len = file.read_at(&mut ident, 0)?;
+    if len < 6 || ident[..4] != *b"\x7fELF" {
+        bail!("not an ELF file");
+    }
+    let elf = Elf {
+        file,
+        wide: match ident[4] {
+            1 => false,
+            2 => true,
+            class => bail!("unknown ELF class {}", class),
+        },
+        little: match ident[5] {
+            1 => true,
+            2 => false,
+            data => bail!("unknown ELF byte order {}", data),
+        },
+    };
+    if len < elf.pick(52, 64) {
+        bail!("truncated ELF header");
+    }
+    let header = elf.fields(&ident);
+    let kind = header.u16(16);
+    let mut info = ElfInfo {
+        bits: if elf.wide { 64 } else { 32 },
+        little_endian: elf.little,
+        machine: machine_name(header.u16(18)),
+        entry: header.word(24, 24),
+        ..Default::default()
+    };
+
+    // program headers: the interpreter and the dynamic section
+    let (phoff, phentsize, phnum) = (header.word(28, 32), header.u16(elf.pick(42, 54)), header.u16(elf.pick(44, 56)));
+    let mut loads = vec![];
+    let mut dynamic = None;
+    if phnum > 0 {
+        if usize::from(phentsize) < elf.pick(32, 56) {
+            bail!("invalid program header size {}", phentsize);
+        }
+        let table = elf.read(phoff, u64::from(phentsize) * u64::from(phnum))?;
+        for entry in table.chunks_exact(usize::from(phentsize)) {
+            let entry = elf.fields(entry);
+            let (offset, vaddr, filesz) = (entry.word(4, 8), entry.word(8, 16), entry.word(16, 32));
+            match entry.u32(0) {
+                PT_LOAD => loads.push((vaddr, offset, filesz)),
+                PT_DYNAMIC => dynamic = Some((offset, filesz)),
+                PT_INTERP => {
+                    let bytes = elf.read(offset, filesz)?;
+                    info.interpreter = c_str(&bytes, 0);
+                },
+                _ => {},
+            }
+        }
+    }
+
+    let mut pie = false;
+    if let Some((offset, size)) = dynamic {
+        info.dynamic = true;
+        let table = elf.read(offset, size)?;
+        let mut entries = vec![];
+        for entry in table.chunks_exact(elf.pick(8, 16)) {
+            let entry = elf.fields(entry);
+            let (tag, value) = (entry.word(0, 0), entry.word(4, 8));
+            if tag == DT_NULL {
+                break;
+            }
+            entries.push((tag, value));
+        }
+        let value = |tag: u64| entries.iter().find(|(t, _)| *t == tag).map(|(_, value)| *value);
+        pie = value(DT_FLAGS_1).is_some_and(|flags| flags & DF_1_PIE != 0);
+        // the string table is given by its address, within one of the loaded segments
+        let strtab = value(DT_STRTAB).and_then(|address| {
+            loads.iter()
+                .find(|(vaddr, _, filesz)| (*vaddr..vaddr.saturating_add(*filesz)).contains(&address))
+                .and_then(|(vaddr, offset, _)| address.checked_sub(*vaddr)?.checked_add(*offset))
+        });
+        if let (Some(strtab), Some(size)) = (strtab, value(DT_STRSZ)) {
+            let strings = elf.read(strtab, size)?;
+            let string = |value: u64| usize::try_from(value).ok().and_then(|offset| c_str(&strings, offset));
+            for (tag, value) in &entries {
+                match *tag {
+                    DT_NEEDED => info.needed.extend(string(*value)),
+                    DT_SONAME => info.soname = string(*value),
+                    DT_RPATH => info.rpath = string(*value),
+                    DT_RUNPATH => info.runpath = string(*value),
+                    _ => {},
+                }
+            }
+        }
+    }
+    info.kind = match kind {
+        1 => ElfKind::Relocatable,
+        2 => ElfKind::Executable,
+        3 if pie || info.interpreter.is_some() => ElfKind::PositionIndependent,
+        3 => ElfKind::SharedObject,
+        4 => ElfKind::Core,
+        _ => ElfKind::Unknown,
+    };
+
+    // section headers: their names, sizes and types
+    let (shoff, shentsize) = (header.word(32, 40), header.u16(elf.pick(46, 58)));
+    let (mut shnum, mut shstrndx) = (u64::from(header.u16(elf.pick(48, 60))), u32::from(header.u16(elf.pick(50, 62))));
+    if shoff > 0 {
+        if usize::from(shentsize) < elf.pick(40, 64) {
+            bail!("invalid section header size {}", shentsize);
+        }
+        // with too many sections for the header, their number and the index of their names are
+        // in the first section header
+        let first = elf.read(shoff, u64::from(shentsize))?;
+        let first = elf.fields(&first);
+        if shnum == 0 {
+            shnum = first.word(20, 32);
+        }
+        if shstrndx == 0xffff {
+            shstrndx = first.u32(elf.pick(24, 40));
+        }
+        let table = elf.read(shoff, u64::from(shentsize).saturating_mul(shnum))?;
+        let sections: Vec<Fields> = table.chunks_exact(usize::from(shentsize)).map(|entry| elf.fields(entry)).collect();
+        let names = match sections.get(shstrndx as usize) {
+            Some(names) => elf.read(names.word(16, 24), names.word(20, 32))?,
+            None => vec![],
+        };
+        info.stripped = true;
+        for section in sections.iter().skip(1) {
+            let name = c_str(&names, section.u32(0) as usize).unwrap_or_default();
+            if section.u32(4) == SHT_SYMTAB {
+                info.stripped = false;
+            }
+            if name.starts_with(".debug_") || name.starts_with(".zdebug_") {
+                info.debug_info = true;
+            }
+            info.sections.push((name, section.word(20, 32)));
+        }
+    }
+    Ok(info)
+}
+
+/// An open ELF file, of the class and byte order given by its header.
+struct Elf {
+    file: File,
+    wide: bool,
+    little: bool,
+}
+
+impl Elf {
+    /// The offset (or size) of something in 32-bit and in 64-bit files.
+    fn pick(&self, offset32: usize, offset64: usize) -> usize {
+        if self.wide { offset64 } else { offset32 }
+    }
+
+    fn read(&self, offset: u64, len: u64) -> Result<Vec<u8>> {
+        if len > MAX_TABLE_BYTES {
+            bail!("table too big ({} bytes)", len);
+        }
+        let mut buf = vec![0; len as usize];
+        self.file.read_exact_at(&mut buf, offset)?;
+        Ok(buf)
+    }
+
+    fn fields<'a>(&self, bytes: &'a [u8]) -> Fields<'a> {
+        Fields { bytes, wide: self.wide, little: self.little }
+    }
+}
+
+/// The fields of a header or table entry. Fields beyond its end read as 0.
+struct Fields<'a> {
+    bytes: &'a [u8],
+    wide: bool,
+    little: bool,
+}
+
+impl Fields<'_> {
+    fn array<const N: usize>(&self, offset: usize) -> [u8; N] {
+        self.bytes.get(offset..offset + N).and_then(|bytes| bytes.try_into().ok()).unwrap_or([0; N])
+    }
+
+    fn u16(&self, offset: usize) -> u16 {
+        let bytes = self.array(offset);
+        if self.little { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) }
+    }
+
+    fn u32(&self, offset: usize) -> u32 {
+        let bytes = self.array(offset);
+        if self.little { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) }
+    }
+
+    fn u64(&self, offset: usize) -> u64 {
+        let bytes = self.array(offset);
+        if self.little { u64::from_le_bytes(bytes) } else { u64::from_be_bytes(bytes) }
+    }
+
+    /// An address, offset or size: 4 bytes at `offset32` in 32-bit files, 8 bytes at `offset64`
+    /// in 64-bit ones.
+    fn word(&self, offset32: usize, offset64: usize) -> u64 {
+        if self.wide { self.u64(offset64) } else { u64::from(self.u32(offset32)) }
+    }
+}
+
+/// The NUL-terminated string at `offset`.
+fn c_str(bytes: &[u8], offset: usize) -> Option<String> {
+    let bytes = bytes.get(offset..)?;
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    Some(String::from_utf8_lossy(&bytes[..end]).to_string())
+}
+
+fn machine_name(machine: u16) -> String {
+    let name = match machine {
+        2 => "SPARC",
+        3 => "x86",
+        8 => "MIPS",
+        20 => "PowerPC",
+        21 => "PowerPC64",
+        22 => "S/390",
+        40 => "ARM",
+        43 => "SPARC V9",
+        50 => "IA-64",
+        62 => "x86-64",
+        183 => "AArch64",
+        243 => "RISC-V",
+        247 => "BPF",
+        258 => "LoongArch",
+        _ => return format!("unknown ({})", machine),
+    };
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// A little-endian ELF64 position-independent executable, with an interpreter, a dynamic
+    /// section and section headers, but no code.
+    fn sample_elf() -> Vec<u8> {
+        fn put(bytes: &mut Vec<u8>, offset: usize, value: &[u8]) {
+            if bytes.len() < offset + value.len() {
+                bytes.resize(offset + value.len(), 0);
+            }
+            bytes[offset..offset + value.len()].copy_from_slice(value);
+        }
+        let base = 0x40_0000u64;
+        let mut elf = vec![];
+        // header
+        put(&mut elf, 0, b"\x7fELF\x02\x01\x01");
+        put(&mut elf, 16, &3u16.to_le_bytes());
+        put(&mut elf, 18, &62u16.to_le_bytes());
+        put(&mut elf, 24, &(base + 0x1040).to_le_bytes());
+        put(&mut elf, 32, &64u64.to_le_bytes());
+        put(&mut elf, 40, &392u64.to_le_bytes());
+        put(&mut elf, 54, &56u16.to_le_bytes());
+        put(&mut elf, 56, &3u16.to_le_bytes());
+        put(&mut elf, 58, &64u16.to_le_bytes());
+        put(&mut elf, 60, &4u16.to_le_bytes());
+        put(&mut elf, 62, &2u16.to_le_bytes());
+        // program headers: (type, offset, address, size)
+        for (i, (kind, offset, size)) in [(PT_LOAD, 0u64, 456u64), (PT_INTERP, 232, 11), (PT_DYNAMIC, 280, 80)].iter().enumerate() {
+            let entry = 64 + i * 56;
+            put(&mut elf, entry, &kind.to_le_bytes());
+            put(&mut elf, entry + 8, &offset.to_le_bytes());
+            put(&mut elf, entry + 16, &(base + offset).to_le_bytes());
+            put(&mut elf, entry + 32, &size.to_le_bytes());
+        }
+        put(&mut elf, 232, b"/lib/ld.so\0");
+        put(&mut elf, 248, b"\0libfoo.so.1\0$ORIGIN/lib\0");
+        for (i, (tag, value)) in [(DT_NEEDED, 1), (DT_RUNPATH, 13), (DT_STRTAB, base + 248), (DT_STRSZ, 25), (DT_NULL, 0)].iter().enumerate() {
+            put(&mut elf, 280 + i * 16, &tag.to_le_bytes());
+            put(&mut elf, 280 + i * 16 + 8, &value.to_le_bytes());
+        }
+        put(&mut elf, 360, b"\0.text\0.shstrtab\0.symtab\0");
+        // section headers: (name, type, offset, size), after the null one
+        for (i, (name, kind, offset, size)) in [(1u32, 1u32, 0u64, 100u64), (7, 3, 360, 25), (17, SHT_SYMTAB, 0, 48)].iter().enumerate() {
+            let entry = 392 + (i + 1) * 64;
+            put(&mut elf, entry, &name.to_le_bytes());
+            put(&mut elf, entry + 4, &kind.to_le_bytes());
+            put(&mut elf, entry + 24, &offset.to_le_bytes());
+            put(&mut elf, entry + 32, &size.to_le_bytes());
+        }
+        elf.resize(392 + 4 * 64, 0);
+        elf
+    }
+
+    #[test]
+    fn test_read_elf() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("a.out");
+        fs::write(&path, sample_elf()).unwrap();
+        assert!(is_elf(&path));
+        let info = read_elf(&path).unwrap();
+        assert_eq!(ElfInfo {
+            bits: 64,
+            little_endian: true,
+            kind: ElfKind::PositionIndependent,
+            machine: "x86-64".to_string(),
+            entry: 0x40_1040,
+            interpreter: Some("/lib/ld.so".to_string()),
+            dynamic: true,
+            soname: None,
+            needed: vec!["libfoo.so.1".to_string()],
+            rpath: None,
+            runpath: Some("$ORIGIN/lib".to_string()),
+            stripped: false,
+            debug_info: false,
+            sections: vec![(".text".to_string(), 100), (".shstrtab".to_string(), 25), (".symtab".to_string(), 48)],
+        }, info);
+        let lines = info.describe();
+        assert!(lines.contains(&"  libfoo.so.1".to_string()));
+        assert!(lines.contains(&"Symbols: not stripped, no debug info".to_string()));
+        assert_eq!("  .shstrtab             25", lines[lines.len() - 2]);
+
+        // truncated tables are errors, not panics
+        let mut truncated = sample_elf();
+        truncated.truncate(300);
+        fs::write(&path, truncated).unwrap();
+        assert!(read_elf(&path).is_err());
+
+        // a string table which can't be in the file is left out, like a missing one
+        let mut overflowing = sample_elf();
+        overflowing[72..80].copy_from_slice(&(u64::MAX - 10).to_le_bytes());
+        fs::write(&path, overflowing).unwrap();
+        let info = read_elf(&path).unwrap();
+        assert!(info.needed.is_empty() && info.runpath.is_none());
+
+        // the test binary itself
+        let exe = std::env::current_exe().unwrap();
+        assert!(is_elf(&exe));
+        let info = read_elf(&exe).unwrap();
+        assert!(!info.stripped && !info.sections.is_empty());
+    }
+
+    #[test]
+    fn test_shebang() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("run");
+        fs::write(&path, "#!/usr/bin/env python3 -u\nprint()\n").unwrap();
+        assert_eq!(Some("/usr/bin/env python3 -u".to_string()), shebang(&path));
+        fs::write(&path, "echo\n").unwrap();
+        assert_eq!(None, shebang(&path));
+        assert!(!is_elf(&path));
+    }
+}
@@ -0,0 +1,113 @@
+use std::fmt;
+
+use anyhow::Result;
+use globset::{GlobBuilder, GlobMatcher};
+use regex::{Regex, RegexBuilder};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FilterMode {
+    #[default]
+    Substring,
+    Glob,
+    Regex,
+}
+
+impl FilterMode {
+    pub(crate) fn next(&self) -> Self {
+        match self {
+            FilterMode::Substring => FilterMode::Glob,
+            FilterMode::Glob => FilterMode::Regex,
+            FilterMode::Regex => FilterMode::Substring,
+        }
+    }
+}
+
+impl fmt::Display for FilterMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let output = match self {
+            FilterMode::Substring => "substring",
+            FilterMode::Glob => "glob",
+            FilterMode::Regex => "regex",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Matcher {
+    Substring(String),
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+/// Narrows a directory listing down to the names matching a pattern. Matching is
+/// case-insensitive unless the pattern contains an uppercase letter ("smart case").
+#[derive(Debug, Clone)]
+pub struct Filter {
+    pub mode: FilterMode,
+    pub pattern: String,
+    matcher: Matcher,
+}
+
+impl Filter {
+    pub(crate) fn new(mode: FilterMode, pattern: &str) -> Result<Self> {
+        let ignore_case = !pattern.chars().any(|c| c.is_uppercase());
+        let matcher = match mode {
+            FilterMode::Substring => {
+                Matcher::Substring(if ignore_case { pattern.to_lowercase() } else { pattern.to_string() })
+            },
+            FilterMode::Glob => {
+                let glob = GlobBuilder::new(pattern)
+                    .case_insensitive(ignore_case)
+                    .literal_separator(true)
+                    .build()?;
+                Matcher::Glob(glob.compile_matcher())
+            },
+            FilterMode::Regex => {
+                Matcher::Regex(RegexBuilder::new(pattern).case_insensitive(ignore_case).build()?)
+            },
+        };
+        Ok(Self { mode, pattern: pattern.to_string(), matcher })
+    }
+
+    pub(crate) fn is_match(&self, name: &str) -> bool {
+        match &self.matcher {
+            Matcher::Substring(s) if s.chars().any(|c| c.is_uppercase()) => name.contains(s.as_str()),
+            Matcher::Substring(s) => name.to_lowercase().contains(s.as_str()),
+            Matcher::Glob(glob) => glob.is_match(name),
+            Matcher::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.mode, self.pattern)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modes() {
+        let filter = Filter::new(FilterMode::Substring, "read").unwrap();
+        assert!(filter.is_match("README.md"));
+        assert!(!filter.is_match("main.rs"));
+        // smart case
+        let filter = Filter::new(FilterMode::Substring, "Read").unwrap();
+        assert!(!filter.is_match("README.md"));
+
+        let filter = Filter::new(FilterMode::Glob, "*.RS").unwrap();
+        assert!(!filter.is_match("main.rs"));
+        let filter = Filter::new(FilterMode::Glob, "*.rs").unwrap();
+        assert!(filter.is_match("MAIN.RS"));
+        assert!(!filter.is_match("main.rs.bak"));
+
+        let filter = Filter::new(FilterMode::Regex, r"^\w+\.rs$").unwrap();
+        assert!(filter.is_match("main.rs"));
+        assert!(!filter.is_match("main.rs.bak"));
+        assert!(Filter::new(FilterMode::Regex, "[").is_err());
+    }
+}
@@ -0,0 +1,410 @@
+use std::path::{Path, PathBuf};
+use std::cmp::Reverse;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::input::TextInput;
+use crate::walk::walk_files;
+
+/// Candidates are sent to the UI thread in batches of this size...
+const FINDER_BATCH_SIZE: usize = 1000;
+/// ...or at least this often, so results show up quickly on slow filesystems.
+const FINDER_BATCH_MILLIS: u64 = 50;
+
+// Scoring, loosely modelled after fzf: every matched char scores, gaps cost, and matches
+// at the start of a word or path component earn a bonus.
+const SCORE_MATCH: i64 = 16;
+const SCORE_GAP_START: i64 = -3;
+const SCORE_GAP_EXTENSION: i64 = -1;
+const BONUS_PATH_SEPARATOR: i64 = 9;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CAMEL: i64 = 7;
+const BONUS_CONSECUTIVE: i64 = 4;
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+/// Candidates are matched on several threads once there are at least this many per thread.
+const MATCH_CHUNK_SIZE: usize = 20_000;
+
+/// A candidate which matches the pattern.
+#[derive(Debug, Clone)]
+pub struct FinderMatch {
+    /// Index into `Finder::candidates`.
+    pub index: usize,
+    pub score: i64,
+    /// Length of the candidate; shorter paths win ties.
+    len: usize,
+}
+
+/// State of the fuzzy finder popup. The files below `root` are collected by a background
+/// thread and streamed in while the user types.
+#[derive(Debug)]
+pub struct Finder {
+    pub root: PathBuf,
+    pub input: TextInput,
+    /// Paths relative to `root`.
+    pub candidates: Vec<String>,
+    /// Matching candidates, best first.
+    pub matches: Vec<FinderMatch>,
+    pub selected: usize,
+    pub walking: bool,
+    /// The pattern `matches` were computed for.
+    pattern: String,
+    rx: Option<Receiver<Vec<String>>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Finder {
+    pub(crate) fn new(root: PathBuf, show_hidden: bool, respect_ignore: bool) -> Self {
+        let (tx, rx) = channel::<Vec<String>>();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let walk_root = root.clone();
+        let walk_cancel = cancel.clone();
+        thread::spawn(move || {
+            let start = Instant::now();
+            let mut batch = vec![];
+            let mut last_sent = Instant::now();
+            walk_files(&walk_root, show_hidden, respect_ignore, &walk_cancel, &mut |path| {
+                batch.push(path.to_string_lossy().to_string());
+                if batch.len() >= FINDER_BATCH_SIZE || last_sent.elapsed() >= Duration::from_millis(FINDER_BATCH_MILLIS) {
+                    last_sent = Instant::now();
+                    // the popup was closed if the receiver is gone
+                    return tx.send(std::mem::take(&mut batch)).is_ok();
+                }
+                true
+            });
+            let _result = tx.send(batch);
+            debug!("finder walk of {} took {:?}", walk_root.display(), start.elapsed());
+        });
+        Self {
+            root,
+            input: TextInput::default(),
+            candidates: vec![],
+            matches: vec![],
+            selected: 0,
+            walking: true,
+            pattern: String::new(),
+            rx: Some(rx),
+            cancel,
+        }
+    }
+
+    /// Take the candidates found since the last call. Returns `true` if there were any.
+    pub(crate) fn poll(&mut self) -> bool {
+        let mut received = false;
+        while let Some(rx) = self.rx.as_mut() {
+            match rx.try_recv() {
+                Ok(batch) => {
+                    let first = self.candidates.len();
+                    self.candidates.extend(batch);
+                    let indexes: Vec<usize> = (first..self.candidates.len()).collect();
+                    let new_matches = match_candidates(&self.candidates, &indexes, &self.pattern);
+                    self.matches.extend(new_matches);
+                    received = true;
+                },
+                Err(TryRecvError::Empty) => {
+                    break;
+                },
+                Err(TryRecvError::Disconnected) => {
+                    self.rx = None;
+                    self.walking = false;
+                }
+            }
+        }
+        if received {
+            self.sort_matches();
+        }
+        received
+    }
+
+    /// Re-match the candidates against the text in `input`.
+    pub(crate) fn update(&mut self) {
+        let new_pattern = self.input.value.clone();
+        if new_pattern == self.pattern {
+            return;
+        }
+        // a longer pattern can only narrow down the previous matches
+        let narrowing = new_pattern.starts_with(&self.pattern)
+            && prepare_pattern(&self.pattern).1 == prepare_pattern(&new_pattern).1;
+        let indexes: Vec<usize> = match narrowing {
+            true => self.matches.iter().map(|m| m.index).collect(),
+            false => (0..self.candidates.len()).collect(),
+        };
+        self.matches = match_candidates(&self.candidates, &indexes, &new_pattern);
+        self.pattern = new_pattern;
+        self.sort_matches();
+        self.selected = 0;
+    }
+
+    fn sort_matches(&mut self) {
+        if self.pattern.is_empty() {
+            // everything matches; keep the order the files were found in
+            return;
+        }
+        self.matches.sort_unstable_by_key(|m| (Reverse(m.score), m.len, m.index));
+    }
+
+    /// The positions (in chars) of the matched characters of a match, for highlighting.
+    /// They are only computed for the matches on screen.
+    pub(crate) fn positions(&self, m: &FinderMatch) -> Vec<usize> {
+        let (pattern, ignore_case) = prepare_pattern(&self.pattern);
+        let chars: Vec<char> = self.candidates[m.index].chars().collect();
+        let mut positions = vec![];
+        fuzzy_match(&pattern, ignore_case, &chars, Some(&mut positions));
+        positions
+    }
+
+    pub(crate) fn select_next(&mut self) {
+        if self.selected + 1 < self.matches.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub(crate) fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// The absolute path of the selected match.
+    pub(crate) fn selected_path(&self) -> Option<PathBuf> {
+        let m = self.matches.get(self.selected)?;
+        Some(self.root.join(Path::new(&self.candidates[m.index])))
+    }
+}
+
+impl Drop for Finder {
+    fn drop(&mut self) {
+        // stop walking once the popup is closed
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Match the candidates at `indexes` against `pattern`, splitting the work across threads
+/// when there are many.
+fn match_candidates(candidates: &[String], indexes: &[usize], pattern: &str) -> Vec<FinderMatch> {
+    let (pattern, ignore_case) = prepare_pattern(pattern);
+    // most candidates can be rejected by looking at their bytes, without decoding chars
+    let ascii_pattern: Option<Vec<u8>> = pattern.iter().all(char::is_ascii)
+        .then(|| pattern.iter().map(|&c| c as u8).collect());
+    let match_chunk = |chunk: &[usize]| -> Vec<FinderMatch> {
+        // reuse one buffer for the chars of all candidates
+        let mut chars: Vec<char> = vec![];
+        chunk
+            .iter()
+            .filter_map(|&index| {
+                let candidate = &candidates[index];
+                if let Some(ascii_pattern) = &ascii_pattern {
+                    if !contains_ascii_subsequence(candidate.as_bytes(), ascii_pattern, ignore_case) {
+                        return None;
+                    }
+                }
+                chars.clear();
+                chars.extend(candidate.chars());
+                fuzzy_match(&pattern, ignore_case, &chars, None)
+                    .map(|score| FinderMatch { index, score, len: candidate.len() })
+            })
+            .collect()
+    };
+    let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let chunk_size = indexes.len().div_ceil(threads).max(MATCH_CHUNK_SIZE);
+    if indexes.len() <= chunk_size {
+        return match_chunk(indexes);
+    }
+    thread::scope(|scope| {
+        let handles: Vec<_> = indexes
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| match_chunk(chunk)))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("finder match thread panicked"))
+            .collect()
+    })
+}
+
+fn contains_ascii_subsequence(haystack: &[u8], needle: &[u8], ignore_case: bool) -> bool {
+    let mut needle = needle.iter().peekable();
+    for &b in haystack {
+        let b = if ignore_case { b.to_ascii_lowercase() } else { b };
+        match needle.peek() {
+            Some(&&n) if n == b => {
+                needle.next();
+            },
+            Some(_) => {},
+            None => break,
+        }
+    }
+    needle.peek().is_none()
+}
+
+/// The chars of `pattern`, and whether to ignore case (if it has no uppercase letters).
+fn prepare_pattern(pattern: &str) -> (Vec<char>, bool) {
+    let ignore_case = !pattern.chars().any(|c| c.is_uppercase());
+    let chars = pattern
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| if ignore_case { fold_case(c) } else { c })
+        .collect();
+    (chars, ignore_case)
+}
+
+fn fold_case(c: char) -> char {
+    if c.is_ascii() {
+        c.to_ascii_lowercase()
+    } else {
+        c.to_lowercase().next().unwrap_or(c)
+    }
+}
+
+/// How much a match at `chars[i]` is worth on top of `SCORE_MATCH`.
+fn bonus_at(chars: &[char], i: usize) -> i64 {
+    let c = chars[i];
+    let Some(&prev) = i.checked_sub(1).and_then(|p| chars.get(p)) else {
+        return BONUS_PATH_SEPARATOR;
+    };
+    if prev == '/' {
+        BONUS_PATH_SEPARATOR
+    } else if !prev.is_alphanumeric() {
+        if c.is_alphanumeric() { BONUS_BOUNDARY } else { 0 }
+    } else if (prev.is_lowercase() && c.is_uppercase()) || (!prev.is_numeric() && c.is_numeric()) {
+        BONUS_CAMEL
+    } else {
+        0
+    }
+}
+
+/// Match `pattern` (as prepared by `prepare_pattern()`) against the `chars` of a candidate,
+/// returning the score and, if asked for, the positions of the matched chars. Like fzf's v1
+/// algorithm, the first occurrence of the pattern is found and then shortened from the back.
+pub(crate) fn fuzzy_match(pattern: &[char], ignore_case: bool, chars: &[char],
+                          mut positions: Option<&mut Vec<usize>>) -> Option<i64> {
+    if pattern.is_empty() {
+        return Some(0);
+    }
+    let fold = |c: char| if ignore_case { fold_case(c) } else { c };
+
+    // the end of the first complete match...
+    let mut pi = 0;
+    let mut end = None;
+    for (i, &c) in chars.iter().enumerate() {
+        if fold(c) == pattern[pi] {
+            pi += 1;
+            if pi == pattern.len() {
+                end = Some(i);
+                break;
+            }
+        }
+    }
+    let end = end?;
+    // ...and the latest start which still matches up to there
+    let mut pi = pattern.len();
+    let mut start = end;
+    for i in (0..=end).rev() {
+        if fold(chars[i]) == pattern[pi - 1] {
+            pi -= 1;
+            if pi == 0 {
+                start = i;
+                break;
+            }
+        }
+    }
+
+    let mut score = 0;
+    let mut pi = 0;
+    let mut in_gap = false;
+    let mut chunk_bonus = 0;
+    let mut consecutive = false;
+    for (i, &c) in chars.iter().enumerate().take(end + 1).skip(start) {
+        if pi < pattern.len() && fold(c) == pattern[pi] {
+            let mut bonus = bonus_at(chars, i);
+            if consecutive {
+                // a run of matches is worth as much as its start
+                chunk_bonus = chunk_bonus.max(bonus);
+                bonus = chunk_bonus.max(BONUS_CONSECUTIVE);
+            } else {
+                chunk_bonus = bonus;
+            }
+            if pi == 0 {
+                bonus *= BONUS_FIRST_CHAR_MULTIPLIER;
+            }
+            score += SCORE_MATCH + bonus;
+            if let Some(positions) = positions.as_mut() {
+                positions.push(i);
+            }
+            pi += 1;
+            in_gap = false;
+            consecutive = true;
+        } else {
+            score += if in_gap { SCORE_GAP_EXTENSION } else { SCORE_GAP_START };
+            in_gap = true;
+            consecutive = false;
+        }
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn score(pattern: &str, candidate: &str) -> Option<(i64, Vec<usize>)> {
+        let (pattern, ignore_case) = prepare_pattern(pattern);
+        let chars: Vec<char> = candidate.chars().collect();
+        let mut positions = vec![];
+        fuzzy_match(&pattern, ignore_case, &chars, Some(&mut positions)).map(|score| (score, positions))
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert_eq!(vec![4, 9], score("mr", "src/main.rs").unwrap().1);
+        assert!(score("mr", "src/lib.rs").is_none());
+        // smart case
+        assert!(score("Main", "src/main.rs").is_none());
+        assert!(score("main", "src/Main.rs").is_some());
+
+        // word starts and consecutive runs beat scattered matches
+        assert!(score("mr", "src/main.rs").unwrap().0 > score("mr", "some/timer").unwrap().0);
+        assert!(score("dl", "src/dir_list.rs").unwrap().0 > score("dl", "src/idle.rs").unwrap().0);
+        assert!(score("main", "main.rs").unwrap().0 > score("main", "my_animation.rs").unwrap().0);
+        // the shortest occurrence is highlighted
+        assert_eq!(vec![6, 7], score("ab", "a/x/a/ab").unwrap().1);
+    }
+
+    #[test]
+    fn test_finder() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join("src/ui")).unwrap();
+        for name in ["src/main.rs", "src/ui/menu.rs", "README.md"] {
+            fs::write(tmp.path().join(name), "").unwrap();
+        }
+        let mut finder = Finder::new(tmp.path().to_path_buf(), false, false);
+        let start = Instant::now();
+        while finder.walking && start.elapsed() < Duration::from_secs(5) {
+            finder.poll();
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(3, finder.candidates.len());
+        assert_eq!(3, finder.matches.len());
+
+        finder.input = TextInput::new("m", vec![]);
+        finder.update();
+        assert_eq!(3, finder.matches.len());
+        finder.input = TextInput::new("mrs", vec![]);
+        finder.update();
+        assert_eq!(2, finder.matches.len());
+        assert_eq!(Some(tmp.path().join("src/main.rs")), finder.selected_path());
+        finder.select_next();
+        assert_eq!(Some(tmp.path().join("src/ui/menu.rs")), finder.selected_path());
+        finder.select_next();
+        assert_eq!(Some(tmp.path().join("src/ui/menu.rs")), finder.selected_path());
+
+        // widening the pattern again brings back everything
+        finder.input = TextInput::new("", vec![]);
+        finder.update();
+        assert_eq!(3, finder.matches.len());
+    }
+}
@@ -0,0 +1,454 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Result};
+use log::debug;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+
+/// The git status of a file, or the combined status of everything below a directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GitStatus {
+    pub conflicted: bool,
+    pub staged: bool,
+    pub modified: bool,
+    pub untracked: bool,
+    pub ignored: bool,
+}
+
+impl GitStatus {
+    /// Colored by the most pressing state.
+    pub(crate) fn style(&self) -> Style {
+        let color = match self {
+            s if s.conflicted => Color::LightRed,
+            s if s.modified => Color::LightYellow,
+            s if s.staged => Color::LightGreen,
+            s if s.untracked => Color::LightCyan,
+            _ => Color::DarkGray,
+        };
+        Style::default().fg(color)
+    }
+
+    fn merge(&mut self, other: &GitStatus) {
+        self.conflicted |= other.conflicted;
+        self.staged |= other.staged;
+        self.modified |= other.modified;
+        self.untracked |= other.untracked;
+        self.ignored |= other.ignored;
+    }
+}
+
+impl fmt::Display for GitStatus {
+    /// One letter per state: `U`nmerged, `S`taged, `M`odified, `?` untracked, `I`gnored.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags = [
+            (self.conflicted, 'U'),
+            (self.staged, 'S'),
+            (self.modified, 'M'),
+            (self.untracked, '?'),
+            (self.ignored, 'I'),
+        ];
+        let output: String = flags.iter().filter(|(set, _)| *set).map(|(_, c)| c).collect();
+        write!(f, "{}", output)
+    }
+}
+
+/// The state of the work tree containing a directory, as far as it concerns that directory.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GitRepoStatus {
+    /// The top level of the work tree.
+    pub root: PathBuf,
+    /// The branch name, or the abbreviated commit when detached.
+    pub branch: String,
+    /// Commits ahead of/behind the upstream, if there is one.
+    pub ahead_behind: Option<(usize, usize)>,
+    /// The directory the status was computed for, relative to `root`.
+    prefix: PathBuf,
+    /// Changed paths relative to `root`. Untracked and ignored directories end up here as a
+    /// whole, without their contents.
+    entries: Vec<(PathBuf, GitStatus)>,
+}
+
+impl GitRepoStatus {
+    /// Run `git status` for `dir`. Returns `None` if `dir` isn't inside a work tree.
+    pub(crate) fn load(dir: &Path) -> Result<Option<Self>> {
+        let output = git(dir, &["rev-parse", "--show-toplevel"])?;
+        if !output.status.success() {
+            return Ok(None);
+        }
+        let root = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim_end());
+        let prefix = dir.canonicalize()?
+            .strip_prefix(&root)
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let output = git_output(dir, &["status", "--porcelain=v2", "--branch", "-z", "--ignored", "--", "."])?;
+        let mut status = parse_status(&output);
+        status.root = root;
+        status.prefix = prefix;
+        Ok(Some(status))
+    }
+
+    /// The status of `name` in the directory the status was computed for.
+    pub(crate) fn status_of(&self, name: &str, is_dir: bool) -> GitStatus {
+        let path = self.prefix.join(name);
+        let mut status = GitStatus::default();
+        for (entry_path, entry_status) in &self.entries {
+            if path.starts_with(entry_path) {
+                // the entry itself, or inside an untracked/ignored directory
+                status.merge(entry_status);
+            } else if is_dir && entry_path.starts_with(&path) {
+                // something below the directory; a directory holding ignored files isn't ignored
+                status.merge(&GitStatus { ignored: false, ..*entry_status });
+            }
+        }
+        status
+    }
+
+    /// The changes below the directory `name`, relative to it. Ignored files are left out.
+    pub(crate) fn changes_below(&self, name: &str) -> Vec<(PathBuf, GitStatus)> {
+        let path = self.prefix.join(name);
+        self.entries
+            .iter()
+            .filter(|(_, status)| !status.ignored)
+            .filter_map(|(entry_path, status)| {
+                let rel_path = entry_path.strip_prefix(&path).ok()?;
+                (!rel_path.as_os_str().is_empty()).then(|| (rel_path.to_path_buf(), *status))
+            })
+            .collect()
+    }
+}
+
+/// What the working tree is compared to in a diff.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffBase {
+    Head,
+    Index,
+}
+
+impl fmt::Display for DiffBase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let output = match self {
+            DiffBase::Head => "HEAD",
+            DiffBase::Index => "index",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+/// The unified diff of a file in the working tree against `base`. Empty if it is unchanged.
+pub(crate) fn diff(path: &Path, base: DiffBase) -> Result<Vec<String>> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        bail!("no file to diff: {}", path.display());
+    };
+    let name = name.to_string_lossy();
+    let mut args = vec!["diff", "--no-color", "--no-ext-diff"];
+    if base == DiffBase::Head {
+        args.push("HEAD");
+    }
+    args.extend(["--", &name]);
+    let output = git_output(dir, &args)?;
+    Ok(String::from_utf8_lossy(&output).lines().map(str::to_string).collect())
+}
+
+/// Add the changes of the named entries of `dir` (including removals) to the index.
+pub(crate) fn stage(dir: &Path, names: &[String]) -> Result<()> {
+    git_with_paths(dir, &["add", "--all"], names).map(|_| ())
+}
+
+/// Reset the index entries of the named entries of `dir` to HEAD.
+pub(crate) fn unstage(dir: &Path, names: &[String]) -> Result<()> {
+    git_with_paths(dir, &["reset", "--quiet"], names).map(|_| ())
+}
+
+/// Throw away the working tree changes of the named entries of `dir`, restoring them from
+/// the index. Untracked files are left alone.
+pub(crate) fn discard(dir: &Path, names: &[String]) -> Result<()> {
+    git_with_paths(dir, &["checkout", "--quiet"], names).map(|_| ())
+}
+
+/// Commit what is staged. Returns git's summary line, e.g. `[main 1a2b3c4] message`.
+pub(crate) fn commit(dir: &Path, message: &str) -> Result<String> {
+    let output = git_output(dir, &["commit", "--quiet", "--message", message])?;
+    let output = git_output(dir, &["log", "-1", "--format=[%h] %s"]).unwrap_or(output);
+    Ok(String::from_utf8_lossy(&output).trim_end().to_string())
+}
+
+/// Color the lines of a unified diff.
+pub(crate) fn diff_line(line: String) -> Line<'static> {
+    let style = match line.as_str() {
+        l if l.starts_with("+++") || l.starts_with("---") => Style::default().add_modifier(Modifier::BOLD),
+        l if l.starts_with('+') => Style::default().fg(Color::LightGreen),
+        l if l.starts_with('-') => Style::default().fg(Color::LightRed),
+        l if l.starts_with("@@") => Style::default().fg(Color::LightCyan),
+        l if l.starts_with(' ') => Style::default(),
+        _ => Style::default().add_modifier(Modifier::BOLD),
+    };
+    Line::styled(line, style)
+}
+
+impl fmt::Display for GitRepoStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.branch)?;
+        if let Some((ahead, behind)) = self.ahead_behind {
+            if ahead > 0 {
+                write!(f, " ↑{}", ahead)?;
+            }
+            if behind > 0 {
+                write!(f, " ↓{}", behind)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run git in `dir`, without taking locks that would get in the way of other git processes.
+/// Paths are taken literally, so that names like `*.txt` don't match other files.
+fn git(dir: &Path, args: &[&str]) -> Result<std::process::Output> {
+    debug!("running git {:?} in {}", args, dir.display());
+    Ok(Command::new("git")
+        .args(["--no-optional-locks", "--literal-pathspecs"])
+        .args(args)
+        .current_dir(dir)
+        .output()?)
+}
+
+/// Run git in `dir`, returning its output. Failures carry the first line git complained with.
+fn git_output(dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = git(dir, args)?;
+    if !output.status.success() {
+        // some complaints (e.g. "nothing to commit") go to stdout
+        let complaint = [&output.stderr, &output.stdout]
+            .into_iter()
+            .map(|out| String::from_utf8_lossy(out).trim().to_string())
+            .find(|out| !out.is_empty())
+            .unwrap_or_else(|| output.status.to_string());
+        let first_line = complaint.lines().next().unwrap_or_default().to_string();
+        bail!("git {}: {}", args.first().unwrap_or(&""), first_line);
+    }
+    Ok(output.stdout)
+}
+
+/// Run a git command on the named entries of `dir`.
+fn git_with_paths(dir: &Path, args: &[&str], names: &[String]) -> Result<Vec<u8>> {
+    let mut args = args.to_vec();
+    args.push("--");
+    args.extend(names.iter().map(String::as_str));
+    git_output(dir, &args)
+}
+
+/// Parse the output of `git status --porcelain=v2 --branch -z`.
+fn parse_status(output: &[u8]) -> GitRepoStatus {
+    let mut status = GitRepoStatus::default();
+    let mut oid = String::new();
+    let mut records = output.split(|b| *b == 0).map(String::from_utf8_lossy);
+    while let Some(record) = records.next() {
+        let mut fields = record.splitn(2, ' ');
+        let kind = fields.next().unwrap_or_default();
+        let rest = fields.next().unwrap_or_default();
+        let (path, entry) = match kind {
+            "#" => {
+                if let Some(head) = rest.strip_prefix("branch.head ") {
+                    status.branch = head.to_string();
+                } else if let Some(commit) = rest.strip_prefix("branch.oid ") {
+                    oid = commit.chars().take(7).collect();
+                } else if let Some(ab) = rest.strip_prefix("branch.ab ") {
+                    let counts: Vec<usize> = ab
+                        .split(' ')
+                        .filter_map(|n| n.trim_start_matches(['+', '-']).parse().ok())
+                        .collect();
+                    if let [ahead, behind] = counts[..] {
+                        status.ahead_behind = Some((ahead, behind));
+                    }
+                }
+                continue;
+            },
+            "1" | "2" | "u" => {
+                // XY, then a number of fields before the path, which may contain spaces
+                let skip = match kind {
+                    "1" => 7,
+                    "2" => 8,
+                    _ => 9,
+                };
+                let fields: Vec<&str> = rest.splitn(skip + 1, ' ').collect();
+                let Some(path) = fields.get(skip) else {
+                    continue;
+                };
+                if kind == "2" {
+                    // the original path of a rename comes as the next record
+                    records.next();
+                }
+                let xy: Vec<char> = fields[0].chars().collect();
+                let entry = match kind {
+                    "u" => GitStatus { conflicted: true, ..Default::default() },
+                    _ => GitStatus {
+                        staged: xy.first().is_some_and(|c| *c != '.'),
+                        modified: xy.get(1).is_some_and(|c| *c != '.'),
+                        ..Default::default()
+                    },
+                };
+                (path.to_string(), entry)
+            },
+            "?" => (rest.to_string(), GitStatus { untracked: true, ..Default::default() }),
+            "!" => (rest.to_string(), GitStatus { ignored: true, ..Default::default() }),
+            _ => continue,
+        };
+        status.entries.push((PathBuf::from(path.trim_end_matches('/')), entry));
+    }
+    if status.branch == "(detached)" && !oid.is_empty() {
+        status.branch = format!("({})", oid);
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Run git in `dir` for a test, failing it if git does.
+    fn run_git(dir: &Path, args: &[&str]) {
+        let output = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    }
+
+    #[test]
+    fn test_parse_status() {
+        let output = b"# branch.oid 0123456789abcdef\0# branch.head (detached)\0\
+            1 .M N... 100644 100644 100644 aaa bbb src/main.rs\0\
+            2 R. N... 100644 100644 100644 aaa bbb R100 new name.rs\0old name.rs\0\
+            u UU N... 100644 100644 100644 100644 aaa bbb ccc conflict.rs\0\
+            ? notes/\0! target/\0";
+        let status = parse_status(output);
+        assert_eq!("(0123456)", status.branch);
+        assert_eq!(None, status.ahead_behind);
+        assert_eq!(5, status.entries.len());
+        assert_eq!("M", status.status_of("src/main.rs", false).to_string());
+        assert_eq!("M", status.status_of("src", true).to_string());
+        assert_eq!("S", status.status_of("new name.rs", false).to_string());
+        assert_eq!(GitStatus::default(), status.status_of("old name.rs", false));
+        assert_eq!("U", status.status_of("conflict.rs", false).to_string());
+        assert_eq!("?", status.status_of("notes", true).to_string());
+        assert_eq!("I", status.status_of("target/debug", true).to_string());
+    }
+
+    #[test]
+    fn test_load() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        assert_eq!(None, GitRepoStatus::load(&root).unwrap());
+
+        run_git(&root, &["init", "-q", "-b", "main"]);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.join("src/lib.rs"), "").unwrap();
+        fs::write(root.join("src/main.rs"), "").unwrap();
+        run_git(&root, &["add", "."]);
+        run_git(&root, &["commit", "-q", "-m", "initial"]);
+        fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(root.join("src/new.rs"), "").unwrap();
+        fs::write(root.join("src/debug.log"), "").unwrap();
+
+        let status = GitRepoStatus::load(&root).unwrap().unwrap();
+        assert_eq!("main", status.to_string());
+        assert_eq!("M?", status.status_of("src", true).to_string());
+
+        let status = GitRepoStatus::load(&root.join("src")).unwrap().unwrap();
+        assert_eq!(root, status.root);
+        assert_eq!("M", status.status_of("main.rs", false).to_string());
+        assert_eq!(GitStatus::default(), status.status_of("lib.rs", false));
+        assert_eq!("?", status.status_of("new.rs", false).to_string());
+        assert_eq!("I", status.status_of("debug.log", false).to_string());
+    }
+
+    #[test]
+    fn test_diff() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        run_git(&root, &["init", "-q"]);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {\n}\n").unwrap();
+        run_git(&root, &["add", "."]);
+        run_git(&root, &["commit", "-q", "-m", "initial"]);
+        fs::write(root.join("src/main.rs"), "fn main() {\n    run();\n}\n").unwrap();
+        fs::write(root.join("src/new.rs"), "").unwrap();
+
+        let main_rs = root.join("src/main.rs");
+        let lines = diff(&main_rs, DiffBase::Head).unwrap();
+        assert!(lines.contains(&"+    run();".to_string()));
+        assert_eq!(lines, diff(&main_rs, DiffBase::Index).unwrap());
+        run_git(&root, &["add", "src/main.rs"]);
+        assert!(diff(&main_rs, DiffBase::Index).unwrap().is_empty());
+        assert_eq!(lines, diff(&main_rs, DiffBase::Head).unwrap());
+
+        let status = GitRepoStatus::load(&root).unwrap().unwrap();
+        let changes: Vec<String> = status.changes_below("src")
+            .iter()
+            .map(|(path, status)| format!("{} {}", status, path.display()))
+            .collect();
+        assert_eq!(vec!["S main.rs", "? new.rs"], changes);
+    }
+
+    #[test]
+    fn test_stage_unstage_discard_commit() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        run_git(&root, &["init", "-q"]);
+        run_git(&root, &["config", "user.name", "test"]);
+        run_git(&root, &["config", "user.email", "test@example.com"]);
+        fs::write(root.join("a.txt"), "a\n").unwrap();
+        fs::write(root.join("b.txt"), "b\n").unwrap();
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<String>>();
+        let status_of = |name: &str| GitRepoStatus::load(&root).unwrap().unwrap().status_of(name, false).to_string();
+
+        // staging and unstaging work before the first commit, too
+        stage(&root, &names(&["a.txt", "b.txt"])).unwrap();
+        assert_eq!("S", status_of("a.txt"));
+        unstage(&root, &names(&["b.txt"])).unwrap();
+        assert_eq!("?", status_of("b.txt"));
+        assert!(commit(&root, "").is_err());
+        let summary = commit(&root, "add a").unwrap();
+        assert!(summary.ends_with("] add a"), "{}", summary);
+        assert_eq!("", status_of("a.txt"));
+        assert!(commit(&root, "nothing staged").is_err());
+
+        fs::write(root.join("a.txt"), "changed\n").unwrap();
+        assert_eq!("M", status_of("a.txt"));
+        discard(&root, &names(&["a.txt"])).unwrap();
+        assert_eq!("a\n", fs::read_to_string(root.join("a.txt")).unwrap());
+        assert!(discard(&root, &names(&["b.txt"])).is_err());
+        assert!(root.join("b.txt").exists());
+    }
+
+    #[test]
+    fn test_literal_paths() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        run_git(&root, &["init", "-q"]);
+        run_git(&root, &["config", "user.name", "test"]);
+        run_git(&root, &["config", "user.email", "test@example.com"]);
+        for name in ["*.txt", "a.txt", "[ab].c", "a.c"] {
+            fs::write(root.join(name), "old\n").unwrap();
+        }
+        run_git(&root, &["add", "."]);
+        run_git(&root, &["commit", "-q", "-m", "initial"]);
+        for name in ["*.txt", "a.txt", "[ab].c", "a.c"] {
+            fs::write(root.join(name), "new\n").unwrap();
+        }
+        let status_of = |name: &str| GitRepoStatus::load(&root).unwrap().unwrap().status_of(name, false).to_string();
+
+        // only the files with these very names are touched
+        discard(&root, &["*.txt".to_string()]).unwrap();
+        assert_eq!("old\n", fs::read_to_string(root.join("*.txt")).unwrap());
+        assert_eq!("new\n", fs::read_to_string(root.join("a.txt")).unwrap());
+        stage(&root, &["[ab].c".to_string()]).unwrap();
+        assert_eq!("S", status_of("[ab].c"));
+        assert_eq!("M", status_of("a.c"));
+        assert_eq!(1, diff(&root.join("[ab].c"), DiffBase::Head).unwrap().iter().filter(|l| l.starts_with("diff --git")).count());
+    }
+}
@@ -0,0 +1,81 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+
+/// Bytes shown per row of a hex dump.
+pub const HEX_ROW_BYTES: usize = 16;
+
+/// The number of rows needed to dump `len` bytes.
+pub(crate) fn hex_rows(len: u64) -> usize {
+    len.div_ceil(HEX_ROW_BYTES as u64) as usize
+}
+
+/// Dump up to `count` rows, starting at row `first_row`, reading only those bytes.
+pub(crate) fn hex_dump(reader: &mut (impl Read + Seek), first_row: usize, count: usize) -> io::Result<Vec<Line<'static>>> {
+    let offset = (first_row * HEX_ROW_BYTES) as u64;
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut buf = Vec::with_capacity(count * HEX_ROW_BYTES);
+    reader.take((count * HEX_ROW_BYTES) as u64).read_to_end(&mut buf)?;
+    Ok(buf
+        .chunks(HEX_ROW_BYTES)
+        .enumerate()
+        .map(|(i, bytes)| hex_line(offset + (i * HEX_ROW_BYTES) as u64, bytes))
+        .collect())
+}
+
+/// `00000010  48 65 6c 6c 6f 0a 00 00  ...  |Hello...|`, with printable chars highlighted.
+fn hex_line(offset: u64, bytes: &[u8]) -> Line<'static> {
+    let mut spans = vec![Span::styled(format!("{:08x}  ", offset), Style::default().fg(Color::DarkGray))];
+    for i in 0..HEX_ROW_BYTES {
+        let gap = if i == HEX_ROW_BYTES / 2 - 1 { "  " } else { " " };
+        match bytes.get(i) {
+            Some(byte) => spans.push(Span::styled(format!("{:02x}{}", byte, gap), byte_style(*byte))),
+            None => spans.push(Span::raw(format!("  {}", gap))),
+        }
+    }
+    spans.push(Span::raw(" |"));
+    for byte in bytes {
+        let c = if is_printable(*byte) { *byte as char } else { '.' };
+        spans.push(Span::styled(c.to_string(), byte_style(*byte)));
+    }
+    spans.push(Span::raw("|"));
+    Line::from(spans)
+}
+
+fn is_printable(byte: u8) -> bool {
+    byte.is_ascii_graphic() || byte == b' '
+}
+
+fn byte_style(byte: u8) -> Style {
+    match byte {
+        0 => Style::default().fg(Color::DarkGray),
+        _ if is_printable(byte) => Style::default().fg(Color::Cyan),
+        _ => Style::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_hex_dump() {
+        let data: Vec<u8> = (0..40u8).chain(b"Hello".iter().copied()).collect();
+        assert_eq!(3, hex_rows(data.len() as u64));
+        assert_eq!(0, hex_rows(0));
+
+        let mut reader = Cursor::new(data);
+        let lines = hex_dump(&mut reader, 2, 10).unwrap();
+        assert_eq!(1, lines.len());
+        assert_eq!("00000020  20 21 22 23 24 25 26 27  48 65 6c 6c 6f           | !\"#$%&'Hello|",
+                   lines[0].to_string());
+
+        let lines = hex_dump(&mut reader, 0, 1).unwrap();
+        assert_eq!("00000000  00 01 02 03 04 05 06 07  08 09 0a 0b 0c 0d 0e 0f  |................|",
+                   lines[0].to_string());
+        assert_eq!(Some(Color::DarkGray), lines[0].spans[1].style.fg);
+        assert!(hex_dump(&mut reader, 5, 1).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use log::debug;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{self, FontStyle, HighlightState, ScopeSelectors, StyleModifier, Theme, ThemeItem, ThemeSettings};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+
+/// How many highlighted files are remembered.
+const MAX_CACHED_FILES: usize = 200;
+/// Lines longer than this aren't highlighted; syntaxes can be very slow on minified files.
+const MAX_HIGHLIGHT_LINE_LEN: usize = 1000;
+
+/// Theme colors are stored as `r` = ANSI color index, `a` = 0, so that they are drawn with the
+/// terminal's palette like the rest of the UI.
+const THEME: &[(&str, u8, FontStyle)] = &[
+    ("comment", 8, FontStyle::ITALIC),
+    ("string, constant.character", 2, FontStyle::empty()),
+    ("constant.numeric, constant.language, constant.other", 13, FontStyle::empty()),
+    ("keyword, storage, variable.language", 12, FontStyle::empty()),
+    ("entity.name.function, support.function, meta.macro", 14, FontStyle::empty()),
+    ("entity.name.type, entity.name.class, entity.name.struct, entity.name.enum, support.type, support.class", 11, FontStyle::empty()),
+    ("entity.name.tag, meta.tag.sgml", 12, FontStyle::empty()),
+    ("entity.other.attribute-name, meta.mapping.key, support.type.property-name", 14, FontStyle::empty()),
+    ("markup.heading, entity.name.section", 11, FontStyle::BOLD),
+    ("markup.bold", 7, FontStyle::BOLD),
+    ("markup.italic", 7, FontStyle::ITALIC),
+    ("markup.inserted", 10, FontStyle::empty()),
+    ("markup.deleted, invalid", 9, FontStyle::empty()),
+];
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_nonewlines)
+}
+
+fn theme() -> &'static Theme {
+    static THEME_CELL: OnceLock<Theme> = OnceLock::new();
+    THEME_CELL.get_or_init(|| Theme {
+        name: Some("lsls".to_string()),
+        settings: ThemeSettings {
+            // alpha 1 means "the terminal's default color"
+            foreground: Some(highlighting::Color { r: 0, g: 0, b: 0, a: 1 }),
+            ..Default::default()
+        },
+        scopes: THEME
+            .iter()
+            .map(|(scopes, color, font_style)| ThemeItem {
+                scope: ScopeSelectors::from_str(scopes).expect("invalid scope selector in theme"),
+                style: StyleModifier {
+                    foreground: Some(highlighting::Color { r: *color, g: 0, b: 0, a: 0 }),
+                    background: None,
+                    font_style: Some(*font_style),
+                },
+            })
+            .collect(),
+        ..Default::default()
+    })
+}
+
+/// Load the syntaxes ahead of the first preview; this takes a moment.
+pub(crate) fn preload_syntaxes() {
+    let _syntaxes = syntax_set();
+    let _theme = theme();
+}
+
+/// Turns the lines of text files into styled `Line`s, remembering the result per file and
+/// modification time.
+#[derive(Debug, Default)]
+pub struct Highlighter {
+    cache: HashMap<PathBuf, (SystemTime, Vec<Line<'static>>, Option<SyntaxState>)>,
+}
+
+impl Highlighter {
+    /// Highlight `lines`, the start of the file at `path`. Files without a known syntax
+    /// come back unstyled. Also returns the state to highlight the lines that follow with,
+    /// if the file has a syntax.
+    pub(crate) fn highlight(&mut self, path: &Path, mime_type: &str, lines: Vec<String>) -> (Vec<Line<'static>>, Option<SyntaxState>) {
+        let modified = path.metadata().and_then(|m| m.modified()).ok();
+        if let (Some(modified), Some((cached_modified, cached, state))) = (modified, self.cache.get(path)) {
+            if modified == *cached_modified && cached.len() == lines.len() {
+                return (cached.clone(), state.clone());
+            }
+        }
+        let (highlighted, state) = match find_syntax(path, mime_type, lines.first().map(String::as_str)) {
+            Some(syntax) => {
+                debug!("highlighting {} as {}", path.display(), syntax.name);
+                let mut state = SyntaxState::new(syntax);
+                (state.highlight(lines), Some(state))
+            },
+            None => (lines.into_iter().map(Line::from).collect(), None),
+        };
+        if let Some(modified) = modified {
+            if self.cache.len() >= MAX_CACHED_FILES {
+                self.cache.clear();
+            }
+            self.cache.insert(path.to_path_buf(), (modified, highlighted.clone(), state.clone()));
+        }
+        (highlighted, state)
+    }
+}
+
+/// Where highlighting a file stopped, so that it can go on with the lines read next.
+#[derive(Debug, Clone)]
+pub struct SyntaxState {
+    parse: ParseState,
+    highlight: HighlightState,
+}
+
+impl SyntaxState {
+    fn new(syntax: &SyntaxReference) -> Self {
+        let highlighter = highlighting::Highlighter::new(theme());
+        Self {
+            parse: ParseState::new(syntax),
+            highlight: HighlightState::new(&highlighter, ScopeStack::new()),
+        }
+    }
+
+    /// Highlight the lines following the ones highlighted so far.
+    pub(crate) fn highlight(&mut self, lines: Vec<String>) -> Vec<Line<'static>> {
+        let mut highlighter = HighlightLines::from_state(theme(), self.highlight.clone(), self.parse.clone());
+        let mut highlighted = Vec::with_capacity(lines.len());
+        for line in lines {
+            if line.len() > MAX_HIGHLIGHT_LINE_LEN {
+                highlighted.push(Line::from(line));
+                continue;
+            }
+            match highlighter.highlight_line(&line, syntax_set()) {
+                Ok(ranges) => {
+                    let spans: Vec<Span<'static>> = ranges
+                        .into_iter()
+                        .map(|(style, text)| Span::styled(text.to_string(), to_style(style)))
+                        .collect();
+                    highlighted.push(Line::from(spans));
+                },
+                Err(e) => {
+                    debug!("unable to highlight line: {}", e);
+                    highlighted.push(Line::from(line));
+                },
+            }
+        }
+        (self.highlight, self.parse) = highlighter.state();
+        highlighted
+    }
+}
+
+/// Highlight a snippet of code in the language named by `token` (e.g. `rust` or `py`), like a
+/// fenced code block in Markdown. Returns `None` for unknown languages.
+pub(crate) fn highlight_snippet(token: &str, lines: Vec<String>) -> Option<Vec<Line<'static>>> {
+    let syntax = syntax_set().find_syntax_by_token(token)?;
+    Some(SyntaxState::new(syntax).highlight(lines))
+}
+
+/// Detect the language by extension (or file name), shebang/mode line, then mime type.
+fn find_syntax(path: &Path, mime_type: &str, first_line: Option<&str>) -> Option<&'static SyntaxReference> {
+    let syntaxes = syntax_set();
+    let by_name = || {
+        let extension = path.extension().map(|e| e.to_string_lossy());
+        let file_name = path.file_name().map(|n| n.to_string_lossy());
+        extension
+            .and_then(|e| syntaxes.find_syntax_by_extension(&e))
+            .or_else(|| file_name.and_then(|n| syntaxes.find_syntax_by_extension(&n)))
+    };
+    let by_first_line = || first_line.and_then(|line| syntaxes.find_syntax_by_first_line(line));
+    let by_mime = || {
+        let subtype = mime_type.rsplit('/').next()?.trim_start_matches("x-");
+        let token = match subtype {
+            "shellscript" => "sh",
+            "javascript" => "js",
+            _ => subtype,
+        };
+        syntaxes.find_syntax_by_token(token)
+    };
+    by_name()
+        .or_else(by_first_line)
+        .or_else(by_mime)
+        .filter(|syntax| syntax.name != "Plain Text")
+}
+
+fn to_style(style: highlighting::Style) -> Style {
+    let mut converted = Style::default();
+    if style.foreground.a == 0 {
+        converted = converted.fg(Color::Indexed(style.foreground.r));
+    }
+    if style.font_style.contains(FontStyle::BOLD) {
+        converted = converted.add_modifier(Modifier::BOLD);
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        converted = converted.add_modifier(Modifier::ITALIC);
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        converted = converted.add_modifier(Modifier::UNDERLINED);
+    }
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_find_syntax() {
+        let name = |path: &str, mime: &str, first_line: Option<&str>| {
+            find_syntax(Path::new(path), mime, first_line).map(|s| s.name.as_str())
+        };
+        assert_eq!(Some("Rust"), name("main.rs", "text/x-rust", None));
+        assert_eq!(Some("Makefile"), name("Makefile", "text/plain", None));
+        assert_eq!(Some("Python"), name("tool", "text/plain", Some("#!/usr/bin/env python3")));
+        assert_eq!(Some("Bourne Again Shell (bash)"), name("run", "application/x-shellscript", Some("set -e")));
+        assert_eq!(Some("JSON"), name("data", "application/json", Some("{")));
+        assert_eq!(None, name("notes.txt", "text/plain", Some("hello")));
+    }
+
+    #[test]
+    fn test_highlight() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("main.rs");
+        fs::write(&path, "// hello\nfn main() {}\n").unwrap();
+        let lines = vec!["// hello".to_string(), "fn main() {}".to_string()];
+
+        let mut highlighter = Highlighter::default();
+        let (highlighted, state) = highlighter.highlight(&path, "text/x-rust", lines.clone());
+        assert!(state.is_some());
+        assert_eq!(2, highlighted.len());
+        assert_eq!("// hello", highlighted[0].to_string());
+        assert_eq!(Some(Color::Indexed(8)), highlighted[0].spans[0].style.fg);
+        let keyword = highlighted[1].spans.iter().find(|s| s.content == "fn").unwrap();
+        assert_eq!(Some(Color::Indexed(12)), keyword.style.fg);
+        // cached
+        assert_eq!(highlighted, highlighter.highlight(&path, "text/x-rust", lines).0);
+
+        let path = tmp.path().join("notes.txt");
+        fs::write(&path, "hello\n").unwrap();
+        let (plain, state) = highlighter.highlight(&path, "text/plain", vec!["hello".to_string()]);
+        assert_eq!(vec![Line::from("hello")], plain);
+        assert!(state.is_none());
+    }
+
+    #[test]
+    fn test_highlight_continued() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("main.rs");
+        fs::write(&path, "/* a\nb */\nfn main() {}\n").unwrap();
+
+        // the comment goes on in the second chunk
+        let (_, state) = Highlighter::default().highlight(&path, "text/x-rust", vec!["/* a".to_string()]);
+        let rest = state.unwrap().highlight(vec!["b */".to_string(), "fn main() {}".to_string()]);
+        assert_eq!(Some(Color::Indexed(8)), rest[0].spans[0].style.fg);
+        let keyword = rest[1].spans.iter().find(|s| s.content == "fn").unwrap();
+        assert_eq!(Some(Color::Indexed(12)), keyword.style.fg);
+    }
+}
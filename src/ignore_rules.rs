@@ -0,0 +1,150 @@
+use std::fmt;
+use std::path::Path;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use log::debug;
+
+/// What to do with entries matched by `.gitignore` / `.ignore` rules.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum IgnoreMode {
+    #[default]
+    Show,
+    Dim,
+    Hide,
+}
+
+impl IgnoreMode {
+    pub(crate) fn next(&self) -> Self {
+        match self {
+            IgnoreMode::Show => IgnoreMode::Dim,
+            IgnoreMode::Dim => IgnoreMode::Hide,
+            IgnoreMode::Hide => IgnoreMode::Show,
+        }
+    }
+}
+
+impl fmt::Display for IgnoreMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let output = match self {
+            IgnoreMode::Show => "shown",
+            IgnoreMode::Dim => "dimmed",
+            IgnoreMode::Hide => "hidden",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+/// The ignore rules which apply to the entries of a single directory: `.ignore` and
+/// `.gitignore` files in it and its parents (up to the root of the git repository),
+/// `.git/info/exclude` and the user's global git excludes.
+#[derive(Debug, Default)]
+pub struct IgnoreRules {
+    /// From the most to the least specific; the first one with an opinion wins.
+    matchers: Vec<Gitignore>,
+}
+
+impl IgnoreRules {
+    pub(crate) fn for_dir(dir: &Path) -> Self {
+        let repo_root = dir.ancestors().find(|a| a.join(".git").exists());
+        let mut matchers = vec![];
+        for ancestor in dir.ancestors() {
+            // `.ignore` files take precedence over `.gitignore` files, and apply outside git too
+            add_matcher(&mut matchers, ancestor, &ancestor.join(".ignore"));
+            if repo_root.is_some() {
+                add_matcher(&mut matchers, ancestor, &ancestor.join(".gitignore"));
+            }
+            if Some(ancestor) == repo_root {
+                break;
+            }
+        }
+        if let Some(repo_root) = repo_root {
+            add_matcher(&mut matchers, repo_root, &repo_root.join(".git/info/exclude"));
+            let (global, err) = GitignoreBuilder::new(repo_root).build_global();
+            if let Some(err) = err {
+                debug!("problem reading global git excludes: {}", err);
+            }
+            if !global.is_empty() {
+                matchers.push(global);
+            }
+        }
+        Self { matchers }
+    }
+
+    /// Whether `path` (which must be inside the directory the rules were made for) is ignored.
+    pub(crate) fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for matcher in &self.matchers {
+            match matcher.matched_path_or_any_parents(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {},
+            }
+        }
+        false
+    }
+}
+
+/// Add the rules in `path` (if it exists), with patterns relative to `root`.
+fn add_matcher(matchers: &mut Vec<Gitignore>, root: &Path, path: &Path) {
+    if !path.is_file() {
+        return;
+    }
+    let mut builder = GitignoreBuilder::new(root);
+    if let Some(err) = builder.add(path) {
+        debug!("problem reading {}: {}", path.display(), err);
+    }
+    match builder.build() {
+        Ok(matcher) if !matcher.is_empty() => matchers.push(matcher),
+        Ok(_) => {},
+        Err(e) => debug!("unable to use {}: {}", path.display(), e),
+    }
+}
+
+/// Whether a file name holds ignore rules, so a change to it invalidates `IgnoreRules`.
+pub(crate) fn is_ignore_file(name: &str) -> bool {
+    name == ".gitignore" || name == ".ignore"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_ignore_rules() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join(".git/info")).unwrap();
+        fs::create_dir_all(root.join("src/gen")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join(".git/info/exclude"), "secret.txt\n").unwrap();
+        fs::write(root.join("src/.gitignore"), "gen/\n!keep.log\n").unwrap();
+        fs::write(root.join("src/.ignore"), "*.tmp\n").unwrap();
+
+        let rules = IgnoreRules::for_dir(root);
+        assert!(rules.is_ignored(&root.join("target"), true));
+        assert!(!rules.is_ignored(&root.join("target"), false));
+        assert!(rules.is_ignored(&root.join("debug.log"), false));
+        assert!(rules.is_ignored(&root.join("secret.txt"), false));
+        assert!(!rules.is_ignored(&root.join("src"), true));
+
+        let src = root.join("src");
+        let rules = IgnoreRules::for_dir(&src);
+        assert!(rules.is_ignored(&src.join("gen"), true));
+        assert!(rules.is_ignored(&src.join("a.log"), false));
+        // the deeper whitelist wins
+        assert!(!rules.is_ignored(&src.join("keep.log"), false));
+        assert!(rules.is_ignored(&src.join("x.tmp"), false));
+    }
+
+    #[test]
+    fn test_outside_git() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join(".gitignore"), "*.log\n").unwrap();
+        fs::write(tmp.path().join(".ignore"), "*.tmp\n").unwrap();
+        let rules = IgnoreRules::for_dir(tmp.path());
+        // `.gitignore` only applies inside a git repository
+        assert!(!rules.is_ignored(&tmp.path().join("a.log"), false));
+        assert!(rules.is_ignored(&tmp.path().join("a.tmp"), false));
+    }
+}
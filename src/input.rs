@@ -0,0 +1,257 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+pub enum InputResult {
+    /// Still editing.
+    Pending,
+    Submit(String),
+    Cancel,
+}
+
+/// A single-line text input with readline-style editing and history.
+#[derive(Debug, Clone, Default)]
+pub struct TextInput {
+    pub value: String,
+    /// Cursor position, in chars (not bytes).
+    pub cursor: usize,
+    history: Vec<String>,
+    /// Index into `history` while browsing it with up/down.
+    history_idx: Option<usize>,
+    /// The value which was being edited before browsing the history.
+    draft: String,
+}
+
+impl TextInput {
+    pub(crate) fn new(value: &str, history: Vec<String>) -> Self {
+        Self {
+            value: value.to_string(),
+            cursor: value.chars().count(),
+            history,
+            history_idx: None,
+            draft: String::new(),
+        }
+    }
+
+    pub(crate) fn handle_key(&mut self, key: KeyEvent) -> InputResult {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Enter => return InputResult::Submit(self.value.clone()),
+            KeyCode::Esc => return InputResult::Cancel,
+            KeyCode::Char('c') | KeyCode::Char('g') if ctrl => return InputResult::Cancel,
+            KeyCode::Left => self.move_left(),
+            KeyCode::Right => self.move_right(),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.len(),
+            KeyCode::Up => self.history_previous(),
+            KeyCode::Down => self.history_next(),
+            KeyCode::Backspace => self.delete_before(),
+            KeyCode::Delete => self.delete_at(),
+            KeyCode::Char('a') if ctrl => self.cursor = 0,
+            KeyCode::Char('e') if ctrl => self.cursor = self.len(),
+            KeyCode::Char('b') if ctrl => self.move_left(),
+            KeyCode::Char('f') if ctrl => self.move_right(),
+            KeyCode::Char('b') if alt => self.cursor = self.word_start(),
+            KeyCode::Char('f') if alt => self.cursor = self.word_end(),
+            KeyCode::Char('h') if ctrl => self.delete_before(),
+            KeyCode::Char('d') if ctrl => self.delete_at(),
+            KeyCode::Char('w') if ctrl => {
+                let start = self.word_start();
+                self.remove_range(start, self.cursor);
+                self.cursor = start;
+            },
+            KeyCode::Char('u') if ctrl => {
+                self.remove_range(0, self.cursor);
+                self.cursor = 0;
+            },
+            KeyCode::Char('k') if ctrl => self.remove_range(self.cursor, self.len()),
+            KeyCode::Char('p') if ctrl => self.history_previous(),
+            KeyCode::Char('n') if ctrl => self.history_next(),
+            KeyCode::Char(c) if !ctrl && !alt => {
+                let idx = self.byte_idx(self.cursor);
+                self.value.insert(idx, c);
+                self.cursor += 1;
+            },
+            _ => {}
+        }
+        InputResult::Pending
+    }
+
+    fn len(&self) -> usize {
+        self.value.chars().count()
+    }
+
+    fn byte_idx(&self, char_idx: usize) -> usize {
+        self.value
+            .char_indices()
+            .nth(char_idx)
+            .map(|(i, _)| i)
+            .unwrap_or(self.value.len())
+    }
+
+    fn remove_range(&mut self, start: usize, end: usize) {
+        let (start, end) = (self.byte_idx(start), self.byte_idx(end));
+        self.value.replace_range(start..end, "");
+    }
+
+    fn move_left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    fn move_right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.len());
+    }
+
+    fn delete_before(&mut self) {
+        if self.cursor > 0 {
+            self.remove_range(self.cursor - 1, self.cursor);
+            self.cursor -= 1;
+        }
+    }
+
+    fn delete_at(&mut self) {
+        if self.cursor < self.len() {
+            self.remove_range(self.cursor, self.cursor + 1);
+        }
+    }
+
+    /// Start of the word before the cursor.
+    fn word_start(&self) -> usize {
+        let chars: Vec<char> = self.value.chars().collect();
+        let mut i = self.cursor;
+        while i > 0 && !chars[i - 1].is_alphanumeric() {
+            i -= 1;
+        }
+        while i > 0 && chars[i - 1].is_alphanumeric() {
+            i -= 1;
+        }
+        i
+    }
+
+    /// End of the word after the cursor.
+    fn word_end(&self) -> usize {
+        let chars: Vec<char> = self.value.chars().collect();
+        let mut i = self.cursor;
+        while i < chars.len() && !chars[i].is_alphanumeric() {
+            i += 1;
+        }
+        while i < chars.len() && chars[i].is_alphanumeric() {
+            i += 1;
+        }
+        i
+    }
+
+    fn history_previous(&mut self) {
+        let idx = match self.history_idx {
+            _ if self.history.is_empty() => return,
+            None => {
+                self.draft = self.value.clone();
+                self.history.len() - 1
+            },
+            Some(idx) => idx.saturating_sub(1),
+        };
+        self.history_idx = Some(idx);
+        self.set_value(self.history[idx].clone());
+    }
+
+    fn history_next(&mut self) {
+        match self.history_idx {
+            Some(idx) if idx + 1 < self.history.len() => {
+                self.history_idx = Some(idx + 1);
+                self.set_value(self.history[idx + 1].clone());
+            },
+            Some(_) => {
+                self.history_idx = None;
+                self.set_value(self.draft.clone());
+            },
+            None => {}
+        }
+    }
+
+    fn set_value(&mut self, value: String) {
+        self.value = value;
+        self.cursor = self.len();
+    }
+
+    /// The part of the value which fits into `width` columns, keeping the cursor visible,
+    /// along with the cursor column within it.
+    pub(crate) fn visible(&self, width: usize) -> (String, usize) {
+        let width = width.max(1);
+        let skip = (self.cursor + 1).saturating_sub(width);
+        let visible: String = self.value.chars().skip(skip).take(width).collect();
+        (visible, self.cursor - skip)
+    }
+}
+
+/// Remember a submitted value, most recent last, without repeating the previous entry.
+pub(crate) fn push_history(history: &mut Vec<String>, value: &str) {
+    if !value.is_empty() && history.last().map(|s| s.as_str()) != Some(value) {
+        history.push(value.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_keys(input: &mut TextInput, keys: &[(KeyCode, KeyModifiers)]) {
+        for (code, modifiers) in keys {
+            input.handle_key(KeyEvent::new(*code, *modifiers));
+        }
+    }
+
+    fn type_str(input: &mut TextInput, s: &str) {
+        for c in s.chars() {
+            input.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+    }
+
+    #[test]
+    fn test_editing() {
+        let mut input = TextInput::new("héllo", vec![]);
+        assert_eq!(5, input.cursor);
+        type_keys(&mut input, &[(KeyCode::Char('a'), KeyModifiers::CONTROL)]);
+        type_str(&mut input, "> ");
+        assert_eq!("> héllo", input.value);
+
+        type_keys(&mut input, &[(KeyCode::Char('e'), KeyModifiers::CONTROL), (KeyCode::Left, KeyModifiers::NONE),
+                                (KeyCode::Backspace, KeyModifiers::NONE)]);
+        assert_eq!("> hélo", input.value);
+
+        type_keys(&mut input, &[(KeyCode::End, KeyModifiers::NONE)]);
+        type_str(&mut input, " world");
+        type_keys(&mut input, &[(KeyCode::Char('w'), KeyModifiers::CONTROL)]);
+        assert_eq!("> hélo ", input.value);
+
+        type_keys(&mut input, &[(KeyCode::Home, KeyModifiers::NONE), (KeyCode::Char('k'), KeyModifiers::CONTROL)]);
+        assert!(input.value.is_empty());
+
+        type_str(&mut input, "abc");
+        assert!(matches!(input.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)),
+                         InputResult::Submit(s) if s == "abc"));
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = vec![];
+        push_history(&mut history, "one");
+        push_history(&mut history, "two");
+        push_history(&mut history, "two");
+        assert_eq!(2, history.len());
+
+        let mut input = TextInput::new("", history);
+        type_str(&mut input, "draft");
+        type_keys(&mut input, &[(KeyCode::Up, KeyModifiers::NONE)]);
+        assert_eq!("two", input.value);
+        type_keys(&mut input, &[(KeyCode::Up, KeyModifiers::NONE), (KeyCode::Up, KeyModifiers::NONE)]);
+        assert_eq!("one", input.value);
+        type_keys(&mut input, &[(KeyCode::Down, KeyModifiers::NONE), (KeyCode::Down, KeyModifiers::NONE)]);
+        assert_eq!("draft", input.value);
+    }
+
+    #[test]
+    fn test_visible() {
+        let input = TextInput::new("0123456789", vec![]);
+        // one column is left for the cursor at the end
+        assert_eq!(("6789".to_string(), 4), input.visible(5));
+    }
+}
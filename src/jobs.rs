@@ -0,0 +1,584 @@
+use std::{fmt, fs, thread};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use fs_extra::dir::{get_size, TransitProcessResult};
+use log::{debug, info};
+
+use crate::archive::{create_archive, extract_members, ArchiveFormat};
+use crate::journal::Operation;
+use crate::trash::Trash;
+
+/// Minimum time between two progress notifications for the same job.
+const PROGRESS_INTERVAL_MILLIS: u64 = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobKind {
+    Copy,
+    Move,
+    Trash,
+    Delete,
+    /// Extract these members of the archive (the job's source), which are in the archive
+    /// directory `prefix`. Without members, the whole archive is extracted into a new directory,
+    /// the job's destination.
+    Extract { members: Vec<String>, prefix: String },
+    /// Create the archive at the job's destination, with the sources in it.
+    Compress,
+}
+
+impl fmt::Display for JobKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let output = match self {
+            JobKind::Copy => "Copy",
+            JobKind::Move => "Move",
+            JobKind::Trash => "Trash",
+            JobKind::Delete => "Delete",
+            JobKind::Extract { .. } => "Extract",
+            JobKind::Compress => "Compress",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Finished,
+}
+
+impl fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let output = match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Finished => "finished",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+/// A file operation, as tracked by the UI thread.
+#[derive(Debug)]
+pub struct Job {
+    pub id: usize,
+    pub kind: JobKind,
+    pub sources: Vec<PathBuf>,
+    pub dest: Option<PathBuf>,
+    pub status: JobStatus,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    pub started: Option<Instant>,
+    pub finished: Option<Instant>,
+    pub errors: Vec<String>,
+    /// The operations which revert the items done so far, for the journal.
+    pub undo: Vec<Operation>,
+}
+
+impl Job {
+    /// Average throughput in bytes per second.
+    pub fn speed(&self) -> u64 {
+        let Some(started) = self.started else {
+            return 0;
+        };
+        let elapsed = match self.finished {
+            Some(finished) => finished.duration_since(started),
+            None => started.elapsed(),
+        };
+        let millis = elapsed.as_millis() as u64;
+        if millis == 0 {
+            return 0;
+        }
+        self.bytes_done.saturating_mul(1000) / millis
+    }
+}
+
+/// Items that were yanked (copy) or cut (move), waiting to be pasted.
+#[derive(Debug, Clone)]
+pub struct Clipboard {
+    pub kind: JobKind,
+    pub paths: Vec<PathBuf>,
+}
+
+/// A request sent from the UI thread to the worker thread.
+#[derive(Debug)]
+struct JobRequest {
+    id: usize,
+    kind: JobKind,
+    sources: Vec<PathBuf>,
+    dest: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum JobEvent {
+    Started { bytes_total: u64 },
+    Progress { bytes_done: u64 },
+    Error(String),
+    /// An item is done; the operation reverts it.
+    Done(Operation),
+    Finished,
+}
+
+/// Sent from the worker thread back to the UI thread.
+#[derive(Debug)]
+pub struct JobNotification {
+    pub id: usize,
+    pub event: JobEvent,
+}
+
+/// Runs file operations one after another in a background thread. Progress is reported through
+/// a channel, which is drained by `poll()` on every tick.
+#[derive(Debug, Default)]
+pub struct JobQueue {
+    pub jobs: Vec<Job>,
+    next_id: usize,
+    job_request_tx: Option<Sender<JobRequest>>,
+    job_notify_rx: Option<Receiver<JobNotification>>,
+}
+
+impl JobQueue {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a new job. The worker thread is created on first use.
+    pub(crate) fn submit(&mut self, kind: JobKind, sources: Vec<PathBuf>, dest: Option<PathBuf>) -> usize {
+        if self.job_request_tx.is_none() {
+            let (request_tx, request_rx): (Sender<JobRequest>, Receiver<JobRequest>) = channel();
+            let (notify_tx, notify_rx): (Sender<JobNotification>, Receiver<JobNotification>) = channel();
+            self.job_request_tx = Some(request_tx);
+            self.job_notify_rx = Some(notify_rx);
+            thread::spawn(move || {
+                for request in request_rx {
+                    run_job(&request, &notify_tx);
+                }
+                debug!("job worker thread exiting");
+            });
+        }
+
+        self.next_id += 1;
+        let id = self.next_id;
+        info!("queueing job #{} {} {:?} -> {:?}", id, kind, sources, dest);
+        self.jobs.push(Job {
+            id,
+            kind: kind.clone(),
+            sources: sources.clone(),
+            dest: dest.clone(),
+            status: JobStatus::Queued,
+            bytes_done: 0,
+            bytes_total: 0,
+            started: None,
+            finished: None,
+            errors: vec![],
+            undo: vec![],
+        });
+        if let Some(tx) = &self.job_request_tx {
+            tx.send(JobRequest { id, kind, sources, dest })
+                .expect("unable to send job request");
+        }
+        id
+    }
+
+    /// Drain the notification channel. Returns the ids of the jobs which finished.
+    pub(crate) fn poll(&mut self) -> Vec<usize> {
+        let mut finished = vec![];
+        while let Some(rx) = self.job_notify_rx.as_mut() {
+            match rx.try_recv() {
+                Ok(notification) => {
+                    if matches!(notification.event, JobEvent::Finished) {
+                        finished.push(notification.id);
+                    }
+                    self.update(notification);
+                },
+                Err(TryRecvError::Empty) => {
+                    break;
+                },
+                Err(TryRecvError::Disconnected) => {
+                    break;
+                }
+            }
+        }
+        finished
+    }
+
+    fn update(&mut self, notification: JobNotification) {
+        let Some(job) = self.jobs.iter_mut().find(|j| j.id == notification.id) else {
+            return;
+        };
+        match notification.event {
+            JobEvent::Started { bytes_total } => {
+                job.status = JobStatus::Running;
+                job.bytes_total = bytes_total;
+                job.started = Some(Instant::now());
+            },
+            JobEvent::Progress { bytes_done } => {
+                job.bytes_done = bytes_done;
+            },
+            JobEvent::Error(msg) => {
+                job.errors.push(msg);
+            },
+            JobEvent::Done(operation) => {
+                job.undo.push(operation);
+            },
+            JobEvent::Finished => {
+                job.status = JobStatus::Finished;
+                job.finished = Some(Instant::now());
+            },
+        }
+    }
+
+    pub(crate) fn running_count(&self) -> usize {
+        self.jobs
+            .iter()
+            .filter(|j| j.status != JobStatus::Finished)
+            .count()
+    }
+
+    /// Forget about jobs which are no longer running.
+    pub(crate) fn clear_finished(&mut self) {
+        self.jobs.retain(|j| j.status != JobStatus::Finished);
+    }
+}
+
+/// Rate-limits progress notifications so large copies don't flood the UI thread.
+struct ProgressReporter<'a> {
+    id: usize,
+    tx: &'a Sender<JobNotification>,
+    last_sent: Instant,
+}
+
+impl ProgressReporter<'_> {
+    fn report(&mut self, bytes_done: u64) {
+        if self.last_sent.elapsed() >= Duration::from_millis(PROGRESS_INTERVAL_MILLIS) {
+            self.report_now(bytes_done);
+        }
+    }
+
+    fn report_now(&mut self, bytes_done: u64) {
+        let _result = self.tx.send(JobNotification { id: self.id, event: JobEvent::Progress { bytes_done } });
+        self.last_sent = Instant::now();
+    }
+
+    fn error(&self, msg: String) {
+        let _result = self.tx.send(JobNotification { id: self.id, event: JobEvent::Error(msg) });
+    }
+
+    fn done(&self, operation: Operation) {
+        let _result = self.tx.send(JobNotification { id: self.id, event: JobEvent::Done(operation) });
+    }
+}
+
+/// Executes a single job in the worker thread. Errors are reported per item, so one failing item
+/// does not abort the whole job.
+fn run_job(request: &JobRequest, tx: &Sender<JobNotification>) {
+    let bytes_total: u64 = request.sources
+        .iter()
+        .map(|p| item_size(p))
+        .sum();
+    let _result = tx.send(JobNotification { id: request.id, event: JobEvent::Started { bytes_total } });
+    debug!("job #{} started: {} bytes", request.id, bytes_total);
+
+    let mut reporter = ProgressReporter {
+        id: request.id,
+        tx,
+        last_sent: Instant::now(),
+    };
+    match item_step(request) {
+        Some(mut step) => {
+            let mut bytes_done: u64 = 0;
+            for source in &request.sources {
+                let size = item_size(source);
+                match step(source, bytes_done, &mut reporter) {
+                    Ok(Some(operation)) => reporter.done(operation),
+                    Ok(None) => {},
+                    Err(e) => {
+                        info!("job #{} error on {}: {}", request.id, source.display(), e);
+                        reporter.error(format!("{}: {}", source.display(), e));
+                    },
+                }
+                bytes_done += size;
+                reporter.report_now(bytes_done);
+            }
+        },
+        None => {
+            // all sources go into one archive
+            let result = match &request.dest {
+                Some(dest) => compress_items(&request.sources, dest, &mut reporter),
+                None => Err(anyhow!("no destination")),
+            };
+            if let Err(e) = result {
+                info!("job #{} error: {}", request.id, e);
+                reporter.error(e.to_string());
+            }
+            reporter.report_now(bytes_total);
+        },
+    }
+
+    let _result = tx.send(JobNotification { id: request.id, event: JobEvent::Finished });
+    debug!("job #{} finished", request.id);
+}
+
+/// What a job does with one of its sources, starting at `bytes_done` of the job's bytes. Returns
+/// the operation reverting it, if it can be reverted at all.
+type ItemStep<'a> = Box<dyn FnMut(&PathBuf, u64, &mut ProgressReporter) -> Result<Option<Operation>> + 'a>;
+
+/// The step of a job which handles its sources one by one; `None` when all of them are handled
+/// at once (stored in one archive).
+fn item_step(request: &JobRequest) -> Option<ItemStep<'_>> {
+    let dest = || request.dest.as_deref().ok_or_else(|| anyhow!("no destination"));
+    Some(match &request.kind {
+        JobKind::Copy => Box::new(move |source, bytes_done, reporter| {
+            copy_item(source, dest()?, bytes_done, reporter).map(|path| Some(Operation::Trash { path }))
+        }),
+        JobKind::Move => Box::new(move |source, bytes_done, reporter| {
+            move_item(source, dest()?, bytes_done, reporter)
+                .map(|path| (&path != source).then(|| Operation::Rename { from: path, to: source.clone() }))
+        }),
+        JobKind::Trash => Box::new(|source, _, _| {
+            Trash::new()
+                .and_then(|trash| trash.trash(source))
+                .map(|item| Some(Operation::Restore { item }))
+        }),
+        JobKind::Delete => Box::new(|source, _, _| delete_item(source).map(|_| None)),
+        JobKind::Extract { members, prefix } => Box::new(move |source, bytes_done, reporter| {
+            extract_item(source, members, prefix, dest()?, bytes_done, reporter).map(|_| None)
+        }),
+        JobKind::Compress => return None,
+    })
+}
+
+/// Size of a file, or the recursive size of a directory. Symlinks are not followed.
+fn item_size(path: &Path) -> u64 {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => get_size(path).unwrap_or(0),
+        Ok(meta) => meta.len(),
+        Err(_) => 0,
+    }
+}
+
+/// Find a name in `dest_dir` that isn't taken yet, e.g. `file (copy).txt`, `file (copy 2).txt`.
+pub(crate) fn unique_target(dest_dir: &Path, file_name: &str) -> PathBuf {
+    let target = dest_dir.join(file_name);
+    if fs::symlink_metadata(&target).is_err() {
+        return target;
+    }
+    let path = Path::new(file_name);
+    let (stem, ext) = match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => {
+            (stem.to_string_lossy().to_string(), format!(".{}", ext.to_string_lossy()))
+        },
+        _ => (file_name.to_string(), String::new()),
+    };
+    let mut n = 1;
+    loop {
+        let candidate = match n {
+            1 => format!("{} (copy){}", stem, ext),
+            _ => format!("{} (copy {}){}", stem, n, ext),
+        };
+        let target = dest_dir.join(candidate);
+        if fs::symlink_metadata(&target).is_err() {
+            return target;
+        }
+        n += 1;
+    }
+}
+
+fn file_name_of(source: &Path) -> Result<String> {
+    source.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or_else(|| anyhow!("invalid file name"))
+}
+
+/// Copy a file or directory into `dest_dir`. Returns the path of the new copy.
+fn copy_item(source: &Path, dest_dir: &Path, base: u64, reporter: &mut ProgressReporter) -> Result<PathBuf> {
+    if dest_dir.starts_with(source) {
+        return Err(anyhow!("cannot copy a directory into itself"));
+    }
+    let target = unique_target(dest_dir, &file_name_of(source)?);
+    let meta = fs::symlink_metadata(source)?;
+    if meta.is_dir() {
+        let options = fs_extra::dir::CopyOptions::new().copy_inside(true);
+        fs_extra::dir::copy_with_progress(source, &target, &options, |tp| {
+            reporter.report(base + tp.copied_bytes);
+            TransitProcessResult::ContinueOrAbort
+        })?;
+    } else {
+        let options = fs_extra::file::CopyOptions::new();
+        fs_extra::file::copy_with_progress(source, &target, &options, |tp| {
+            reporter.report(base + tp.copied_bytes);
+        })?;
+    }
+    Ok(target)
+}
+
+/// Move a file or directory into `dest_dir`. A plain rename is tried first; moving across
+/// filesystems falls back to copy + delete. Returns the new path.
+fn move_item(source: &Path, dest_dir: &Path, base: u64, reporter: &mut ProgressReporter) -> Result<PathBuf> {
+    if source.parent() == Some(dest_dir) {
+        // moving an item onto itself is a no-op
+        return Ok(source.to_path_buf());
+    }
+    if dest_dir.starts_with(source) {
+        return Err(anyhow!("cannot move a directory into itself"));
+    }
+    let target = unique_target(dest_dir, &file_name_of(source)?);
+    if fs::rename(source, &target).is_ok() {
+        return Ok(target);
+    }
+    debug!("rename failed, copying {} instead", source.display());
+    let copied = copy_item(source, dest_dir, base, reporter)?;
+    delete_item(source)?;
+    Ok(copied)
+}
+
+/// Extract members of the archive at `source` into `dest_dir`, or all of it into the new
+/// directory `dest_dir` without members. Entries which can't be extracted are reported one by
+/// one; the created items are reported as done.
+fn extract_item(source: &Path, members: &[String], prefix: &str, dest_dir: &Path, base: u64,
+                reporter: &mut ProgressReporter) -> Result<()> {
+    let format = ArchiveFormat::detect(source).ok_or_else(|| anyhow!("not a known archive format"))?;
+    if members.is_empty() {
+        fs::create_dir(dest_dir)?;
+        reporter.done(Operation::Trash { path: dest_dir.to_path_buf() });
+    }
+    let report = extract_members(source, format, members, prefix, dest_dir, &mut |bytes| reporter.report(base + bytes))?;
+    for error in report.errors {
+        reporter.error(error);
+    }
+    if !members.is_empty() {
+        for path in report.created {
+            reporter.done(Operation::Trash { path });
+        }
+    }
+    Ok(())
+}
+
+/// Create the archive `dest` with the `sources`. Items which can't be added are reported one by
+/// one; the archive is reported as done.
+fn compress_items(sources: &[PathBuf], dest: &Path, reporter: &mut ProgressReporter) -> Result<()> {
+    let format = ArchiveFormat::detect(dest).ok_or_else(|| anyhow!("not a known archive format"))?;
+    let errors = create_archive(dest, format, sources, &mut |bytes| reporter.report(bytes))?;
+    for error in errors {
+        reporter.error(error);
+    }
+    reporter.done(Operation::Trash { path: dest.to_path_buf() });
+    Ok(())
+}
+
+/// Permanently delete a file, symlink or directory.
+fn delete_item(source: &Path) -> Result<()> {
+    let meta = fs::symlink_metadata(source)?;
+    if meta.is_dir() {
+        fs::remove_dir_all(source)?;
+    } else {
+        fs::remove_file(source)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(kind: JobKind, sources: Vec<PathBuf>, dest: Option<PathBuf>) -> Vec<JobNotification> {
+        let (tx, rx) = channel();
+        run_job(&JobRequest { id: 1, kind, sources, dest }, &tx);
+        drop(tx);
+        rx.into_iter().collect()
+    }
+
+    #[test]
+    fn test_copy_file_and_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let src = tmp.path().join("src");
+        let dst = tmp.path().join("dst");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::create_dir(&dst).unwrap();
+        fs::write(src.join("a.txt"), "hello").unwrap();
+        fs::write(src.join("sub/b.txt"), "world!").unwrap();
+
+        let events = run(JobKind::Copy, vec![src.join("a.txt"), src.join("sub")], Some(dst.clone()));
+        assert!(events.iter().all(|n| !matches!(n.event, JobEvent::Error(_))));
+        assert!(matches!(events.first().unwrap().event, JobEvent::Started { bytes_total: 11 }));
+        assert!(matches!(events.last().unwrap().event, JobEvent::Finished));
+        assert_eq!("hello", fs::read_to_string(dst.join("a.txt")).unwrap());
+        assert_eq!("world!", fs::read_to_string(dst.join("sub/b.txt")).unwrap());
+        assert!(src.join("a.txt").exists());
+        let done: Vec<&JobEvent> = events.iter().map(|n| &n.event).filter(|e| matches!(e, JobEvent::Done(_))).collect();
+        assert_eq!(2, done.len());
+        assert!(matches!(done[0], JobEvent::Done(Operation::Trash { path }) if *path == dst.join("a.txt")));
+    }
+
+    #[test]
+    fn test_copy_into_same_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("a.txt"), "hello").unwrap();
+
+        run(JobKind::Copy, vec![tmp.path().join("a.txt")], Some(tmp.path().to_path_buf()));
+        assert_eq!("hello", fs::read_to_string(tmp.path().join("a (copy).txt")).unwrap());
+        assert_eq!(tmp.path().join("a (copy 2).txt"), unique_target(tmp.path(), "a.txt"));
+    }
+
+    #[test]
+    fn test_move_and_delete() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dst = tmp.path().join("dst");
+        fs::create_dir(&dst).unwrap();
+        fs::write(tmp.path().join("a.txt"), "hello").unwrap();
+
+        run(JobKind::Move, vec![tmp.path().join("a.txt")], Some(dst.clone()));
+        assert!(!tmp.path().join("a.txt").exists());
+        assert!(dst.join("a.txt").exists());
+
+        let events = run(JobKind::Delete, vec![dst.clone(), tmp.path().join("missing")], None);
+        assert!(!dst.exists());
+        let errors = events.iter().filter(|n| matches!(n.event, JobEvent::Error(_))).count();
+        assert_eq!(1, errors);
+    }
+
+    #[test]
+    fn test_compress_and_extract() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir(tmp.path().join("src")).unwrap();
+        fs::write(tmp.path().join("src/a.txt"), "hello").unwrap();
+        let archive = tmp.path().join("src.zip");
+
+        let events = run(JobKind::Compress, vec![tmp.path().join("src")], Some(archive.clone()));
+        assert!(events.iter().all(|n| !matches!(n.event, JobEvent::Error(_))));
+        assert!(events.iter().any(|n| matches!(&n.event, JobEvent::Done(Operation::Trash { path }) if *path == archive)));
+        assert!(matches!(events.iter().rev().nth(1).unwrap().event, JobEvent::Progress { bytes_done: 5 }));
+
+        let dest = tmp.path().join("out");
+        let kind = JobKind::Extract { members: vec![], prefix: String::new() };
+        let events = run(kind.clone(), vec![archive.clone()], Some(dest.clone()));
+        assert!(events.iter().all(|n| !matches!(n.event, JobEvent::Error(_))));
+        assert_eq!("hello", fs::read_to_string(dest.join("src/a.txt")).unwrap());
+        // only the new directory needs to be trashed to undo the extraction
+        let done: Vec<&JobEvent> = events.iter().map(|n| &n.event).filter(|e| matches!(e, JobEvent::Done(_))).collect();
+        assert_eq!(1, done.len());
+
+        let events = run(kind, vec![archive], Some(dest));
+        assert!(events.iter().any(|n| matches!(n.event, JobEvent::Error(_))));
+    }
+
+    #[test]
+    fn test_queue_poll() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("a.txt"), "hello").unwrap();
+
+        let mut queue = JobQueue::new();
+        let id = queue.submit(JobKind::Delete, vec![tmp.path().join("a.txt")], None);
+        let start = Instant::now();
+        while queue.running_count() > 0 && start.elapsed() < Duration::from_secs(5) {
+            queue.poll();
+            thread::sleep(Duration::from_millis(10));
+        }
+        let job = queue.jobs.iter().find(|j| j.id == id).unwrap();
+        assert_eq!(JobStatus::Finished, job.status);
+        assert_eq!(5, job.bytes_done);
+        queue.clear_finished();
+        assert!(queue.jobs.is_empty());
+    }
+}
@@ -0,0 +1,275 @@
+use std::{env, fs};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveDateTime};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::trash::{move_path, Trash, TrashItem};
+
+/// Maximum number of entries kept on each of the undo and redo stacks.
+const MAX_JOURNAL_ENTRIES: usize = 100;
+const JOURNAL_FILE_NAME: &str = "journal.json";
+
+/// A reversible file operation. Applying one returns the operation which reverts it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Operation {
+    /// Rename or move `from` to `to`.
+    Rename { from: PathBuf, to: PathBuf },
+    /// Move `path` into the trash.
+    Trash { path: PathBuf },
+    /// Move a trashed item back to where it came from.
+    Restore { item: TrashItem },
+}
+
+impl Operation {
+    pub(crate) fn apply(&self, trash: &Trash) -> Result<Operation> {
+        match self {
+            Operation::Rename { from, to } => {
+                // never clobber something which appeared in the meantime
+                if fs::symlink_metadata(to).is_ok() {
+                    return Err(anyhow!("{} already exists", to.display()));
+                }
+                move_path(from, to)?;
+                Ok(Operation::Rename { from: to.clone(), to: from.clone() })
+            },
+            Operation::Trash { path } => {
+                let item = trash.trash(path)?;
+                Ok(Operation::Restore { item })
+            },
+            Operation::Restore { item } => {
+                let path = trash.restore(item)?;
+                Ok(Operation::Trash { path })
+            },
+        }
+    }
+}
+
+/// Something the user did, e.g. a rename or a paste.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub description: String,
+    pub time: NaiveDateTime,
+    /// The operations which revert this entry, in the order the original operations were
+    /// performed. They are applied last to first.
+    pub operations: Vec<Operation>,
+}
+
+/// Undo and redo stacks of file operations, persisted as JSON so they survive a restart.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Journal {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    undo: Vec<JournalEntry>,
+    redo: Vec<JournalEntry>,
+}
+
+impl Journal {
+    /// `$XDG_STATE_HOME/lsls/journal.json`, `$XDG_STATE_HOME` defaulting to `~/.local/state`.
+    pub(crate) fn default_path() -> Option<PathBuf> {
+        let state_home = match env::var_os("XDG_STATE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => Path::new(&env::var_os("HOME")?).join(".local/state"),
+        };
+        Some(state_home.join("lsls").join(JOURNAL_FILE_NAME))
+    }
+
+    /// Load the journal stored at `path`. Without a path, the journal is kept in memory only.
+    pub(crate) fn open(path: Option<PathBuf>) -> Self {
+        let mut journal = path
+            .as_ref()
+            .and_then(|path| {
+                let contents = fs::read_to_string(path).ok()?;
+                serde_json::from_str::<Journal>(&contents)
+                    .inspect_err(|e| error!("ignoring unreadable journal {}: {}", path.display(), e))
+                    .ok()
+            })
+            .unwrap_or_default();
+        journal.path = path;
+        journal
+    }
+
+    /// Remember an operation the user performed. `operations` are the ones reverting it.
+    /// Recording something new forgets everything which could be redone.
+    pub(crate) fn record(&mut self, description: String, operations: Vec<Operation>) {
+        if operations.is_empty() {
+            return;
+        }
+        info!("journal: {}", description);
+        push_entry(&mut self.undo, JournalEntry { description, time: Local::now().naive_local(), operations });
+        self.redo.clear();
+        self.save();
+    }
+
+    /// Revert the most recent entry. Returns its description, or `None` if there is nothing to undo.
+    pub(crate) fn undo(&mut self, trash: &Trash) -> Result<Option<String>> {
+        let result = replay(&mut self.undo, &mut self.redo, trash);
+        self.save();
+        result
+    }
+
+    /// Re-apply the most recently undone entry.
+    pub(crate) fn redo(&mut self, trash: &Trash) -> Result<Option<String>> {
+        let result = replay(&mut self.redo, &mut self.undo, trash);
+        self.save();
+        result
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = serde_json::to_string_pretty(self)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                // write a temporary file first, so a crash can't leave a truncated journal behind
+                let temp = path.with_extension("json.tmp");
+                fs::write(&temp, contents)?;
+                fs::rename(&temp, path)?;
+                Ok(())
+            });
+        if let Err(e) = result {
+            error!("unable to save journal {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn push_entry(stack: &mut Vec<JournalEntry>, entry: JournalEntry) {
+    stack.push(entry);
+    if stack.len() > MAX_JOURNAL_ENTRIES {
+        stack.remove(0);
+    }
+}
+
+/// Apply the top entry of `from` and push the entry reverting that onto `to`. If an operation
+/// fails, the operations which were not applied stay on `from`, so nothing is forgotten.
+fn replay(from: &mut Vec<JournalEntry>, to: &mut Vec<JournalEntry>, trash: &Trash) -> Result<Option<String>> {
+    let Some(mut entry) = from.pop() else {
+        return Ok(None);
+    };
+    let mut reverse = vec![];
+    let mut failure = None;
+    while let Some(operation) = entry.operations.pop() {
+        match operation.apply(trash) {
+            Ok(inverse) => {
+                debug!("journal: applied {:?}", operation);
+                reverse.push(inverse);
+            },
+            Err(e) => {
+                entry.operations.push(operation);
+                failure = Some(e);
+                break;
+            },
+        }
+    }
+    let description = entry.description.clone();
+    if !reverse.is_empty() {
+        push_entry(to, JournalEntry { description: description.clone(), time: Local::now().naive_local(), operations: reverse });
+    }
+    match failure {
+        Some(e) => {
+            from.push(entry);
+            Err(anyhow!("{}: {}", description, e))
+        },
+        None => Ok(Some(description)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_redo() {
+        let tmp = tempfile::tempdir().unwrap();
+        let trash = Trash::with_home(tmp.path().join("Trash"));
+        let dir = tmp.path().canonicalize().unwrap();
+        let (a, b, c) = (dir.join("a"), dir.join("b"), dir.join("c"));
+        let mut journal = Journal::open(None);
+
+        // rename a -> b, then create c
+        fs::write(&b, "a").unwrap();
+        journal.record("rename a".to_string(), vec![Operation::Rename { from: b.clone(), to: a.clone() }]);
+        fs::write(&c, "c").unwrap();
+        journal.record("create c".to_string(), vec![Operation::Trash { path: c.clone() }]);
+
+        assert_eq!(Some("create c".to_string()), journal.undo(&trash).unwrap());
+        assert!(!c.exists());
+        assert_eq!(Some("rename a".to_string()), journal.undo(&trash).unwrap());
+        assert_eq!("a", fs::read_to_string(&a).unwrap());
+        assert_eq!(None, journal.undo(&trash).unwrap());
+
+        journal.redo(&trash).unwrap();
+        assert!(b.exists() && !a.exists());
+        journal.redo(&trash).unwrap();
+        assert_eq!("c", fs::read_to_string(&c).unwrap());
+        assert_eq!(None, journal.redo(&trash).unwrap());
+
+        // and back once more, through the trash again
+        journal.undo(&trash).unwrap();
+        assert!(!c.exists());
+        journal.redo(&trash).unwrap();
+        assert!(c.exists());
+    }
+
+    #[test]
+    fn test_record_clears_redo() {
+        let tmp = tempfile::tempdir().unwrap();
+        let trash = Trash::with_home(tmp.path().join("Trash"));
+        let (a, b) = (tmp.path().join("a"), tmp.path().join("b"));
+        fs::write(&b, "").unwrap();
+        let mut journal = Journal::open(None);
+        journal.record("rename a".to_string(), vec![Operation::Rename { from: b.clone(), to: a.clone() }]);
+        journal.undo(&trash).unwrap();
+        journal.record("something else".to_string(), vec![Operation::Trash { path: a.clone() }]);
+        assert_eq!(None, journal.redo(&trash).unwrap());
+    }
+
+    #[test]
+    fn test_failed_undo_keeps_remaining_operations() {
+        let tmp = tempfile::tempdir().unwrap();
+        let trash = Trash::with_home(tmp.path().join("Trash"));
+        let p = |name: &str| tmp.path().join(name);
+        fs::write(p("x"), "").unwrap();
+        fs::write(p("y"), "").unwrap();
+        let mut journal = Journal::open(None);
+        // a -> x and b -> y were renamed, in that order
+        journal.record("rename 2".to_string(), vec![
+            Operation::Rename { from: p("x"), to: p("a") },
+            Operation::Rename { from: p("y"), to: p("b") },
+        ]);
+        // something took the name `a`
+        fs::write(p("a"), "").unwrap();
+
+        assert!(journal.undo(&trash).is_err());
+        assert!(p("b").exists() && p("x").exists());
+        assert_eq!(1, journal.undo.len());
+        assert_eq!(1, journal.undo[0].operations.len());
+        assert_eq!(1, journal.redo.len());
+
+        fs::remove_file(p("a")).unwrap();
+        journal.undo(&trash).unwrap();
+        assert!(p("a").exists() && !p("x").exists());
+    }
+
+    #[test]
+    fn test_persistence() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("state/lsls").join(JOURNAL_FILE_NAME);
+        let mut journal = Journal::open(Some(path.clone()));
+        journal.record("rename a".to_string(), vec![Operation::Rename { from: tmp.path().join("b"), to: tmp.path().join("a") }]);
+        assert!(path.exists());
+
+        let journal = Journal::open(Some(path.clone()));
+        assert_eq!(1, journal.undo.len());
+        assert_eq!("rename a", journal.undo[0].description);
+
+        // a corrupt journal is ignored rather than fatal
+        fs::write(&path, "not json").unwrap();
+        assert!(Journal::open(Some(path)).undo.is_empty());
+    }
+}
@@ -1,12 +1,15 @@
-use std::{fs, io, io::{BufRead, BufReader}};
-use std::fs::File;
-use std::path::Path;
+use std::{env, fs, io, io::{BufRead, BufReader}};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::sync::mpsc::TryRecvError;
 use std::time::{Duration, Instant};
 
 use clap::Parser;
 use ratatui::{prelude::*, widgets::*};
 use crossterm::{
+    cursor,
     event::{self, DisableMouseCapture, EnableMouseCapture, KeyCode, KeyEvent, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
@@ -20,14 +23,61 @@ use log4rs::append::file::FileAppender;
 use log4rs::encode::pattern::PatternEncoder;
 use log4rs::config::{Appender, Root};
 use num_format::{Locale, ToFormattedString};
+use byte_unit::{Byte, UnitType};
+use archive::*;
+use bulk_rename::*;
+use compressed::*;
 use dir_list::*;
-
+use executable::*;
+use filter::*;
+use finder::*;
+use git::*;
+use highlight::*;
+use ignore_rules::*;
+use input::*;
+use jobs::*;
+use journal::*;
+use markdown::*;
+use pdf::*;
+use preview::*;
+use search::*;
+use structured::*;
+use thumbnail::*;
+use trash::*;
+
+mod archive;
+mod bulk_rename;
+mod compressed;
 mod dir_list;
+mod executable;
+mod filter;
+mod finder;
+mod git;
+mod hexdump;
+mod highlight;
+mod ignore_rules;
+mod input;
+mod jobs;
+mod journal;
+mod markdown;
+mod pdf;
+mod preview;
+mod search;
+mod structured;
+mod thumbnail;
+mod trash;
+mod walk;
+mod worker;
 
 const TICK_RATE_MILLIS: u64 = 250;
 const SNIPPET_LINES: usize = 50;
+/// Lines shown above a search hit in the preview.
+const SEARCH_CONTEXT_LINES: usize = 10;
+/// How much of an archive member is read to preview it.
+const MEMBER_PREVIEW_BYTES: u64 = 64 * 1024;
 
 // Column widths for UI
+const UI_COL_GIT: u16 = 3;
 const UI_COL_SIZE: u16 = 10;
 const UI_COL_DATE: u16 = 19;
 const UI_COL_USER: u16 = 12;
@@ -50,27 +100,140 @@ struct Args {
     #[clap(index = 1)]
     dir_name: Option<String>,
     #[arg(short, long, default_value_t = 2)]
-    log: u8
+    log: u8,
+    /// Show hidden files (dotfiles)
+    #[arg(short, long)]
+    all: bool,
 }
 
 enum PopupType {
     Help,
     Sort,
     Info,
+    Jobs,
+    Confirm(ConfirmAction),
+    Input(InputPrompt),
+    Trash,
+    BulkRename(RenamePlan),
+    Finder(Finder),
+    /// Content search results, shown in place of the file listing.
+    Search(ContentSearch),
+    /// The contents of an archive, shown in place of the file listing.
+    Archive(ArchiveView),
+}
+
+/// An action which must be confirmed by the user before it is carried out.
+enum ConfirmAction {
+    Trash(Vec<PathBuf>),
+    Delete(Vec<PathBuf>),
+    Purge(Vec<TrashItem>),
+    /// Names of entries in the current directory.
+    GitDiscard(Vec<String>),
+}
+
+/// What the text typed into an input popup is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum InputKind {
+    MarkGlob,
+    Rename,
+    Mkdir,
+    Touch,
+    Filter,
+    Search,
+    Commit,
+    PreviewSearch,
+    Compress,
+}
+
+impl InputKind {
+    fn title(&self) -> &'static str {
+        match self {
+            InputKind::MarkGlob => "Mark by glob (e.g. *.rs)",
+            InputKind::Rename => "Rename",
+            InputKind::Mkdir => "New directory",
+            InputKind::Touch => "New file",
+            InputKind::Filter => "Filter",
+            InputKind::Search => "Search file contents",
+            InputKind::Commit => "Commit message",
+            InputKind::PreviewSearch => "Search preview",
+            InputKind::Compress => "Compress into (.zip, .tar, .tar.gz, .tar.xz or .tar.zst)",
+        }
+    }
+}
+
+struct InputPrompt {
+    kind: InputKind,
+    input: TextInput,
+    /// The entry the input applies to (e.g. the one being renamed).
+    target: Option<String>,
+    /// Shown in the title while the value is unusable (e.g. an invalid filter pattern).
+    error: Option<String>,
+}
+
+impl ConfirmAction {
+    fn prompt(&self) -> String {
+        match self {
+            ConfirmAction::Trash(paths) => {
+                match paths.as_slice() {
+                    [path] => format!("Move {} to the trash?", path.display()),
+                    _ => format!("Move {} items to the trash?", paths.len()),
+                }
+            }
+            ConfirmAction::Delete(paths) => {
+                match paths.as_slice() {
+                    [path] => format!("Permanently delete {}?", path.display()),
+                    _ => format!("Permanently delete {} items?", paths.len()),
+                }
+            }
+            ConfirmAction::Purge(items) => {
+                match items.as_slice() {
+                    [item] => format!("Permanently delete {} from the trash?", item.original_path.display()),
+                    _ => format!("Permanently delete {} items from the trash?", items.len()),
+                }
+            }
+            ConfirmAction::GitDiscard(names) => {
+                match names.as_slice() {
+                    [name] => format!("Discard the uncommitted changes to {}?", name),
+                    _ => format!("Discard the uncommitted changes to {} items?", names.len()),
+                }
+            }
+        }
+    }
 }
 
 enum KeyInputResult {
     Continue,
     Stop,
+    /// Suspend the UI and open the file in the user's editor.
+    EditFile(PathBuf),
 }
 
 struct App {
     dir: String,
     dir_list: DirectoryList,
-    preview: Vec<String>,
+    preview: Preview,
     show_preview: bool,
+    /// Keys scroll the preview rather than move through the listing.
+    preview_focused: bool,
     show_popup: Option<PopupType>,
     visible_rows: u16,
+    /// Rows and columns of the preview pane showing content.
+    preview_rows: u16,
+    preview_cols: u16,
+    jobs: JobQueue,
+    clipboard: Option<Clipboard>,
+    trash_view: TrashView,
+    rename_session: Option<RenameSession>,
+    message: Option<String>,
+    input_history: HashMap<InputKind, Vec<String>>,
+    journal: Journal,
+    /// How the `/` filter interprets its pattern; kept between uses.
+    filter_mode: FilterMode,
+    /// Whether content searches take a regex rather than a literal string.
+    search_regex: bool,
+    /// Preview changed files as a diff against this.
+    preview_diff: Option<DiffBase>,
+    highlighter: Highlighter,
 }
 
 impl App {
@@ -79,10 +242,25 @@ impl App {
         let mut app = Self {
             dir: dir_name.clone(),
             dir_list: DirectoryList::new(dir_name.clone()),
-            preview: vec![],
+            preview: Preview::default(),
             show_preview: true,
+            preview_focused: false,
             show_popup: None,
             visible_rows: 10,
+            preview_rows: 10,
+            preview_cols: 40,
+            jobs: JobQueue::new(),
+            clipboard: None,
+            trash_view: TrashView::default(),
+            rename_session: None,
+            message: None,
+            input_history: HashMap::new(),
+            // tests must not touch the user's journal
+            journal: Journal::open(if cfg!(test) { None } else { Journal::default_path() }),
+            filter_mode: FilterMode::default(),
+            search_regex: false,
+            preview_diff: None,
+            highlighter: Highlighter::default(),
         };
         app.set_dir(dir_name);
         app
@@ -95,13 +273,11 @@ impl App {
 
         // this is the main loop
         loop {
-           let draw_result = terminal.draw(|f| self.draw(f))
-                .or_else(|e| {
+            let draw_result = terminal.draw(|f| self.draw(f))
+                .inspect_err(|e| {
                     error!("Unable to draw terminal: {}", e);
-                    Err(e)
                 });
-            if draw_result.is_err() {
-                let err = draw_result.unwrap_err();
+            if let Err(err) = draw_result {
                 return Err(anyhow!(err.to_string()));
             }
 
@@ -117,6 +293,12 @@ impl App {
                             return Ok(());
                         }
                         KeyInputResult::Continue => {}
+                        KeyInputResult::EditFile(path) => {
+                            suspend_terminal()?;
+                            let edit_result = run_editor(path.as_path());
+                            resume_terminal(terminal)?;
+                            self.on_editor_closed(edit_result);
+                        }
                     }
                 }
             }
@@ -166,14 +348,12 @@ impl App {
             .dir_list
             .items
             .iter()
-            .map(|item| {
-                let row= Row::from(item);
-                row
-            })
+            .map(Row::from)
             .collect();
 
-        // setup the column widths
-        let widths = &[
+        // setup the column widths; the git column is only shown inside a work tree
+        let in_git = self.dir_list.git.is_some();
+        let mut widths = vec![
             Constraint::Fill(1),                      // name
             Constraint::Length(UI_COL_SIZE),          // size
             Constraint::Length(UI_COL_DATE),          // date
@@ -183,55 +363,133 @@ impl App {
             Constraint::Length(UI_COL_GRP_MASK),      // grp (mask)
             Constraint::Length(UI_COL_OTH_MASK),      // oth (mask)
         ];
+        let mut header = vec!["Name", "Size", "Modified", "User", "Group", "Usr", "Grp", "Oth"];
+        if in_git {
+            widths.insert(1, Constraint::Length(UI_COL_GIT));
+            header.insert(1, "Git");
+        }
+        let title = match &self.dir_list.git {
+            Some(git) => format!("{} [{}]", self.dir, git),
+            None => self.dir.clone(),
+        };
 
         // create the file listing table
         let file_list_table = Table::new(rows, widths)
             .header(
-                Row::new(vec!["Name", "Size", "Modified", "User", "Group", "Usr", "Grp", "Oth"])
+                Row::new(header)
                     .style(Style::default().fg(Color::Yellow))
                     .bottom_margin(0),
             )
             .row_highlight_style(style.bg(Color::Gray).fg(Color::Black))
             .block(
                 Block::default()
-                    .title(self.dir.as_str())
+                    .title(title)
                     .borders(Borders::ALL),
             );
 
         // render the file_pane
-        frame.render_stateful_widget(file_list_table, file_pane, &mut self.dir_list.state);
+        match &mut self.show_popup {
+            Some(PopupType::Search(search)) => Self::draw_search_results(frame, file_pane, search),
+            Some(PopupType::Archive(view)) => Self::draw_archive_view(frame, file_pane, view),
+            _ => frame.render_stateful_widget(file_list_table, file_pane, &mut self.dir_list.state),
+        }
 
         // render the preview pane
-        if self.show_preview {
+        if let Some(preview_pane) = preview_pane {
+            let preview = &self.preview;
+            let mut title = preview.title.clone().unwrap_or("Preview".to_string());
+            if self.preview_focused || preview.scroll > 0 {
+                title.push_str(format!(" - line {} of {}{}", preview.scroll + 1, preview.line_count(),
+                                       if preview.has_more() { "+" } else { "" }).as_str());
+            }
+            if let Some(search) = &preview.search {
+                title.push_str(format!(" - /{}", search.pattern).as_str());
+            }
+            let border_style = match self.preview_focused {
+                true => Style::default().fg(Color::Yellow),
+                false => Style::default(),
+            };
             let preview_block = Block::default()
                 .borders(Borders::ALL)
+                .border_style(border_style)
                 .style(Style::default())
-                .title("Preview");
-
-            let preview_lines: Vec<Line> = self.preview
-                .iter()
-                .map(|s| Line::from(s.as_str()))
-                .collect();
-            let preview_text = Text::from(preview_lines);
-
-            let mut preview_paragraph = Paragraph::new(preview_text.clone())
-                .style(Style::default())
-                .block(preview_block)
-                .alignment(Alignment::Left);
+                .title(title);
+
+            // tables take a row for their header
+            let header_rows = preview.table.is_some() as u16;
+            let content_rows = preview_pane.height.saturating_sub(2 + header_rows);
+            if let Some(table) = &preview.table {
+                Self::draw_preview_table(frame, preview_pane, preview_block, preview, table, content_rows);
+            } else {
+                let highlight = Style::default().bg(Color::LightYellow).fg(Color::Black);
+                let gutter_style = Style::default().fg(Color::DarkGray);
+                let first_line = preview.first_line.filter(|_| preview.line_numbers);
+                let gutter_width = first_line
+                    .map(|first| (first + preview.line_count()).to_string().len())
+                    .unwrap_or_default();
+                // only the visible lines, the preview may have been scrolled far into a big file
+                let preview_lines: Vec<Line> = preview
+                    .visible_lines(preview_pane.height.saturating_sub(2) as usize)
+                    .map(|(i, line)| {
+                        let mut line = line.clone();
+                        if let Some(first) = first_line {
+                            let gutter = Span::styled(format!("{:>gutter_width$} ", first + i), gutter_style);
+                            line.spans.insert(0, gutter);
+                        }
+                        match preview.highlight == Some(i) {
+                            true => line.style(highlight),
+                            false => line,
+                        }
+                    })
+                    .collect();
+
+                let mut preview_paragraph = Paragraph::new(preview_lines)
+                    .style(Style::default())
+                    .block(preview_block)
+                    .alignment(Alignment::Left);
+
+                // wrap single-lined files
+                if preview.wrap || preview.line_count() <= 1 {
+                    let preview_wrap = Wrap { trim: false };
+                    preview_paragraph = preview_paragraph.wrap(preview_wrap);
+                }
 
-            // wrap single-lined files
-            if preview_text.lines.len() <= 1 {
-                let preview_wrap = Wrap { trim: false };
-                preview_paragraph = preview_paragraph.wrap(preview_wrap);
+                frame.render_widget(preview_paragraph, preview_pane);
             }
-
-            frame.render_widget(preview_paragraph, preview_pane.unwrap());
+            self.preview_rows = content_rows;
+            self.preview_cols = preview_pane.width.saturating_sub(2);
         }
 
         // render the status_pane
-        let status_text = format!("{} of {} items",
+        let mut status_text = format!("{} of {} items",
                                   (self.dir_list.state.selected().unwrap_or(0) + 1).to_formatted_string(&Locale::en),
                                   self.dir_list.items.len().to_formatted_string(&Locale::en));
+        if let Some(clipboard) = &self.clipboard {
+            let verb = match clipboard.kind {
+                JobKind::Move => "cut",
+                _ => "yanked",
+            };
+            status_text.push_str(format!(" | {} {}", clipboard.paths.len(), verb).as_str());
+        }
+        let marked = self.dir_list.marked_entries();
+        if !marked.is_empty() {
+            let size: u64 = marked.iter().filter_map(|e| e.size).sum();
+            let size = Byte::from(size).get_appropriate_unit(UnitType::Decimal);
+            status_text.push_str(format!(" | {} marked ({size:.1})", marked.len().to_formatted_string(&Locale::en)).as_str());
+        }
+        let running_jobs = self.jobs.running_count();
+        if running_jobs > 0 {
+            status_text.push_str(format!(" | {} job(s) running", running_jobs).as_str());
+        }
+        if let Some(filter) = &self.dir_list.filter {
+            status_text.push_str(format!(" | filter {}", filter).as_str());
+        }
+        if self.dir_list.ignore_mode != IgnoreMode::Show {
+            status_text.push_str(format!(" | ignored {}", self.dir_list.ignore_mode).as_str());
+        }
+        if let Some(message) = &self.message {
+            status_text.push_str(format!(" | {}", message).as_str());
+        }
         frame.render_widget(
             Paragraph::new(status_text)
                 .block(Block::default()
@@ -243,6 +501,14 @@ impl App {
             Some(PopupType::Sort) => self.show_popup_sort(frame),
             Some(PopupType::Help) => self.show_popup_help(frame),
             Some(PopupType::Info) => self.show_popup_info(frame),
+            Some(PopupType::Jobs) => self.show_popup_jobs(frame),
+            Some(PopupType::Confirm(_)) => self.show_popup_confirm(frame),
+            Some(PopupType::Input(_)) => self.show_popup_input(frame),
+            Some(PopupType::Trash) => self.show_popup_trash(frame),
+            Some(PopupType::BulkRename(_)) => self.show_popup_bulk_rename(frame),
+            Some(PopupType::Finder(_)) => self.show_popup_finder(frame),
+            // drawn in place of the file listing
+            Some(PopupType::Search(_)) | Some(PopupType::Archive(_)) => {},
             None => {},
         }
 
@@ -265,6 +531,262 @@ impl App {
         KeyInputResult::Continue
     }
 
+    fn handle_input_jobs_popup(&mut self, key: KeyEvent) -> KeyInputResult {
+        match key.code {
+            KeyCode::Char('c') => {
+                self.jobs.clear_finished();
+            },
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.show_popup = None;
+            },
+            _ => {}
+        }
+        KeyInputResult::Continue
+    }
+
+    fn handle_input_confirm_popup(&mut self, key: KeyEvent) -> KeyInputResult {
+        let Some(PopupType::Confirm(action)) = self.show_popup.take() else {
+            return KeyInputResult::Continue;
+        };
+        let confirmed = matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter);
+        match action {
+            ConfirmAction::Trash(paths) if confirmed => {
+                self.jobs.submit(JobKind::Trash, paths, None);
+            }
+            ConfirmAction::Delete(paths) if confirmed => {
+                self.jobs.submit(JobKind::Delete, paths, None);
+            }
+            ConfirmAction::GitDiscard(names) if confirmed => {
+                let result = discard(Path::new(&self.dir), &names);
+                self.on_git_changed(result.map(|_| format!("discarded changes to {} item(s)", names.len())));
+            }
+            ConfirmAction::Purge(items) => {
+                if confirmed {
+                    self.purge_trash_items(&items);
+                }
+                // back to the trash view
+                self.show_popup = Some(PopupType::Trash);
+            }
+            _ => {}
+        }
+        KeyInputResult::Continue
+    }
+
+    fn handle_input_bulk_rename_popup(&mut self, key: KeyEvent) -> KeyInputResult {
+        let Some(PopupType::BulkRename(plan)) = self.show_popup.take() else {
+            return KeyInputResult::Continue;
+        };
+        if !plan.problems.is_empty() {
+            return KeyInputResult::Continue;
+        }
+        if let KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter = key.code {
+            self.message = match plan.apply() {
+                Ok(()) => {
+                    let operations = plan.steps
+                        .iter()
+                        .map(|step| Operation::Rename { from: plan.dir.join(&step.to), to: plan.dir.join(&step.from) })
+                        .collect();
+                    self.journal.record(format!("rename {} item(s)", plan.renames.len()), operations);
+                    Some(format!("renamed {} item(s)", plan.renames.len()))
+                },
+                Err(e) => Some(format!("rename failed: {}", e)),
+            };
+            self.dir_list.clear_marks();
+            self.dir_list.refresh().ok();
+        }
+        KeyInputResult::Continue
+    }
+
+    fn handle_input_finder_popup(&mut self, key: KeyEvent) -> KeyInputResult {
+        let Some(PopupType::Finder(finder)) = &mut self.show_popup else {
+            return KeyInputResult::Continue;
+        };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Down => finder.select_next(),
+            KeyCode::Char('n') | KeyCode::Char('j') if ctrl => finder.select_next(),
+            KeyCode::Up => finder.select_previous(),
+            KeyCode::Char('p') | KeyCode::Char('k') if ctrl => finder.select_previous(),
+            _ => {
+                match finder.input.handle_key(key) {
+                    InputResult::Pending => finder.update(),
+                    InputResult::Cancel => self.show_popup = None,
+                    InputResult::Submit(_) => {
+                        let path = finder.selected_path();
+                        self.show_popup = None;
+                        if let Some(path) = path {
+                            self.navigate_to_path(&path);
+                        }
+                    },
+                }
+            },
+        }
+        KeyInputResult::Continue
+    }
+
+    fn handle_input_search_popup(&mut self, key: KeyEvent) -> KeyInputResult {
+        let Some(PopupType::Search(search)) = &mut self.show_popup else {
+            return KeyInputResult::Continue;
+        };
+        let last = search.hits.len().saturating_sub(1);
+        let selected = search.state.selected();
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.show_popup = None;
+                self.restore_listing_preview();
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Enter => {
+                let path = search.selected_hit().map(|hit| search.root.join(&hit.path));
+                self.show_popup = None;
+                self.restore_listing_preview();
+                if let Some(path) = path {
+                    self.navigate_to_path(&path);
+                }
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Down | KeyCode::Char('j') => search.state.select(selected.map(|i| (i + 1).min(last))),
+            KeyCode::Up | KeyCode::Char('k') => search.state.select(selected.map(|i| i.saturating_sub(1))),
+            KeyCode::Char('g') => search.state.select(selected.map(|_| 0)),
+            KeyCode::Char('G') => search.state.select(selected.map(|_| last)),
+            _ => {},
+        }
+        if search.state.selected() != selected {
+            self.load_search_preview();
+        }
+        KeyInputResult::Continue
+    }
+
+    fn handle_input_archive_popup(&mut self, key: KeyEvent) -> KeyInputResult {
+        let Some(PopupType::Archive(view)) = &mut self.show_popup else {
+            return KeyInputResult::Continue;
+        };
+        let selected = (view.prefix.clone(), view.state.selected());
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.show_popup = None;
+                self.restore_listing_preview();
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Down | KeyCode::Char('j') => view.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => view.select_previous(),
+            KeyCode::Char('g') => view.state.select(selected.1.map(|_| 0)),
+            KeyCode::Char('G') => view.state.select(selected.1.map(|_| view.items.len() - 1)),
+            KeyCode::Enter | KeyCode::Char('l') | KeyCode::Right => {
+                view.enter();
+            },
+            // leaving the top directory of the archive closes it
+            KeyCode::Left | KeyCode::Char('h') if !view.leave() => {
+                self.show_popup = None;
+                self.restore_listing_preview();
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('m') => {
+                view.toggle_mark();
+                view.select_next();
+            },
+            KeyCode::Char('e') => {
+                let members = view.selected_members();
+                if !members.is_empty() {
+                    let kind = JobKind::Extract { members, prefix: view.prefix.clone() };
+                    view.marked.clear();
+                    let (archive, dest) = (view.path.clone(), PathBuf::from(&self.dir));
+                    self.jobs.submit(kind, vec![archive], Some(dest));
+                }
+            },
+            _ => {},
+        }
+        if let Some(PopupType::Archive(view)) = &self.show_popup {
+            if (view.prefix.clone(), view.state.selected()) != selected {
+                self.load_archive_preview();
+            }
+        }
+        KeyInputResult::Continue
+    }
+
+    fn handle_input_trash_popup(&mut self, key: KeyEvent) -> KeyInputResult {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.show_popup = None;
+            },
+            KeyCode::Down | KeyCode::Char('j') => {
+                if let Some(i) = self.trash_view.state.selected() {
+                    if i + 1 < self.trash_view.items.len() {
+                        self.trash_view.state.select(Some(i + 1));
+                    }
+                }
+            },
+            KeyCode::Up | KeyCode::Char('k') => {
+                if let Some(i) = self.trash_view.state.selected() {
+                    self.trash_view.state.select(Some(i.saturating_sub(1)));
+                }
+            },
+            KeyCode::Char('r') => {
+                if let Some(item) = self.trash_view.selected_item().cloned() {
+                    self.trash_view.message = match Trash::new().and_then(|trash| trash.restore(&item)) {
+                        Ok(path) => {
+                            // undoing it trashes the item again
+                            self.journal.record(format!("restore {}", path.display()), vec![Operation::Trash { path: path.clone() }]);
+                            Some(format!("restored {}", path.display()))
+                        },
+                        Err(e) => Some(format!("unable to restore: {}", e)),
+                    };
+                    self.reload_trash_view();
+                }
+            },
+            KeyCode::Char('D') => {
+                if let Some(item) = self.trash_view.selected_item().cloned() {
+                    self.show_popup = Some(PopupType::Confirm(ConfirmAction::Purge(vec![item])));
+                }
+            },
+            KeyCode::Char('E') if !self.trash_view.items.is_empty() => {
+                let items = self.trash_view.items.clone();
+                self.show_popup = Some(PopupType::Confirm(ConfirmAction::Purge(items)));
+            },
+            _ => {}
+        }
+        KeyInputResult::Continue
+    }
+
+    fn handle_input_input_popup(&mut self, key: KeyEvent) -> KeyInputResult {
+        let Some(PopupType::Input(prompt)) = &mut self.show_popup else {
+            return KeyInputResult::Continue;
+        };
+        if prompt.kind == InputKind::Filter && key.code == KeyCode::Tab {
+            self.filter_mode = self.filter_mode.next();
+            self.update_filter();
+            return KeyInputResult::Continue;
+        }
+        if matches!(prompt.kind, InputKind::Search | InputKind::PreviewSearch) && key.code == KeyCode::Tab {
+            self.search_regex = !self.search_regex;
+            return KeyInputResult::Continue;
+        }
+        match prompt.input.handle_key(key) {
+            InputResult::Pending if prompt.kind == InputKind::Filter => {
+                // filter as you type
+                self.update_filter();
+            },
+            InputResult::Pending => {},
+            InputResult::Cancel => {
+                if prompt.kind == InputKind::Filter {
+                    self.dir_list.set_filter(None);
+                    self.load_preview().ok();
+                }
+                self.show_popup = None;
+            },
+            InputResult::Submit(value) => {
+                let (kind, target) = (prompt.kind, prompt.target.take());
+                self.show_popup = None;
+                push_history(self.input_history.entry(kind).or_default(), value.as_str());
+                if let Err(e) = self.on_input_submitted(kind, target, value.as_str()) {
+                    self.message = Some(e.to_string());
+                }
+                self.load_preview().ok();
+            },
+        }
+        KeyInputResult::Continue
+    }
+
     fn handle_input_sort_popup(&mut self, key: KeyEvent) -> KeyInputResult {
         match key.code {
             KeyCode::Char('q') | KeyCode::Esc => {
@@ -276,13 +798,13 @@ impl App {
                         .selected()
                         .expect("unable to identify selected sort_by item")
                     ].clone();
-                debug!("sort_by changed to {}", self.dir_list.sort_by.to_string());
+                debug!("sort_by changed to {}", self.dir_list.sort_by);
                 self.dir_list.sort();
                 self.show_popup = None;
             },
             KeyCode::Down | KeyCode::Char('j') => {
                 if let Some(mut selected_idx) = self.dir_list.sort_by_list_state.selected() {
-                    selected_idx = selected_idx + 1;
+                    selected_idx += 1;
                     if selected_idx < SortBy::all().len() {
                         self.dir_list.sort_by_list_state.select(Some(selected_idx));
                     }
@@ -293,7 +815,7 @@ impl App {
             KeyCode::Up | KeyCode::Char('k') => {
                 if let Some(mut selected_idx) = self.dir_list.sort_by_list_state.selected() {
                     if selected_idx > 0 {
-                        selected_idx = selected_idx - 1;
+                        selected_idx -= 1;
                         self.dir_list.sort_by_list_state.select(Some(selected_idx));
                     }
                 } else {
@@ -305,6 +827,68 @@ impl App {
         KeyInputResult::Continue
     }
 
+    /// Keys while the preview pane has the focus.
+    fn handle_input_preview(&mut self, key: KeyEvent) -> KeyInputResult {
+        let height = self.preview_rows as usize;
+        let page = height.max(1) as isize;
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Tab | KeyCode::Char('q') => {
+                self.preview_focused = false;
+            },
+            KeyCode::Esc => {
+                match self.preview.search {
+                    Some(_) => {
+                        self.preview.search = None;
+                        self.preview.highlight = None;
+                    },
+                    None => self.preview_focused = false,
+                }
+            },
+            KeyCode::Char('f') | KeyCode::Char('d') if ctrl => {
+                let rows = if key.code == KeyCode::Char('d') { page / 2 } else { page };
+                self.preview.scroll_by(rows, height);
+            },
+            KeyCode::Char('b') | KeyCode::Char('u') if ctrl => {
+                let rows = if key.code == KeyCode::Char('u') { page / 2 } else { page };
+                self.preview.scroll_by(-rows, height);
+            },
+            KeyCode::Down | KeyCode::Char('j') => self.preview.scroll_by(1, height),
+            KeyCode::Up | KeyCode::Char('k') => self.preview.scroll_by(-1, height),
+            KeyCode::PageDown | KeyCode::Char(' ') => self.preview.scroll_by(page, height),
+            KeyCode::PageUp => self.preview.scroll_by(-page, height),
+            KeyCode::Home | KeyCode::Char('g') => self.preview.scroll_to_top(height),
+            KeyCode::End | KeyCode::Char('G') => self.preview.scroll_to_bottom(height),
+            KeyCode::Char('w') => {
+                self.preview.wrap = !self.preview.wrap;
+            },
+            KeyCode::Char('#') => {
+                self.preview.line_numbers = !self.preview.line_numbers;
+            },
+            KeyCode::Char('r') => {
+                self.preview.render_markdown = !self.preview.render_markdown;
+                // reloaded, if it is a Markdown file
+                self.dir_list.selection_changed = true;
+                self.load_preview().ok();
+            },
+            KeyCode::Char('/') => {
+                let pattern = self.preview.search.as_ref().map(|s| s.pattern.clone()).unwrap_or_default();
+                self.open_input(InputKind::PreviewSearch, pattern.as_str(), None);
+            },
+            KeyCode::Char('n') | KeyCode::Char('N') => {
+                let forward = key.code == KeyCode::Char('n');
+                if let Some(search) = &self.preview.search {
+                    let pattern = search.pattern.clone();
+                    if !self.preview.next_match(forward, height) {
+                        self.message = Some(format!("pattern not found: {}", pattern));
+                    }
+                }
+            },
+            _ => {},
+        }
+        KeyInputResult::Continue
+    }
+
     fn handle_input(&mut self, key_event: KeyEvent) -> KeyInputResult {
         match self.show_popup {
             Some(PopupType::Sort) => {
@@ -316,9 +900,40 @@ impl App {
             Some(PopupType::Info) => {
                 return self.handle_input_info_popup(key_event);
             },
+            Some(PopupType::Jobs) => {
+                return self.handle_input_jobs_popup(key_event);
+            },
+            Some(PopupType::Confirm(_)) => {
+                return self.handle_input_confirm_popup(key_event);
+            },
+            Some(PopupType::Input(_)) => {
+                return self.handle_input_input_popup(key_event);
+            },
+            Some(PopupType::Trash) => {
+                return self.handle_input_trash_popup(key_event);
+            },
+            Some(PopupType::BulkRename(_)) => {
+                return self.handle_input_bulk_rename_popup(key_event);
+            },
+            Some(PopupType::Finder(_)) => {
+                return self.handle_input_finder_popup(key_event);
+            },
+            Some(PopupType::Search(_)) => {
+                return self.handle_input_search_popup(key_event);
+            },
+            Some(PopupType::Archive(_)) => {
+                return self.handle_input_archive_popup(key_event);
+            },
             None => {},
         }
 
+        // messages are only shown until the next key press
+        self.message = None;
+
+        if self.preview_focused {
+            return self.handle_input_preview(key_event);
+        }
+
         match key_event.code {
             KeyCode::Char('q') => {
                 // QUIT -> bail
@@ -327,6 +942,16 @@ impl App {
             KeyCode::Char('p') => {
                 self.show_preview = !self.show_preview;
             },
+            KeyCode::Tab if self.show_preview => {
+                self.preview_focused = true;
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('.') => {
+                self.set_show_hidden(!self.dir_list.show_hidden);
+            },
+            KeyCode::Char('I') => {
+                self.set_ignore_mode(self.dir_list.ignore_mode.next());
+            },
             KeyCode::Char('s') => {
                 self.show_popup = Some(PopupType::Sort);
                 return KeyInputResult::Continue;
@@ -339,10 +964,165 @@ impl App {
                 self.show_popup = Some(PopupType::Info);
                 return KeyInputResult::Continue;
             },
+            KeyCode::Char('J') => {
+                self.show_popup = Some(PopupType::Jobs);
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('y') => {
+                let paths = self.selected_paths();
+                if !paths.is_empty() {
+                    self.clipboard = Some(Clipboard { kind: JobKind::Copy, paths });
+                }
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('x') => {
+                let paths = self.selected_paths();
+                if !paths.is_empty() {
+                    self.clipboard = Some(Clipboard { kind: JobKind::Move, paths });
+                }
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('P') => {
+                if let Some(clipboard) = self.clipboard.clone() {
+                    let dest = PathBuf::from(&self.dir);
+                    self.jobs.submit(clipboard.kind.clone(), clipboard.paths, Some(dest));
+                    // cut items can only be pasted once
+                    if clipboard.kind == JobKind::Move {
+                        self.clipboard = None;
+                    }
+                }
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('d') => {
+                let paths = self.selected_paths();
+                if !paths.is_empty() {
+                    self.show_popup = Some(PopupType::Confirm(ConfirmAction::Trash(paths)));
+                }
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('D') => {
+                let paths = self.selected_paths();
+                if !paths.is_empty() {
+                    self.show_popup = Some(PopupType::Confirm(ConfirmAction::Delete(paths)));
+                }
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('R') => {
+                let mut names: Vec<String> = self.dir_list.marked_entries()
+                    .iter()
+                    .map(|e| e.name.clone())
+                    .collect();
+                if names.is_empty() {
+                    names = self.dir_list.items
+                        .iter()
+                        .filter_map(|item| match item {
+                            DirectoryListItem::Entry(e) => Some(e.name.clone()),
+                            DirectoryListItem::ParentDir(_) => None,
+                        })
+                        .collect();
+                }
+                match RenameSession::start(Path::new(&self.dir), names) {
+                    Ok(session) => {
+                        let file = session.file.clone();
+                        self.rename_session = Some(session);
+                        return KeyInputResult::EditFile(file);
+                    }
+                    Err(e) => {
+                        self.message = Some(e.to_string());
+                    }
+                }
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('Z') => {
+                let paths = self.selected_paths();
+                let name = match paths.as_slice() {
+                    [path] => path.file_name().map(|n| n.to_string_lossy().to_string()),
+                    _ => Path::new(&self.dir).file_name().map(|n| n.to_string_lossy().to_string()),
+                };
+                if !paths.is_empty() {
+                    let name = format!("{}.tar.gz", name.unwrap_or_else(|| "archive".to_string()));
+                    self.open_input(InputKind::Compress, name.as_str(), None);
+                }
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('E') => {
+                if let Some(DirectoryListItem::Entry(entry)) = self.dir_list.get_selected_item() {
+                    let path = Path::new(&self.dir).join(&entry.name);
+                    match ArchiveFormat::stem(&path) {
+                        Some(stem) if !entry.file_type.is_dir() => {
+                            // into a new directory next to the archive
+                            let dest = unique_target(Path::new(&self.dir), &stem);
+                            let kind = JobKind::Extract { members: vec![], prefix: String::new() };
+                            self.jobs.submit(kind, vec![path], Some(dest));
+                        },
+                        _ => self.message = Some(format!("{} is not an archive", entry.name)),
+                    }
+                }
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('T') => {
+                self.trash_view.message = None;
+                self.reload_trash_view();
+                self.show_popup = Some(PopupType::Trash);
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('m') => {
+                self.dir_list.toggle_mark();
+                self.dir_list.select_next();
+            },
+            KeyCode::Char('a') => {
+                self.dir_list.mark_all();
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('v') => {
+                self.dir_list.invert_marks();
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('*') => {
+                self.open_input(InputKind::MarkGlob, "", None);
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('c') => {
+                if let Some(DirectoryListItem::Entry(entry)) = self.dir_list.get_selected_item() {
+                    let name = entry.name.clone();
+                    self.open_input(InputKind::Rename, name.as_str(), Some(name.clone()));
+                }
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('N') => {
+                self.open_input(InputKind::Mkdir, "", None);
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('n') => {
+                self.open_input(InputKind::Touch, "", None);
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Esc => {
+                self.dir_list.clear_marks();
+                self.dir_list.set_filter(None);
+            },
+            KeyCode::Char('f') if key_event.modifiers.is_empty() => {
+                let finder = Finder::new(
+                    PathBuf::from(&self.dir),
+                    self.dir_list.show_hidden,
+                    self.dir_list.ignore_mode != IgnoreMode::Show,
+                );
+                self.show_popup = Some(PopupType::Finder(finder));
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('S') => {
+                self.open_input(InputKind::Search, "", None);
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('/') => {
+                let pattern = self.dir_list.filter.as_ref().map(|f| f.pattern.clone()).unwrap_or_default();
+                self.open_input(InputKind::Filter, pattern.as_str(), None);
+                return KeyInputResult::Continue;
+            },
             KeyCode::Enter | KeyCode::Char(' ') | KeyCode::Char('l') => {
                 // get the selected item
-                if let Some(sel_idx) = self.dir_list.state.selected() {
-                    match &self.dir_list.items[sel_idx] {
+                if let Some(item) = self.dir_list.get_selected_item() {
+                    match item {
                         DirectoryListItem::ParentDir(chg_dir) => {
                             self.navigate_to_relative_directory(chg_dir.to_owned()).ok();
                         }
@@ -350,11 +1130,18 @@ impl App {
                             if entry.file_type.is_dir() {
                                 self.navigate_to_relative_directory(entry.name.clone()).ok();
                             } else {
-                                // open the file (unless `l` key was pressed -- that would just be weird)
-                                if key_event.code != KeyCode::Char('l') {
-                                    let cur_path = Path::new(&self.dir);
-                                    let entry_path = cur_path.join(&entry.name);
-                                    let _result = opener::open(entry_path.as_path());
+                                let entry_path = Path::new(&self.dir).join(&entry.name);
+                                if let Some(format) = ArchiveFormat::detect(&entry_path) {
+                                    // browse archives like directories
+                                    self.show_popup = Some(PopupType::Archive(ArchiveView::open(&entry_path, format)));
+                                    self.load_archive_preview();
+                                } else if key_event.code != KeyCode::Char('l') {
+                                    // open the file(s) (unless `l` key was pressed -- that would just be weird)
+                                    for entry_path in self.selected_paths() {
+                                        if !entry_path.is_dir() {
+                                            let _result = opener::open(entry_path.as_path());
+                                        }
+                                    }
                                 }
                             }
                         }
@@ -378,32 +1165,60 @@ impl App {
             KeyCode::Char('G') => {
                 self.dir_list.select_last();
             },
+            KeyCode::Char('r') if key_event.modifiers == KeyModifiers::CONTROL => {
+                self.replay_journal(false);
+            },
             KeyCode::Char('r') => {
                 self.dir_list.refresh().ok();
             },
-            KeyCode::Char('f') => {
-                match key_event.modifiers {
-                    KeyModifiers::CONTROL => {
-                        self.dir_list.scroll_forward(self.visible_rows);
-                    },
-                    _ => {}
+            KeyCode::Char('=') => {
+                self.toggle_diff_preview();
+            },
+            KeyCode::Char('A') => {
+                let names = self.git_entries(|s| s.modified || s.untracked || s.conflicted, "nothing to stage");
+                if !names.is_empty() {
+                    let result = stage(Path::new(&self.dir), &names);
+                    self.on_git_changed(result.map(|_| format!("staged {} item(s)", names.len())));
                 }
             },
-            KeyCode::Char('b') => {
-                match key_event.modifiers {
-                    KeyModifiers::CONTROL => {
-                        self.dir_list.scroll_backward(self.visible_rows);
-                    },
-                    _ => {}
+            KeyCode::Char('U') => {
+                let names = self.git_entries(|s| s.staged, "nothing to unstage");
+                if !names.is_empty() {
+                    let result = unstage(Path::new(&self.dir), &names);
+                    self.on_git_changed(result.map(|_| format!("unstaged {} item(s)", names.len())));
                 }
-            }
-            _ => {}
-        }
-
-        self.load_preview().ok();
-
-        KeyInputResult::Continue
-    }
+            },
+            KeyCode::Char('X') => {
+                let names = self.git_entries(|s| s.modified, "nothing to discard");
+                if !names.is_empty() {
+                    self.show_popup = Some(PopupType::Confirm(ConfirmAction::GitDiscard(names)));
+                }
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('C') => {
+                if self.dir_list.git.is_none() {
+                    self.message = Some("not inside a git work tree".to_string());
+                } else {
+                    self.open_input(InputKind::Commit, "", None);
+                }
+                return KeyInputResult::Continue;
+            },
+            KeyCode::Char('u') => {
+                self.replay_journal(true);
+            },
+            KeyCode::Char('f') if key_event.modifiers == KeyModifiers::CONTROL => {
+                self.dir_list.scroll_forward(self.visible_rows);
+            },
+            KeyCode::Char('b') if key_event.modifiers == KeyModifiers::CONTROL => {
+                self.dir_list.scroll_backward(self.visible_rows);
+            }
+            _ => {}
+        }
+
+        self.load_preview().ok();
+
+        KeyInputResult::Continue
+    }
 
     fn show_popup_sort(&mut self, frame: &mut Frame) {
         let default_style = Style::default();
@@ -419,7 +1234,7 @@ impl App {
             .highlight_style(default_style.bg(Color::Gray).fg(Color::Black))
             .block(Block::default().title("Sort By").borders(Borders::ALL));
         let area = centered_rect(30, 50, frame.area());
-        if self.dir_list.sort_by_list_state.selected() == None {
+        if self.dir_list.sort_by_list_state.selected().is_none() {
             self.dir_list.sort_by_list_state.select(Some(0));
         }
         frame.render_widget(Clear, area);
@@ -432,34 +1247,52 @@ impl App {
             return
         };
         let mut info_vec : Vec<String> = vec![];
-        match item {
-            DirectoryListItem::Entry(e) => {
-                info_vec.push(format!("Name: {}", e.name));
-                if e.file_type.is_file() {
-                    info_vec.push("Type: File".to_string());
-                    let cur_path = Path::new(&self.dir);
-                    let entry_path = cur_path.join(&e.name);
-                    info_vec.push(format!("Path: {}", entry_path.display()));
-                    if let Some(mime_type) = tree_magic_mini::from_filepath(entry_path.as_path()) {
-                        info_vec.push(format!("Mime: {}", mime_type));
+        let marked = self.dir_list.marked_entries();
+        if !marked.is_empty() {
+            let size: u64 = marked.iter().filter_map(|e| e.size).sum();
+            info_vec.push(format!("Marked: {} items", marked.len().to_formatted_string(&Locale::en)));
+            info_vec.push(format!("Size: {} bytes", size.to_formatted_string(&Locale::en)));
+            for e in marked {
+                info_vec.push(format!("  {}", e.name));
+            }
+        } else {
+            match item {
+                DirectoryListItem::Entry(e) => {
+                    info_vec.push(format!("Name: {}", e.name));
+                    if e.file_type.is_file() {
+                        info_vec.push("Type: File".to_string());
+                        let cur_path = Path::new(&self.dir);
+                        let entry_path = cur_path.join(&e.name);
+                        info_vec.push(format!("Path: {}", entry_path.display()));
+                        if let Some(mime_type) = tree_magic_mini::from_filepath(entry_path.as_path()) {
+                            info_vec.push(format!("Mime: {}", mime_type));
+                        }
+                        let size = e.size.unwrap_or(0);
+                        info_vec.push(format!("Size: {} bytes", size.to_formatted_string(&Locale::en)));
+                        let created_dt: DateTime<Local> = e.created.into();
+                        let created_dt_str = created_dt.format("%Y-%m-%d %T").to_string();
+                        info_vec.push(format!("Created: {}", created_dt_str));
+                        let modified_dt: DateTime<Local> = e.modified.into();
+                        let modified_dt_str = modified_dt.format("%Y-%m-%d %T").to_string();
+                        info_vec.push(format!("Modified: {}", modified_dt_str));
+                        if is_elf(&entry_path) {
+                            match read_elf(&entry_path) {
+                                Ok(elf) => info_vec.extend(elf.summary()),
+                                Err(e) => info_vec.push(format!("ELF: {}", e)),
+                            }
+                        } else if let Some(interpreter) = shebang(&entry_path) {
+                            info_vec.push(format!("Interpreter: {}", interpreter));
+                        }
+                    } else if e.file_type.is_dir() {
+                        info_vec.push("Type: Directory".to_string());
+                    } else if e.file_type.is_symlink() {
+                        info_vec.push("Type: Symlink".to_string());
                     }
-                    let size = e.size.unwrap_or(0);
-                    info_vec.push(format!("Size: {} bytes", size.to_formatted_string(&Locale::en)));
-                    let created_dt: DateTime<Local> = e.created.into();
-                    let created_dt_str = created_dt.format("%Y-%m-%d %T").to_string();
-                    info_vec.push(format!("Created: {}", created_dt_str));
-                    let modified_dt: DateTime<Local> = e.modified.into();
-                    let modified_dt_str = modified_dt.format("%Y-%m-%d %T").to_string();
-                    info_vec.push(format!("Modified: {}", modified_dt_str));
-                } else if e.file_type.is_dir() {
-                    info_vec.push("Type: Directory".to_string());
-                } else if e.file_type.is_symlink() {
-                    info_vec.push("Type: Symlink".to_string());
-                }
-            },
-            DirectoryListItem::ParentDir(_) => {
-                self.show_popup = None;
-                return;
+                },
+                DirectoryListItem::ParentDir(_) => {
+                    self.show_popup = None;
+                    return;
+                }
             }
         }
         let info_items: Vec<ListItem> = info_vec
@@ -476,14 +1309,292 @@ impl App {
         frame.render_widget(info_list, area);
     }
 
+    fn show_popup_jobs(&self, frame: &mut Frame) {
+        let job_items: Vec<ListItem> = self.jobs.jobs
+            .iter()
+            .map(|job| {
+                let mut lines: Vec<Line> = vec![];
+                let dest = match &job.dest {
+                    Some(dest) => format!(" -> {}", dest.display()),
+                    None => "".to_string(),
+                };
+                lines.push(Line::from(format!("#{} {} {} item(s){} [{}]",
+                                              job.id, job.kind, job.sources.len(), dest, job.status)));
+                let percent = match job.bytes_total {
+                    0 => 100,
+                    total => job.bytes_done.saturating_mul(100) / total,
+                };
+                let bytes_done = Byte::from(job.bytes_done).get_appropriate_unit(UnitType::Decimal);
+                let bytes_total = Byte::from(job.bytes_total).get_appropriate_unit(UnitType::Decimal);
+                let speed = Byte::from(job.speed()).get_appropriate_unit(UnitType::Decimal);
+                lines.push(Line::from(format!("    {bytes_done:.1} / {bytes_total:.1} ({percent}%) {speed:.1}/s")));
+                for error in &job.errors {
+                    lines.push(Line::styled(format!("    error: {}", error), Style::default().fg(Color::Red)));
+                }
+                ListItem::new(lines)
+            })
+            .collect();
+        let title = "Jobs (c: clear finished)";
+        let job_list = List::new(job_items)
+            .block(Block::default().title(title).borders(Borders::ALL));
+        let area = centered_rect(60, 50, frame.area());
+        frame.render_widget(Clear, area);
+        frame.render_widget(job_list, area);
+    }
+
+    fn show_popup_input(&self, frame: &mut Frame) {
+        let Some(PopupType::Input(prompt)) = &self.show_popup else {
+            return;
+        };
+        let area = centered_rect(50, 20, frame.area());
+        let area = Rect { height: area.height.min(3), ..area };
+        let inner_width = area.width.saturating_sub(2) as usize;
+        let (visible, cursor) = prompt.input.visible(inner_width);
+        let mut title = match prompt.kind {
+            InputKind::Filter => format!("{} ({}, <TAB> to change)", prompt.kind.title(), self.filter_mode),
+            InputKind::Search | InputKind::PreviewSearch => format!("{} ({}, <TAB> to change)", prompt.kind.title(),
+                                         if self.search_regex { "regex" } else { "literal" }),
+            _ => prompt.kind.title().to_string(),
+        };
+        if let Some(error) = &prompt.error {
+            title.push_str(format!(" - {}", error).as_str());
+        }
+        let input_paragraph = Paragraph::new(visible)
+            .block(Block::default().title(title).borders(Borders::ALL));
+        frame.render_widget(Clear, area);
+        frame.render_widget(input_paragraph, area);
+        frame.set_cursor_position((area.x + 1 + cursor as u16, area.y + 1));
+    }
+
+    fn show_popup_finder(&self, frame: &mut Frame) {
+        let Some(PopupType::Finder(finder)) = &self.show_popup else {
+            return;
+        };
+        let area = centered_rect(80, 80, frame.area());
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+            .split(area);
+        let (input_area, list_area) = (layout[0], layout[1]);
+
+        let (visible, cursor) = finder.input.visible(input_area.width.saturating_sub(2) as usize);
+        let title = format!("Find files ({} of {}{})",
+                            finder.matches.len().to_formatted_string(&Locale::en),
+                            finder.candidates.len().to_formatted_string(&Locale::en),
+                            if finder.walking { ", searching..." } else { "" });
+        let input_paragraph = Paragraph::new(visible)
+            .block(Block::default().title(title).borders(Borders::ALL));
+
+        // only build the rows which fit, there may be hundreds of thousands of matches
+        let height = list_area.height.saturating_sub(2) as usize;
+        let offset = (finder.selected + 1).saturating_sub(height);
+        let highlight = Style::default().fg(Color::LightYellow).add_modifier(Modifier::BOLD);
+        let items: Vec<ListItem> = finder.matches
+            .iter()
+            .skip(offset)
+            .take(height)
+            .map(|m| {
+                let positions = finder.positions(m);
+                let spans: Vec<Span> = finder.candidates[m.index]
+                    .chars()
+                    .enumerate()
+                    .map(|(i, c)| match positions.contains(&i) {
+                        true => Span::styled(c.to_string(), highlight),
+                        false => Span::raw(c.to_string()),
+                    })
+                    .collect();
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL))
+            .highlight_style(Style::default().bg(Color::Gray).fg(Color::Black));
+        let mut state = ListState::default();
+        if !finder.matches.is_empty() {
+            state.select(Some(finder.selected - offset));
+        }
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(input_paragraph, input_area);
+        frame.render_stateful_widget(list, list_area, &mut state);
+        frame.set_cursor_position((input_area.x + 1 + cursor as u16, input_area.y + 1));
+    }
+
+    /// Draw a CSV file being previewed, with its header row.
+    fn draw_preview_table(frame: &mut Frame, area: Rect, block: Block, preview: &Preview, table: &DataTable, rows: u16) {
+        let highlight = Style::default().bg(Color::LightYellow).fg(Color::Black);
+        let header_style = Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+        let visible_rows: Vec<Row> = table.rows
+            .iter()
+            .enumerate()
+            .skip(preview.scroll)
+            .take(rows as usize)
+            .map(|(i, row)| {
+                let row = Row::new(row.clone());
+                match preview.highlight == Some(i) {
+                    true => row.style(highlight),
+                    false => row,
+                }
+            })
+            .collect();
+        let widths = table.widths.iter().map(|width| Constraint::Length(*width as u16));
+        let preview_table = Table::new(visible_rows, widths)
+            .header(Row::new(table.header.clone()).style(header_style))
+            .column_spacing(2)
+            .block(block);
+        frame.render_widget(preview_table, area);
+    }
+
+    fn draw_search_results(frame: &mut Frame, area: Rect, search: &mut ContentSearch) {
+        let location_style = Style::default().fg(Color::Magenta);
+        let items: Vec<ListItem> = search.hits
+            .iter()
+            .map(|hit| {
+                ListItem::new(Line::from(vec![
+                    Span::styled(format!("{}:{}:", hit.path.display(), hit.line_number), location_style),
+                    Span::raw(format!(" {}", hit.text)),
+                ]))
+            })
+            .collect();
+        let status = match (search.running, search.truncated) {
+            (true, _) => ", searching...",
+            (false, true) => ", stopped at the limit",
+            (false, false) => "",
+        };
+        let title = format!("Search \"{}\" - {} hit(s) in {} file(s){} (<ENTER>: go to file, <ESC>: close)",
+                            search.pattern,
+                            search.hits.len().to_formatted_string(&Locale::en),
+                            search.files_searched().to_formatted_string(&Locale::en),
+                            status);
+        let results_list = List::new(items)
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .highlight_style(Style::default().bg(Color::Gray).fg(Color::Black));
+        frame.render_stateful_widget(results_list, area, &mut search.state);
+    }
+
+    fn draw_archive_view(frame: &mut Frame, area: Rect, view: &mut ArchiveView) {
+        let widths = [
+            Constraint::Fill(1),
+            Constraint::Length(UI_COL_SIZE),
+            Constraint::Length(UI_COL_DATE),
+        ];
+        let file_name = view.path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let title = if view.is_loading() {
+            format!("{} (reading...)", file_name)
+        } else {
+            format!("{}:/{} ({} marked, e: extract to the current directory, <ESC>: close)",
+                    file_name, view.prefix, view.marked.len())
+        };
+        let table = Table::new(view.rows(), widths)
+            .header(
+                Row::new(vec!["Name", "Size", "Modified"])
+                    .style(Style::default().fg(Color::Yellow)),
+            )
+            .row_highlight_style(Style::default().bg(Color::Gray).fg(Color::Black))
+            .block(Block::default().title(title).borders(Borders::ALL));
+        frame.render_stateful_widget(table, area, &mut view.state);
+    }
+
+    fn show_popup_trash(&mut self, frame: &mut Frame) {
+        let rows: Vec<Row> = self.trash_view.items
+            .iter()
+            .map(|item| {
+                let name = item.original_path.file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let deleted = match item.deleted {
+                    Some(deleted) => deleted.format("%Y-%m-%d %T").to_string(),
+                    None => "".to_string(),
+                };
+                Row::new(vec![name, item.original_path.display().to_string(), deleted])
+            })
+            .collect();
+        let widths = &[
+            Constraint::Percentage(30),
+            Constraint::Fill(1),
+            Constraint::Length(UI_COL_DATE),
+        ];
+        let title = match &self.trash_view.message {
+            Some(message) => format!("Trash - {}", message),
+            None => "Trash (r: restore, D: delete, E: empty)".to_string(),
+        };
+        let trash_table = Table::new(rows, widths)
+            .header(
+                Row::new(vec!["Name", "Original Path", "Deleted"])
+                    .style(Style::default().fg(Color::Yellow)),
+            )
+            .row_highlight_style(Style::default().bg(Color::Gray).fg(Color::Black))
+            .block(Block::default().title(title).borders(Borders::ALL));
+        let area = centered_rect(80, 60, frame.area());
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(trash_table, area, &mut self.trash_view.state);
+    }
+
+    fn show_popup_bulk_rename(&self, frame: &mut Frame) {
+        let Some(PopupType::BulkRename(plan)) = &self.show_popup else {
+            return;
+        };
+        let mut lines: Vec<Line> = vec![];
+        for problem in &plan.problems {
+            lines.push(Line::styled(format!("error: {}", problem), Style::default().fg(Color::Red)));
+        }
+        for rename in &plan.renames {
+            lines.push(Line::from(format!("{} -> {}", rename.from, rename.to)));
+        }
+        lines.push(Line::from(""));
+        if !plan.problems.is_empty() {
+            lines.push(Line::from("nothing will be renamed -- press any key"));
+        } else if plan.renames.is_empty() {
+            lines.push(Line::from("no names were changed -- press any key"));
+        } else {
+            if plan.cycles > 0 {
+                lines.push(Line::from(format!("{} cycle(s) will be renamed through temporary names", plan.cycles)));
+            }
+            lines.push(Line::from("y -> rename, any other key -> cancel"));
+        }
+        let title = format!("Rename {} item(s)", plan.renames.len());
+        let rename_paragraph = Paragraph::new(lines)
+            .block(Block::default().title(title).borders(Borders::ALL));
+        let area = centered_rect(60, 60, frame.area());
+        frame.render_widget(Clear, area);
+        frame.render_widget(rename_paragraph, area);
+    }
+
+    fn show_popup_confirm(&self, frame: &mut Frame) {
+        let Some(PopupType::Confirm(action)) = &self.show_popup else {
+            return;
+        };
+        let confirm_paragraph = Paragraph::new(vec![
+            Line::from(action.prompt()),
+            Line::from(""),
+            Line::from("y -> yes, any other key -> no"),
+        ])
+            .wrap(Wrap { trim: false })
+            .block(Block::default().title("Confirm").borders(Borders::ALL));
+        let area = centered_rect(40, 20, frame.area());
+        frame.render_widget(Clear, area);
+        frame.render_widget(confirm_paragraph, area);
+    }
+
     // TODO: use a table (rather than a list) for better formatting of alternate key combos
     fn show_popup_help(&self, frame: &mut Frame) {
         let help_vec = vec![
             "?      -> help",
             "q      -> quit",
             "p      -> toggle preview pane",
+            "<TAB>  -> focus the preview pane (and back)",
+            "         preview: j/k scroll, ctrl+f/b page, ctrl+d/u half page,",
+            "         g/G top/bottom, w wrap, # line numbers, / search, n/N next/previous match,",
+            "         r Markdown rendered/raw",
+            "=      -> preview diffs against HEAD, the index or not",
+            "A      -> git: stage changes of item",
+            "U      -> git: unstage item",
+            "X      -> git: discard uncommitted changes of item",
+            "C      -> git: commit staged changes",
+            ".      -> toggle hidden files",
+            "I      -> show, dim or hide ignored files",
             "h      -> traverse to parent - <LEFT>",
-            "l      -> traverse into item - <SPACE> <ENTER>",
+            "l      -> traverse into item (or archive) - <SPACE> <ENTER>",
             "j      -> next item - <DOWN>",
             "k      -> previous item - <UP>",
             "ctrl+f -> scroll forward",
@@ -492,6 +1603,30 @@ impl App {
             "g      -> go to bottom",
             "G      -> go to top",
             "r      -> refresh",
+            "u      -> undo last file operation",
+            "C-r    -> redo",
+            "y      -> yank (copy) item",
+            "x      -> cut item",
+            "P      -> paste yanked/cut items",
+            "d      -> move item to trash",
+            "D      -> delete item permanently",
+            "T      -> show trash",
+            "R      -> rename marked (or all) items in $EDITOR",
+            "m      -> toggle mark",
+            "a      -> mark all",
+            "v      -> invert marks",
+            "*      -> mark by glob",
+            "/      -> filter (substring, glob or regex)",
+            "f      -> find files below the current directory",
+            "S      -> search file contents below the current directory",
+            "c      -> rename item",
+            "N      -> new directory",
+            "n      -> new file",
+            "ESC    -> clear marks and filter",
+            "Z      -> compress marked (or selected) items into an archive",
+            "E      -> extract archive into a new directory",
+            "e      -> archive: extract marked (m) or selected entries",
+            "J      -> show jobs",
             "ESC    -> close popup",
         ];
         let help_items: Vec<ListItem> = help_vec
@@ -516,57 +1651,478 @@ impl App {
             .to_string();
         self.dir_list.dir = self.dir.clone();
 
+        // marks and filters only apply to the directory they were made in
+        self.dir_list.clear_marks();
+        self.dir_list.filter = None;
+        // the git status is recomputed for the new directory
+        self.dir_list.git = None;
         self.dir_list.refresh().expect("unable to refresh");
         self.dir_list.watch().expect("unable to watch");
     }
 
+    /// Show or hide dotfiles, keeping the selected entry selected if it is still listed.
+    fn set_show_hidden(&mut self, show_hidden: bool) {
+        if self.dir_list.show_hidden == show_hidden {
+            return;
+        }
+        let selected = match self.dir_list.get_selected_item() {
+            Some(DirectoryListItem::Entry(entry)) => Some(entry.name.clone()),
+            _ => None,
+        };
+        self.dir_list.show_hidden = show_hidden;
+        self.dir_list.refresh().ok();
+        match selected {
+            Some(name) if self.dir_list.is_visible(&name) => self.dir_list.select_by_name(&name),
+            _ => self.dir_list.select_first(),
+        }
+        self.message = Some(match show_hidden {
+            true => "showing hidden files".to_string(),
+            false => "hiding hidden files".to_string(),
+        });
+    }
+
+    /// Switch how entries matched by ignore files are listed, keeping the selection if possible.
+    fn set_ignore_mode(&mut self, mode: IgnoreMode) {
+        let selected = match self.dir_list.get_selected_item() {
+            Some(DirectoryListItem::Entry(entry)) => Some(entry.name.clone()),
+            _ => None,
+        };
+        self.dir_list.ignore_mode = mode;
+        self.dir_list.refresh().ok();
+        if let Some(name) = selected {
+            self.dir_list.select_by_name(&name);
+        }
+        if self.dir_list.get_selected_item().is_none() {
+            self.dir_list.select_first();
+        }
+        self.message = Some(format!("ignored files are {}", mode));
+    }
+
+    /// Apply the pattern typed into the filter popup so far.
+    fn update_filter(&mut self) {
+        let Some(PopupType::Input(prompt)) = &mut self.show_popup else {
+            return;
+        };
+        let pattern = prompt.input.value.clone();
+        let filter = match pattern.as_str() {
+            "" => Ok(None),
+            _ => Filter::new(self.filter_mode, pattern.as_str()).map(Some),
+        };
+        match filter {
+            Ok(filter) => {
+                prompt.error = None;
+                self.dir_list.set_filter(filter);
+                self.load_preview().ok();
+            },
+            // keep the last valid filter while the pattern is incomplete
+            Err(_) => prompt.error = Some("invalid pattern".to_string()),
+        }
+    }
+
+    /// Show the selected search hit in context in the preview pane.
+    fn load_search_preview(&mut self) {
+        let Some(PopupType::Search(search)) = &self.show_popup else {
+            return;
+        };
+        let Some(hit) = search.selected_hit() else {
+            return;
+        };
+        let path = search.root.join(&hit.path);
+        let first = hit.line_number.saturating_sub(SEARCH_CONTEXT_LINES + 1);
+        let highlight = hit.line_number - 1 - first;
+        self.preview.clear();
+        self.preview.lines = match File::open(&path) {
+            Ok(file) => BufReader::new(file)
+                .split(b'\n')
+                .skip(first)
+                .take(SNIPPET_LINES)
+                .map_while(|line| line.ok())
+                .map(|line| Line::from(String::from_utf8_lossy(&line).trim_end_matches('\r').to_string()))
+                .collect(),
+            Err(e) => vec![Line::from(format!("unable to read {}: {}", path.display(), e))],
+        };
+        self.preview.highlight = Some(highlight);
+        self.preview.first_line = Some(first + 1);
+    }
+
+    /// Preview the selected member of the archive being browsed.
+    fn load_archive_preview(&mut self) {
+        let Some(PopupType::Archive(view)) = &self.show_popup else {
+            return;
+        };
+        self.preview.clear();
+        let Some(ArchiveItem::Entry(entry)) = view.selected_item() else {
+            return;
+        };
+        if entry.is_dir {
+            self.preview.lines = view.children(&entry.path)
+                .take(SNIPPET_LINES)
+                .map(|child| Line::from(child.summary()))
+                .collect();
+            return;
+        }
+        self.preview.load_member(&view.path, view.format, &entry.path, MEMBER_PREVIEW_BYTES);
+    }
+
+    /// Forget the preview of a search hit or archive member; the listing's preview is shown again.
+    fn restore_listing_preview(&mut self) {
+        self.preview.highlight = None;
+        self.dir_list.selection_changed = true;
+        self.load_preview().ok();
+    }
+
+    /// Go to the directory containing `path` and select it.
+    fn navigate_to_path(&mut self, path: &Path) {
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return;
+        };
+        self.set_dir(parent.to_string_lossy().to_string());
+        self.dir_list.select_by_name(&name.to_string_lossy());
+        self.load_preview().ok();
+    }
+
+    fn open_input(&mut self, kind: InputKind, value: &str, target: Option<String>) {
+        let history = self.input_history.get(&kind).cloned().unwrap_or_default();
+        self.show_popup = Some(PopupType::Input(InputPrompt {
+            kind,
+            input: TextInput::new(value, history),
+            target,
+            error: None,
+        }));
+    }
+
+    fn on_input_submitted(&mut self, kind: InputKind, target: Option<String>, value: &str) -> Result<()> {
+        if kind == InputKind::MarkGlob {
+            let count = self.dir_list.mark_by_glob(value)?;
+            self.message = Some(format!("marked {} item(s)", count));
+            return Ok(());
+        }
+        if kind == InputKind::Search {
+            if value.is_empty() {
+                return Ok(());
+            }
+            let regex = self.search_pattern(value)?;
+            let search = ContentSearch::start(
+                PathBuf::from(&self.dir),
+                value,
+                regex,
+                self.dir_list.show_hidden,
+                self.dir_list.ignore_mode != IgnoreMode::Show,
+            );
+            self.show_popup = Some(PopupType::Search(search));
+            return Ok(());
+        }
+        if kind == InputKind::PreviewSearch {
+            if value.is_empty() {
+                self.preview.search = None;
+                self.preview.highlight = None;
+                return Ok(());
+            }
+            let regex = self.search_pattern(value)?;
+            if !self.preview.search(value, regex, self.preview_rows as usize) {
+                self.message = Some(format!("pattern not found: {}", value));
+            }
+            return Ok(());
+        }
+        if kind == InputKind::Commit {
+            if value.trim().is_empty() {
+                return Err(anyhow!("empty commit message"));
+            }
+            let result = commit(Path::new(&self.dir), value);
+            self.on_git_changed(result.map(|summary| format!("committed {}", summary)));
+            return Ok(());
+        }
+        if kind == InputKind::Filter {
+            let filter = match value {
+                "" => None,
+                _ => Some(Filter::new(self.filter_mode, value)?),
+            };
+            self.dir_list.set_filter(filter);
+            return Ok(());
+        }
+        if value.is_empty() || value == "." || value == ".." || value.contains('/') {
+            return Err(anyhow!("invalid name: \"{}\"", value));
+        }
+        let new_path = Path::new(&self.dir).join(value);
+        match kind {
+            InputKind::Rename => {
+                let Some(name) = target else {
+                    return Ok(());
+                };
+                if name == value {
+                    return Ok(());
+                }
+                // `fs::rename()` would silently replace an existing file
+                if fs::symlink_metadata(&new_path).is_ok() {
+                    return Err(anyhow!("{} already exists", value));
+                }
+                let old_path = Path::new(&self.dir).join(&name);
+                fs::rename(&old_path, &new_path)?;
+                self.journal.record(format!("rename {} -> {}", name, value),
+                                    vec![Operation::Rename { from: new_path.clone(), to: old_path }]);
+            },
+            InputKind::Mkdir => {
+                fs::create_dir(&new_path)?;
+                self.journal.record(format!("create directory {}", value), vec![Operation::Trash { path: new_path.clone() }]);
+            },
+            InputKind::Touch => {
+                OpenOptions::new().write(true).create_new(true).open(&new_path)?;
+                self.journal.record(format!("create file {}", value), vec![Operation::Trash { path: new_path.clone() }]);
+            },
+            InputKind::Compress => {
+                if ArchiveFormat::detect(&new_path).is_none() {
+                    return Err(anyhow!("unknown archive format: \"{}\"", value));
+                }
+                if fs::symlink_metadata(&new_path).is_ok() {
+                    return Err(anyhow!("{} already exists", value));
+                }
+                let paths = self.selected_paths();
+                if !paths.is_empty() {
+                    self.jobs.submit(JobKind::Compress, paths, Some(new_path));
+                    self.dir_list.clear_marks();
+                }
+                // the archive shows up once the job has created it
+                return Ok(());
+            },
+            InputKind::MarkGlob | InputKind::Filter | InputKind::Search | InputKind::Commit | InputKind::PreviewSearch => {},
+        }
+        self.dir_list.refresh()?;
+        self.dir_list.select_by_name(value);
+        Ok(())
+    }
+
+    /// The regex to search for what was typed into a search popup; literal unless regex
+    /// searches are enabled.
+    fn search_pattern(&self, value: &str) -> Result<regex::Regex> {
+        let pattern = match self.search_regex {
+            true => value.to_string(),
+            false => regex::escape(value),
+        };
+        // smart case, like the filter
+        let regex = regex::RegexBuilder::new(pattern.as_str())
+            .case_insensitive(!value.chars().any(|c| c.is_uppercase()))
+            .build()?;
+        Ok(regex)
+    }
+
+    /// Called when the editor opened through `KeyInputResult::EditFile` has exited.
+    fn on_editor_closed(&mut self, edit_result: Result<()>) {
+        let Some(session) = self.rename_session.take() else {
+            return;
+        };
+        if let Err(e) = edit_result {
+            let _result = fs::remove_file(&session.file);
+            self.message = Some(format!("editor failed: {}", e));
+            return;
+        }
+        match session.finish() {
+            Ok(plan) => self.show_popup = Some(PopupType::BulkRename(plan)),
+            Err(e) => self.message = Some(format!("unable to read names: {}", e)),
+        }
+    }
+
+    /// Undo (or redo) the most recent file operation in the journal.
+    fn replay_journal(&mut self, undo: bool) {
+        let result = Trash::new().and_then(|trash| match undo {
+            true => self.journal.undo(&trash),
+            false => self.journal.redo(&trash),
+        });
+        let verb = if undo { "undo" } else { "redo" };
+        self.message = match result {
+            Ok(Some(description)) => Some(format!("{}: {}", verb, description)),
+            Ok(None) => Some(format!("nothing to {}", verb)),
+            Err(e) => Some(format!("{} failed: {}", verb, e)),
+        };
+        self.dir_list.refresh().ok();
+    }
+
+    fn reload_trash_view(&mut self) {
+        match Trash::new() {
+            Ok(trash) => self.trash_view.reload(&trash),
+            Err(e) => self.trash_view.message = Some(e.to_string()),
+        }
+    }
+
+    fn purge_trash_items(&mut self, items: &[TrashItem]) {
+        let trash = match Trash::new() {
+            Ok(trash) => trash,
+            Err(e) => {
+                self.trash_view.message = Some(e.to_string());
+                return;
+            }
+        };
+        let errors = items
+            .iter()
+            .filter(|item| {
+                trash.purge(item)
+                    .inspect_err(|e| error!("unable to purge {}: {}", item.name, e))
+                    .is_err()
+            })
+            .count();
+        self.trash_view.message = match errors {
+            0 => Some(format!("deleted {} item(s)", items.len())),
+            _ => Some(format!("unable to delete {} item(s)", errors)),
+        };
+        self.trash_view.reload(&trash);
+    }
+
+    /// Names of the marked (or selected) entries whose git status is `wanted`; sets `nothing`
+    /// as the message if there are none.
+    fn git_entries(&mut self, wanted: fn(&GitStatus) -> bool, nothing: &str) -> Vec<String> {
+        if self.dir_list.git.is_none() {
+            self.message = Some("not inside a git work tree".to_string());
+            return vec![];
+        }
+        let mut entries = self.dir_list.marked_entries();
+        if entries.is_empty() {
+            if let Some(DirectoryListItem::Entry(entry)) = self.dir_list.get_selected_item() {
+                entries.push(entry);
+            }
+        }
+        let names: Vec<String> = entries
+            .iter()
+            .filter(|e| e.git_status.as_ref().is_some_and(wanted))
+            .map(|e| e.name.clone())
+            .collect();
+        if names.is_empty() {
+            self.message = Some(nothing.to_string());
+        }
+        names
+    }
+
+    /// The git status of the selected entry, if it has one.
+    fn selected_git_status(&self) -> Option<GitStatus> {
+        match self.dir_list.get_selected_item() {
+            Some(DirectoryListItem::Entry(entry)) => entry.git_status,
+            _ => None,
+        }
+    }
+
+    /// Report the outcome of a git command, and pick up the new git status.
+    fn on_git_changed(&mut self, result: Result<String>) {
+        self.message = Some(match result {
+            Ok(message) => message,
+            Err(e) => e.to_string(),
+        });
+        self.dir_list.request_git_status();
+        // diffs against the index change, too
+        self.dir_list.selection_changed = true;
+    }
+
+    /// The absolute paths of the marked entries or, if nothing is marked, of the selected entry.
+    fn selected_paths(&self) -> Vec<PathBuf> {
+        let cur_path = Path::new(&self.dir);
+        let marked = self.dir_list.marked_entries();
+        if !marked.is_empty() {
+            return marked
+                .iter()
+                .map(|e| cur_path.join(&e.name))
+                .collect();
+        }
+        match self.dir_list.get_selected_item() {
+            Some(DirectoryListItem::Entry(entry)) => vec![cur_path.join(&entry.name)],
+            _ => vec![],
+        }
+    }
+
     /// Do something every so often
     fn on_tick(&mut self) {
         // check if filesystem has changed
         let mut fs_events: Vec<notify::Event> = vec![];
         // drain the dir_watch channel
-        loop {
-            if let Some(rx) = self.dir_list.dir_watch_rx.as_mut() {
-                match rx.try_recv() {
-                    Ok(event) => {
-                        fs_events.push(event.to_owned());
-                        debug!("FS ev: {:?}:{:?}", event.kind, event.paths);
-                    },
-                    Err(TryRecvError::Empty) => {
-                        break;
-                    },
-                    Err(TryRecvError::Disconnected) => {
-                        break;
-                    }
+        while let Some(rx) = self.dir_list.dir_watch_rx.as_mut() {
+            match rx.try_recv() {
+                Ok(event) => {
+                    fs_events.push(event.to_owned());
+                    debug!("FS ev: {:?}:{:?}", event.kind, event.paths);
+                },
+                Err(TryRecvError::Empty) => {
+                    break;
+                },
+                Err(TryRecvError::Disconnected) => {
+                    break;
                 }
             }
         }
-        if fs_events.len() > 0 {
+        if !fs_events.is_empty() {
+            // the diff of the selected file changes with its contents, not only with its status
+            let selected = match self.dir_list.get_selected_item() {
+                Some(DirectoryListItem::Entry(entry)) => Some(Path::new(&self.dir).join(&entry.name)),
+                _ => None,
+            };
+            let selected_changed = selected.is_some_and(|path| fs_events.iter().any(|e| e.paths.contains(&path)));
             let _result = self.dir_list.smart_refresh(fs_events);
+            if selected_changed {
+                self.reload_diff_preview();
+            }
+        }
+        // stream in the files found by the finder, and the content search hits
+        match &mut self.show_popup {
+            Some(PopupType::Finder(finder)) => {
+                finder.poll();
+            },
+            Some(PopupType::Search(search)) => {
+                let first_hits = search.hits.is_empty();
+                if search.poll() && first_hits {
+                    self.load_search_preview();
+                }
+            },
+            _ => {},
+        }
+        // check for the entries of an archive being opened
+        if let Some(PopupType::Archive(view)) = &mut self.show_popup {
+            match view.poll() {
+                Some(Ok(())) => self.load_archive_preview(),
+                Some(Err(e)) => {
+                    let name = view.path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                    self.message = Some(format!("unable to read {}: {}", name, e));
+                    self.show_popup = None;
+                    self.restore_listing_preview();
+                },
+                None => {},
+            }
+        }
+        // check for a decoded image, the text of a PDF or archive contents to preview
+        self.preview.poll_image();
+        self.preview.poll_pdf();
+        self.preview.poll_archive();
+        self.preview.poll_member(SNIPPET_LINES + 1, self.preview_rows as usize, &mut self.highlighter);
+        // check for job progress, and journal what finished jobs did
+        for id in self.jobs.poll() {
+            if let Some(job) = self.jobs.jobs.iter().find(|j| j.id == id) {
+                let description = format!("{} {} item(s)", job.kind.to_string().to_lowercase(), job.undo.len());
+                self.journal.record(description, job.undo.clone());
+            }
         }
         // check for size notifications
-        loop {
-            if let Some(rx) = self.dir_list.dir_size_rx.as_mut() {
-                match rx.try_recv() {
-                    Ok(size_notify) => {
-                        for item in &mut self.dir_list.items {
-                            match item {
-                                DirectoryListItem::Entry(e) => {
-                                    if e.name == size_notify.name {
-                                        e.size = Some(size_notify.size);
-                                        break;
-                                    }
-                                }
-                                DirectoryListItem::ParentDir(_) => {}
-                            }
-                        }
-                    },
-                    Err(TryRecvError::Empty) => {
-                        break;
-                    },
-                    Err(TryRecvError::Disconnected) => {
-                        break;
+        while let Some(rx) = self.dir_list.dir_size_rx.as_mut() {
+            match rx.try_recv() {
+                Ok(size_notify) => {
+                    self.dir_list.set_size(&size_notify.name, size_notify.size);
+                },
+                Err(TryRecvError::Empty) => {
+                    break;
+                },
+                Err(TryRecvError::Disconnected) => {
+                    break;
+                }
+            }
+        }
+        // check for git status notifications
+        while let Some(rx) = self.dir_list.git_status_rx.as_mut() {
+            match rx.try_recv() {
+                Ok(git_notify) => {
+                    let before = self.selected_git_status();
+                    self.dir_list.set_git_status(git_notify);
+                    if self.selected_git_status() != before {
+                        self.reload_diff_preview();
                     }
+                },
+                Err(TryRecvError::Empty) => {
+                    break;
+                },
+                Err(TryRecvError::Disconnected) => {
+                    break;
                 }
             }
         }
@@ -618,6 +2174,65 @@ impl App {
         Ok(())
     }
 
+    /// Cycle the preview of changed files through diffs against HEAD, the index and no diff.
+    fn toggle_diff_preview(&mut self) {
+        if self.dir_list.git.is_none() {
+            self.message = Some("not inside a git work tree".to_string());
+            return;
+        }
+        self.preview_diff = match self.preview_diff {
+            None => Some(DiffBase::Head),
+            Some(DiffBase::Head) => Some(DiffBase::Index),
+            Some(DiffBase::Index) => None,
+        };
+        self.message = Some(match self.preview_diff {
+            Some(base) => format!("previewing diffs against {}", base),
+            None => "not previewing diffs".to_string(),
+        });
+        self.dir_list.selection_changed = true;
+    }
+
+    /// Run git diff again for the selected entry, if diffs are previewed.
+    fn reload_diff_preview(&mut self) {
+        if self.preview_diff.is_some() && self.show_popup.is_none() && !self.preview_focused {
+            self.dir_list.selection_changed = true;
+            self.load_preview().ok();
+        }
+    }
+
+    /// The diff of a changed file, or a summary of the changes below a directory, if diffs
+    /// are previewed. `None` falls back to the regular preview (e.g. for untracked files).
+    fn diff_preview(&self, entry: &DirEntryData, entry_path: &Path) -> Option<Vec<Line<'static>>> {
+        let base = self.preview_diff?;
+        let git = self.dir_list.git.as_ref()?;
+        let status = entry.git_status?;
+        if entry.file_type.is_dir() {
+            let changes = git.changes_below(&entry.name);
+            if changes.is_empty() {
+                return None;
+            }
+            let mut lines = vec![Line::from(format!("{} changed file(s)", changes.len()))];
+            lines.extend(changes.iter().take(SNIPPET_LINES).map(|(path, status)| {
+                Line::from(vec![
+                    Span::styled(format!("{:<4}", status.to_string()), status.style()),
+                    Span::raw(path.display().to_string()),
+                ])
+            }));
+            return Some(lines);
+        }
+        if !(status.staged || status.modified || status.conflicted) {
+            return None;
+        }
+        match diff(entry_path, base) {
+            Ok(lines) if !lines.is_empty() => Some(lines.into_iter().map(diff_line).collect()),
+            Ok(_) => None,
+            Err(e) => {
+                debug!("no diff for {}: {}", entry_path.display(), e);
+                None
+            },
+        }
+    }
+
     /// Load a preview of the selected file
     fn load_preview(&mut self) -> Result<()> {
         if !self.show_preview {
@@ -628,35 +2243,91 @@ impl App {
             return Ok(());
         }
         self.preview.clear();
-        if let Some(sel_idx) = self.dir_list.state.selected() {
-            match &self.dir_list.items[sel_idx] {
+        if let Some(item) = self.dir_list.get_selected_item() {
+            match item {
                 DirectoryListItem::Entry(entry) => {
                     let cur_path = Path::new(&self.dir);
                     let entry_path = cur_path.join(&entry.name);
-                    if entry.file_type.is_file() {
+                    if let Some(diff) = self.diff_preview(entry, &entry_path) {
+                        self.preview.lines = diff;
+                        self.preview.title = self.preview_diff.map(|base| format!("Diff against {}", base));
+                    } else if let Some(format) = ArchiveFormat::detect(&entry_path).filter(|_| entry.file_type.is_file()) {
+                        self.preview.load_archive(&entry_path, format, SNIPPET_LINES);
+                    } else if entry.file_type.is_file() {
                         if let Some(mime_type) = tree_magic_mini::from_filepath(entry_path.as_path()) {
-                            if mime_type.contains("text") {
-                                let file = File::open(entry_path)?;
-                                let reader = BufReader::new(file);
-                                for (index, line) in reader.lines().enumerate() {
-                                    if index > SNIPPET_LINES { break; }
-                                    self.preview.push(line
-                                        .expect("unable to add line to preview"));
+                            // big data files are parsed as a whole, so they are shown as they are
+                            let data_format = DataFormat::detect(&entry_path)
+                                .filter(|_| entry.size.is_some_and(|size| size <= MAX_STRUCTURED_BYTES));
+                            if let Some(format) = data_format.filter(DataFormat::is_table) {
+                                if let Err(e) = self.preview.load_table(&entry_path, format) {
+                                    self.preview.lines.push(format!("unable to read the table: {}", e).into());
                                 }
+                            } else if let Some(format) = data_format {
+                                self.preview.load_data(&entry_path, format, mime_type, SNIPPET_LINES + 1,
+                                                       self.preview_rows as usize, &mut self.highlighter)?;
+                            } else if is_markdown(&entry_path, mime_type)
+                                && entry.size.is_some_and(|size| size <= MAX_MARKDOWN_BYTES) {
+                                if self.preview.render_markdown {
+                                    self.preview.load_markdown(&entry_path)?;
+                                } else {
+                                    self.preview.load_text(&entry_path, mime_type, SNIPPET_LINES + 1, &mut self.highlighter)?;
+                                    self.preview.title = Some("Markdown - raw".to_string());
+                                }
+                            } else if is_text_mime(mime_type) {
+                                self.preview.load_text(&entry_path, mime_type, SNIPPET_LINES + 1, &mut self.highlighter)?;
+                                if let Some(interpreter) = shebang(&entry_path) {
+                                    self.preview.title = Some(format!("Script - {}", interpreter));
+                                }
+                            } else if is_elf(&entry_path) {
+                                match read_elf(&entry_path) {
+                                    Ok(elf) => {
+                                        self.preview.lines = elf.describe().into_iter().map(Line::from).collect();
+                                        self.preview.title = Some(format!("ELF - {}", elf.kind));
+                                    },
+                                    Err(e) => self.preview.lines.push(format!("unable to read the ELF file: {}", e).into()),
+                                }
+                            } else if let Some((compression, mime_type, reader)) = Compression::detect(&entry_path).and_then(|compression| {
+                                let (mime_type, reader) = open_decompressed(&entry_path, compression).ok()?;
+                                is_text_mime(&mime_type).then_some((compression, mime_type, reader))
+                            }) {
+                                // decompressed as it is scrolled
+                                self.preview.load_reader(&compression.stem(&entry_path), &mime_type, reader, SNIPPET_LINES + 1,
+                                                         &mut self.highlighter);
+                                let compressed = entry.size.unwrap_or(0);
+                                let size = Byte::from(compressed).get_appropriate_unit(UnitType::Decimal);
+                                let sizes = match uncompressed_size(&entry_path, compression).filter(|&size| size > 0) {
+                                    Some(uncompressed) => format!("{:.1} -> {:.1}, {:.1}%", size,
+                                                                  Byte::from(uncompressed).get_appropriate_unit(UnitType::Decimal),
+                                                                  compressed as f64 * 100.0 / uncompressed as f64),
+                                    None => format!("{:.1}", size),
+                                };
+                                self.preview.title = Some(format!("{} - {} ({})", compression, mime_type, sizes));
+                            } else if is_pdf(&entry_path) && entry.size.is_some_and(|size| size <= MAX_PDF_BYTES) {
+                                // extracted in the background, picked up by `on_tick()`
+                                self.preview.load_pdf(&entry_path);
+                                let size = Byte::from(entry.size.unwrap_or(0)).get_appropriate_unit(UnitType::Decimal);
+                                self.preview.title = Some(format!("PDF ({:.1})", size));
+                            } else if let Some(format) = image_format(&entry_path) {
+                                // decoded in the background, picked up by `on_tick()`
+                                self.preview.load_image(&entry_path, format, self.preview_cols, self.preview_rows);
+                                let size = Byte::from(entry.size.unwrap_or(0)).get_appropriate_unit(UnitType::Decimal);
+                                self.preview.title = Some(format!("Image - {} ({:.1})", mime_type, size));
                             } else {
-                                self.preview.push("*** preview not available ***".to_string());
-                                self.preview.push(format!("file type: {}", mime_type).to_string());
+                                self.preview.load_hex(&entry_path, self.preview_rows as usize)?;
+                                let size = Byte::from(entry.size.unwrap_or(0)).get_appropriate_unit(UnitType::Decimal);
+                                self.preview.title = Some(format!("Hex dump - {} ({:.1})", mime_type, size));
                             }
                         }
                     } else if entry.file_type.is_dir() {
-                        let paths = fs::read_dir(entry_path.as_path())?;
+                        let paths = fs::read_dir(entry_path.as_path())?
+                            .filter_map(|path| path.ok())
+                            .filter(|path| self.dir_list.is_visible(&path.file_name().to_string_lossy()));
                         for (i, path) in paths.enumerate() {
                             if i > SNIPPET_LINES { break; }
-                            let path = path?;
                             let mut filename = path.file_name().into_string()
                                 .expect("unable to get filename");
                             filename.insert_str(0, "./");
-                            self.preview.push(filename);
+                            self.preview.lines.push(filename.into());
                         }
                     }
                 }
@@ -708,8 +2379,10 @@ fn main() -> Result<()> {
     let mut terminal = Terminal::new(backend)?;
 
     // create app and run it
+    std::thread::spawn(preload_syntaxes);
     let tick_rate = Duration::from_millis(TICK_RATE_MILLIS);
     let mut app = App::new(args.dir_name.unwrap_or(".".to_string()));
+    app.set_show_hidden(args.all);
     let app_result = app.run(&mut terminal, tick_rate);
 
     // restore terminal
@@ -723,12 +2396,44 @@ fn main() -> Result<()> {
 
     if let Err(err) = app_result {
         println!("{:?}", err);
-        Err(anyhow::Error::from(err))
+        Err(err)
     } else {
         Ok(())
     }
 }
 
+/// Give the terminal back to the shell, e.g. while an editor is running.
+fn suspend_terminal() -> Result<()> {
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture, cursor::Show)?;
+    Ok(())
+}
+
+fn resume_terminal<B: Backend>(terminal: &mut Terminal<B>) -> Result<()> {
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+    terminal.clear().map_err(|e| anyhow!(e.to_string()))?;
+    Ok(())
+}
+
+/// Open `path` in `$VISUAL` or `$EDITOR` (falling back to `vi`) and wait for it to exit.
+fn run_editor(path: &Path) -> Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // the editor may come with arguments, e.g. `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()?;
+    if !status.success() {
+        return Err(anyhow!("{} exited with {}", program, status));
+    }
+    Ok(())
+}
+
 /// helper function to create a centered rect using up certain percentage of the available rect `r`
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
@@ -761,6 +2466,22 @@ mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
 
+    /// Tick until `done` holds for the app, or a few seconds passed.
+    fn tick_until(app: &mut App, done: impl Fn(&App) -> bool) {
+        let start = Instant::now();
+        while !done(app) && start.elapsed() < Duration::from_secs(10) {
+            app.on_tick();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Select `name` in the listing and preview it.
+    fn select(app: &mut App, name: &str) {
+        app.dir_list.select_by_name(name);
+        app.dir_list.selection_changed = true;
+        app.load_preview().unwrap();
+    }
+
     #[test]
     fn test_navigate_to_relative_directory() {
         let mut app = App::new("/".to_string());
@@ -796,4 +2517,307 @@ mod tests {
         println!("absolute dir: {}", app.dir);
         assert!(app.dir.ends_with("/ls-tui/target/debug/deps"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_create_and_rename_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut app = App::new(tmp.path().to_str().unwrap().to_string());
+
+        app.on_input_submitted(InputKind::Mkdir, None, "sub").unwrap();
+        assert!(tmp.path().join("sub").is_dir());
+        app.on_input_submitted(InputKind::Touch, None, "a.txt").unwrap();
+        assert!(tmp.path().join("a.txt").is_file());
+        assert!(app.on_input_submitted(InputKind::Touch, None, "a.txt").is_err());
+
+        app.on_input_submitted(InputKind::Rename, Some("a.txt".to_string()), "b.txt").unwrap();
+        assert!(tmp.path().join("b.txt").is_file());
+        let Some(DirectoryListItem::Entry(entry)) = app.dir_list.get_selected_item() else {
+            panic!("nothing selected");
+        };
+        assert_eq!("b.txt", entry.name);
+
+        // renaming must not replace an existing entry
+        assert!(app.on_input_submitted(InputKind::Rename, Some("b.txt".to_string()), "sub").is_err());
+        assert!(app.on_input_submitted(InputKind::Mkdir, None, "x/y").is_err());
+
+        app.handle_input(KeyEvent::new(KeyCode::Char('u'), KeyModifiers::NONE));
+        assert!(tmp.path().join("a.txt").is_file() && !tmp.path().join("b.txt").exists());
+        app.handle_input(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL));
+        assert!(tmp.path().join("b.txt").is_file() && !tmp.path().join("a.txt").exists());
+    }
+
+    #[test]
+    fn test_preview_focus() {
+        let tmp = tempfile::tempdir().unwrap();
+        let text: String = (1..=1000).map(|i| format!("line {}\n", i)).collect();
+        fs::write(tmp.path().join("a.txt"), text).unwrap();
+        let mut app = App::new(tmp.path().to_str().unwrap().to_string());
+        app.dir_list.select_by_name("a.txt");
+        app.load_preview().unwrap();
+        assert_eq!(SNIPPET_LINES + 1, app.preview.lines.len());
+
+        let key = |app: &mut App, code: KeyCode| app.handle_input(KeyEvent::new(code, KeyModifiers::NONE));
+        key(&mut app, KeyCode::Tab);
+        assert!(app.preview_focused);
+        key(&mut app, KeyCode::Char('G'));
+        assert_eq!(1000, app.preview.lines.len());
+        assert_eq!(1000 - app.preview_rows as usize, app.preview.scroll);
+        key(&mut app, KeyCode::Char('g'));
+        assert_eq!(0, app.preview.scroll);
+
+        key(&mut app, KeyCode::Char('/'));
+        for c in "line 500".chars() {
+            key(&mut app, KeyCode::Char(c));
+        }
+        key(&mut app, KeyCode::Enter);
+        assert_eq!(Some(499), app.preview.highlight);
+        key(&mut app, KeyCode::Char('n'));
+        assert_eq!(Some(499), app.preview.highlight);
+
+        key(&mut app, KeyCode::Esc);
+        assert!(app.preview.search.is_none() && app.preview_focused);
+        key(&mut app, KeyCode::Tab);
+        assert!(!app.preview_focused);
+    }
+
+    #[test]
+    fn test_browse_archive() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut zip = zip::ZipWriter::new(File::create(tmp.path().join("a.zip")).unwrap());
+        zip.start_file("dir/b.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        io::Write::write_all(&mut zip, b"hello\n").unwrap();
+        zip.finish().unwrap();
+        let mut app = App::new(tmp.path().to_str().unwrap().to_string());
+        select(&mut app, "a.zip");
+        tick_until(&mut app, |app| app.preview.lines[0].to_string() != "reading...");
+        assert!(app.preview.lines[0].to_string().ends_with("dir/b.txt"));
+
+        let key = |app: &mut App, code: KeyCode| app.handle_input(KeyEvent::new(code, KeyModifiers::NONE));
+        key(&mut app, KeyCode::Enter);
+        tick_until(&mut app, |app| matches!(&app.show_popup, Some(PopupType::Archive(view)) if !view.is_loading()));
+        key(&mut app, KeyCode::Enter);
+        let Some(PopupType::Archive(view)) = &app.show_popup else {
+            panic!("archive not opened");
+        };
+        assert_eq!("dir/", view.prefix);
+        key(&mut app, KeyCode::Char('j'));
+        tick_until(&mut app, |app| app.preview.lines[0].to_string() != "reading...");
+        assert_eq!("hello", app.preview.lines[0].to_string());
+
+        key(&mut app, KeyCode::Char('e'));
+        tick_until(&mut app, |app| app.jobs.running_count() == 0);
+        assert_eq!("hello\n", fs::read_to_string(tmp.path().join("b.txt")).unwrap());
+        key(&mut app, KeyCode::Char('h'));
+        key(&mut app, KeyCode::Char('h'));
+        assert!(app.show_popup.is_none());
+    }
+
+    fn bzip2_bytes(data: &[u8]) -> Vec<u8> {
+        let mut encoder = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+        std::io::Write::write_all(&mut encoder, data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_compressed_preview() {
+        let tmp = tempfile::tempdir().unwrap();
+        let text: String = (1..=1000).map(|i| format!("line {}\n", i)).collect();
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, text.as_bytes()).unwrap();
+        fs::write(tmp.path().join("app.log.1.gz"), encoder.finish().unwrap()).unwrap();
+        fs::write(tmp.path().join("data.bz2"), bzip2_bytes(&[0, 1, 2, 255])).unwrap();
+        let mut app = App::new(tmp.path().to_str().unwrap().to_string());
+
+        select(&mut app, "app.log.1.gz");
+        let title = app.preview.title.clone().unwrap();
+        assert!(title.starts_with("gzip - text/plain (") && title.ends_with("%)"), "{}", title);
+        assert!(title.contains(" -> 8.9 KB, "), "{}", title);
+        assert_eq!("line 1", app.preview.lines[0].to_string());
+        app.preview.scroll_to_bottom(10);
+        assert_eq!("line 1000", app.preview.lines.last().unwrap().to_string());
+
+        // binary contents are dumped as they are
+        select(&mut app, "data.bz2");
+        assert!(app.preview.title.as_ref().unwrap().starts_with("Hex dump"));
+    }
+
+    #[test]
+    fn test_executable_preview() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("run.sh"), "#!/bin/sh -e\necho hi\n").unwrap();
+        fs::write(tmp.path().join("a.out"), b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0\x03\0").unwrap();
+        let mut app = App::new(tmp.path().to_str().unwrap().to_string());
+
+        select(&mut app, "run.sh");
+        assert_eq!(Some("Script - /bin/sh -e"), app.preview.title.as_deref());
+        assert_eq!("#!/bin/sh -e", app.preview.lines[0].to_string());
+
+        select(&mut app, "a.out");
+        assert_eq!("unable to read the ELF file: truncated ELF header", app.preview.lines[0].to_string());
+    }
+
+    #[test]
+    fn test_pdf_preview() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("broken.pdf"), "%PDF-1.4\nnot really a PDF\n").unwrap();
+        let mut app = App::new(tmp.path().to_str().unwrap().to_string());
+        select(&mut app, "broken.pdf");
+        assert!(app.preview.title.as_ref().unwrap().starts_with("PDF ("));
+        assert_eq!("extracting text...", app.preview.lines[0].to_string());
+
+        tick_until(&mut app, |app| app.preview.lines[0].to_string() != "extracting text...");
+        assert!(app.preview.lines[0].to_string().starts_with("unable to read the PDF: "));
+    }
+
+    #[test]
+    fn test_image_preview() {
+        let tmp = tempfile::tempdir().unwrap();
+        image::RgbImage::from_pixel(40, 20, image::Rgb([0, 128, 255])).save(tmp.path().join("a.png")).unwrap();
+        let mut app = App::new(tmp.path().to_str().unwrap().to_string());
+        select(&mut app, "a.png");
+        assert_eq!("decoding...", app.preview.lines[0].to_string());
+        tick_until(&mut app, |app| app.preview.lines.len() > 1);
+        // two rows of pixels per line
+        assert_eq!(10, app.preview.lines.len());
+        assert!(app.preview.title.as_ref().unwrap().ends_with(" - 40x20, RGB"));
+    }
+
+    #[test]
+    fn test_structured_preview() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("a.json"), "{\"a\": [1, 2]}").unwrap();
+        fs::write(tmp.path().join("b.json"), "{\n  \"a\": 1\n  \"b\": 2\n}\n").unwrap();
+        fs::write(tmp.path().join("c.csv"), "name,size\na,1\nb,2\n").unwrap();
+        let mut app = App::new(tmp.path().to_str().unwrap().to_string());
+
+        select(&mut app, "a.json");
+        assert_eq!(Some("JSON (pretty-printed)"), app.preview.title.as_deref());
+        assert_eq!(6, app.preview.lines.len());
+        assert_eq!(None, app.preview.first_line);
+
+        select(&mut app, "b.json");
+        assert!(app.preview.title.as_ref().unwrap().starts_with("JSON - invalid: expected `,` or `}` at line 3"));
+        assert_eq!(Some(2), app.preview.highlight);
+
+        select(&mut app, "c.csv");
+        let table = app.preview.table.as_ref().unwrap();
+        assert_eq!(vec!["name", "size"], table.header);
+        assert_eq!(Some("CSV - 2 columns, 2 rows"), app.preview.title.as_deref());
+        assert!(app.preview.search("b", regex::Regex::new("b").unwrap(), 10));
+        assert_eq!(Some(1), app.preview.highlight);
+    }
+
+    #[test]
+    fn test_markdown_preview() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("README.md"), "# Title\n\n- *one*\n- two\n").unwrap();
+        let mut app = App::new(tmp.path().to_str().unwrap().to_string());
+        select(&mut app, "README.md");
+        assert_eq!(Some("Markdown - rendered"), app.preview.title.as_deref());
+        assert_eq!(vec!["Title", "", "• one", "• two"],
+                   app.preview.lines.iter().map(|l| l.to_string()).collect::<Vec<String>>());
+
+        app.handle_input(KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE));
+        app.handle_input(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::NONE));
+        assert!(app.preview_focused);
+        assert_eq!(Some("Markdown - raw"), app.preview.title.as_deref());
+        assert_eq!("# Title", app.preview.lines[0].to_string());
+        assert_eq!(Some(1), app.preview.first_line);
+
+        app.handle_input(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::NONE));
+        assert_eq!(Some("Markdown - rendered"), app.preview.title.as_deref());
+    }
+
+    #[test]
+    fn test_compress_and_extract() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir(tmp.path().join("src")).unwrap();
+        fs::write(tmp.path().join("src/a.txt"), "hello\n").unwrap();
+        let mut app = App::new(tmp.path().to_str().unwrap().to_string());
+
+        app.dir_list.select_by_name("src");
+        app.handle_input(KeyEvent::new(KeyCode::Char('Z'), KeyModifiers::NONE));
+        let Some(PopupType::Input(prompt)) = &app.show_popup else {
+            panic!("no name prompt");
+        };
+        assert_eq!("src.tar.gz", prompt.input.value);
+        app.handle_input(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert!(app.on_input_submitted(InputKind::Compress, None, "src.rar").is_err());
+        app.on_input_submitted(InputKind::Compress, None, "src.tar.xz").unwrap();
+        tick_until(&mut app, |app| app.jobs.running_count() == 0);
+        assert!(app.jobs.jobs.iter().all(|j| j.errors.is_empty()));
+        assert!(app.on_input_submitted(InputKind::Compress, None, "src.tar.xz").is_err());
+
+        // "src" is taken, so the archive is extracted next to it
+        app.dir_list.refresh().unwrap();
+        app.dir_list.select_by_name("src.tar.xz");
+        app.handle_input(KeyEvent::new(KeyCode::Char('E'), KeyModifiers::NONE));
+        tick_until(&mut app, |app| app.jobs.running_count() == 0);
+        assert_eq!("hello\n", fs::read_to_string(tmp.path().join("src (copy)/src/a.txt")).unwrap());
+    }
+
+    /// Tick until an up to date git status of the app's directory came in.
+    fn wait_for_git_status(app: &mut App) {
+        let request = app.dir_list.request_git_status();
+        tick_until(app, |app| app.dir_list.has_git_status(request));
+    }
+
+    fn selected_status_letters(app: &App) -> String {
+        match app.dir_list.get_selected_item() {
+            Some(DirectoryListItem::Entry(entry)) => entry.git_status.map(|s| s.to_string()).unwrap_or_default(),
+            _ => panic!("nothing selected"),
+        }
+    }
+
+    #[test]
+    fn test_git_housekeeping() {
+        let tmp = tempfile::tempdir().unwrap();
+        for args in [&["init", "-q"][..], &["config", "user.name", "test"], &["config", "user.email", "test@example.com"]] {
+            assert!(Command::new("git").args(args).current_dir(tmp.path()).status().unwrap().success());
+        }
+        fs::write(tmp.path().join("a.txt"), "a\n").unwrap();
+        let mut app = App::new(tmp.path().to_str().unwrap().to_string());
+        wait_for_git_status(&mut app);
+        app.dir_list.select_by_name("a.txt");
+        assert_eq!("?", selected_status_letters(&app));
+
+        app.handle_input(KeyEvent::new(KeyCode::Char('A'), KeyModifiers::NONE));
+        wait_for_git_status(&mut app);
+        assert_eq!("S", selected_status_letters(&app));
+        assert!(app.on_input_submitted(InputKind::Commit, None, " ").is_err());
+        app.handle_input(KeyEvent::new(KeyCode::Char('C'), KeyModifiers::NONE));
+        assert!(matches!(app.show_popup, Some(PopupType::Input(_))));
+        for c in "add a".chars() {
+            app.handle_input(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        app.handle_input(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert!(app.show_popup.is_none());
+        assert!(app.message.as_ref().is_some_and(|m| m.ends_with("] add a")));
+        wait_for_git_status(&mut app);
+        assert_eq!("", selected_status_letters(&app));
+
+        // discarding asks first
+        fs::write(tmp.path().join("a.txt"), "changed\n").unwrap();
+        wait_for_git_status(&mut app);
+        app.handle_input(KeyEvent::new(KeyCode::Char('X'), KeyModifiers::NONE));
+        app.handle_input(KeyEvent::new(KeyCode::Char('n'), KeyModifiers::NONE));
+        assert_eq!("changed\n", fs::read_to_string(tmp.path().join("a.txt")).unwrap());
+        app.handle_input(KeyEvent::new(KeyCode::Char('X'), KeyModifiers::NONE));
+        app.handle_input(KeyEvent::new(KeyCode::Char('y'), KeyModifiers::NONE));
+        assert_eq!("a\n", fs::read_to_string(tmp.path().join("a.txt")).unwrap());
+
+        app.handle_input(KeyEvent::new(KeyCode::Char('U'), KeyModifiers::NONE));
+        assert_eq!(Some("nothing to unstage".to_string()), app.message);
+
+        // the diff follows edits which keep the status of the file
+        fs::write(tmp.path().join("a.txt"), "changed\n").unwrap();
+        wait_for_git_status(&mut app);
+        app.handle_input(KeyEvent::new(KeyCode::Char('='), KeyModifiers::NONE));
+        app.load_preview().unwrap();
+        let has_line = |app: &App, text: &str| app.preview.lines.iter().any(|l| l.to_string() == text);
+        assert!(has_line(&app, "+changed"));
+        fs::write(tmp.path().join("a.txt"), "changed again\n").unwrap();
+        tick_until(&mut app, |app| has_line(app, "+changed again"));
+        assert!(has_line(&app, "+changed again"));
+    }
+}
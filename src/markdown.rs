@@ -0,0 +1,361 @@
+use std::path::Path;
+
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+
+use crate::highlight::highlight_snippet;
+
+/// Markdown files larger than this are shown as they are.
+pub const MAX_MARKDOWN_BYTES: u64 = 1024 * 1024;
+/// The width of horizontal rules.
+const RULE_WIDTH: usize = 40;
+
+/// Recognize a Markdown file by its extension or mime type.
+pub(crate) fn is_markdown(path: &Path, mime_type: &str) -> bool {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+    matches!(extension.as_deref(), Some("md" | "markdown" | "mdown" | "mkd")) || mime_type == "text/markdown"
+}
+
+/// Render Markdown as styled lines. Line breaks within paragraphs are kept, as most Markdown
+/// files are wrapped by hand.
+pub(crate) fn render_markdown(text: &str) -> Vec<Line<'static>> {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut renderer = Renderer::default();
+    for event in Parser::new_ext(text, options) {
+        renderer.event(event);
+    }
+    renderer.flush();
+    renderer.lines
+}
+
+/// A table being read, rendered as a whole at its end.
+#[derive(Debug, Default)]
+struct TableBuilder {
+    alignments: Vec<Alignment>,
+    rows: Vec<Vec<String>>,
+    cell: String,
+}
+
+#[derive(Debug, Default)]
+struct Renderer {
+    lines: Vec<Line<'static>>,
+    /// The line being built.
+    spans: Vec<Span<'static>>,
+    /// Inline styles, innermost last.
+    styles: Vec<Style>,
+    quote_depth: usize,
+    /// The next number of each enclosing list, `None` for bullet lists, and the width of its
+    /// item markers.
+    lists: Vec<(Option<u64>, usize)>,
+    /// The marker of a list item starting, to go before its first line.
+    item_marker: Option<String>,
+    /// The language and text of a code block being read.
+    code: Option<(String, String)>,
+    table: Option<TableBuilder>,
+    /// The targets of the links being read.
+    links: Vec<String>,
+    /// The quote depth at the end of the last block, to separate it from the next one by an
+    /// empty line.
+    blank: Option<usize>,
+}
+
+impl Renderer {
+    fn event(&mut self, event: Event) {
+        if let Some((_, code)) = self.code.as_mut() {
+            match event {
+                Event::Text(text) => code.push_str(&text),
+                Event::End(TagEnd::CodeBlock) => self.code_block(),
+                _ => {},
+            }
+            return;
+        }
+        if let Some(table) = self.table.as_mut() {
+            match event {
+                Event::Text(text) | Event::Code(text) => table.cell.push_str(&text),
+                Event::End(TagEnd::TableCell) => {
+                    let cell = std::mem::take(&mut table.cell);
+                    if let Some(row) = table.rows.last_mut() {
+                        row.push(cell);
+                    }
+                },
+                Event::Start(Tag::TableHead) | Event::Start(Tag::TableRow) => table.rows.push(vec![]),
+                Event::End(TagEnd::Table) => self.table(),
+                _ => {},
+            }
+            return;
+        }
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.push(text.to_string(), self.style()),
+            Event::Code(text) => self.push(text.to_string(), Style::default().fg(Color::Yellow)),
+            Event::Html(html) | Event::InlineHtml(html) => {
+                let style = Style::default().fg(Color::DarkGray);
+                for (i, line) in html.trim_end_matches('\n').split('\n').enumerate() {
+                    if i > 0 {
+                        self.flush();
+                    }
+                    self.push(line.to_string(), style);
+                }
+            },
+            Event::FootnoteReference(name) => self.push(format!("[^{}]", name), self.style()),
+            Event::SoftBreak | Event::HardBreak => self.flush(),
+            Event::Rule => {
+                self.flush();
+                self.push("─".repeat(RULE_WIDTH), Style::default().fg(Color::DarkGray));
+                self.end_block();
+            },
+            Event::TaskListMarker(checked) => {
+                let marker = if checked { "[x] " } else { "[ ] " };
+                self.push(marker.to_string(), self.style());
+            },
+            Event::InlineMath(text) | Event::DisplayMath(text) => self.push(text.to_string(), self.style()),
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Heading { level, .. } => {
+                let style = match level {
+                    HeadingLevel::H1 => Style::default().fg(Color::LightYellow).add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                    HeadingLevel::H2 => Style::default().fg(Color::LightYellow).add_modifier(Modifier::BOLD),
+                    _ => Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+                };
+                self.styles.push(style);
+            },
+            Tag::BlockQuote(_) => {
+                self.flush();
+                self.quote_depth += 1;
+            },
+            Tag::CodeBlock(kind) => {
+                self.flush();
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info.split([' ', ',']).next().unwrap_or_default().to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                self.code = Some((language, String::new()));
+            },
+            Tag::List(start) => {
+                self.flush();
+                let width = match start {
+                    Some(start) => format!("{}. ", start).len(),
+                    None => 2,
+                };
+                self.lists.push((start, width));
+            },
+            Tag::Item => {
+                self.flush();
+                if let Some((number, width)) = self.lists.last_mut() {
+                    let marker = match number {
+                        Some(n) => {
+                            *n += 1;
+                            format!("{:<width$}", format!("{}.", *n - 1), width = *width)
+                        },
+                        None => "• ".to_string(),
+                    };
+                    self.item_marker = Some(marker);
+                }
+            },
+            Tag::Emphasis => self.styles.push(self.style().add_modifier(Modifier::ITALIC)),
+            Tag::Strong => self.styles.push(self.style().add_modifier(Modifier::BOLD)),
+            Tag::Strikethrough => self.styles.push(self.style().add_modifier(Modifier::CROSSED_OUT)),
+            Tag::Link { dest_url, .. } => {
+                self.styles.push(self.style().fg(Color::LightBlue).add_modifier(Modifier::UNDERLINED));
+                self.links.push(dest_url.to_string());
+            },
+            Tag::Image { dest_url, .. } => {
+                self.push("[image: ".to_string(), Style::default().fg(Color::Magenta));
+                self.styles.push(Style::default().fg(Color::Magenta));
+                self.links.push(dest_url.to_string());
+            },
+            Tag::Table(alignments) => {
+                self.flush();
+                self.table = Some(TableBuilder { alignments, ..Default::default() });
+            },
+            _ => {},
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph | TagEnd::HtmlBlock => self.end_block(),
+            TagEnd::Heading(_) => {
+                self.styles.pop();
+                self.end_block();
+            },
+            TagEnd::BlockQuote(_) => {
+                self.flush();
+                self.quote_depth -= 1;
+                if self.quote_depth == 0 {
+                    self.end_block();
+                }
+            },
+            TagEnd::List(_) => {
+                self.flush();
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.end_block();
+                }
+            },
+            TagEnd::Item => self.flush(),
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough => {
+                self.styles.pop();
+            },
+            TagEnd::Link => {
+                self.styles.pop();
+                let url = self.links.pop().unwrap_or_default();
+                let text: String = self.spans.iter().map(|span| span.content.as_ref()).collect();
+                // links within the file, and plain URLs, are clear enough as they are
+                if !url.starts_with('#') && !text.ends_with(url.as_str()) {
+                    self.push(format!(" ({})", url), Style::default().fg(Color::DarkGray));
+                }
+            },
+            TagEnd::Image => {
+                self.styles.pop();
+                self.links.pop();
+                self.push("]".to_string(), Style::default().fg(Color::Magenta));
+            },
+            _ => {},
+        }
+    }
+
+    fn style(&self) -> Style {
+        self.styles.last().copied().unwrap_or_default()
+    }
+
+    fn push(&mut self, text: String, style: Style) {
+        if self.spans.is_empty() {
+            let prefix = self.prefix();
+            self.spans.extend(prefix);
+        }
+        self.spans.push(Span::styled(text, style));
+    }
+
+    /// What goes before a line: bars for block quotes, then the list item marker, or the
+    /// indentation of the item's following lines. Starts with an empty line after a block.
+    fn prefix(&mut self) -> Vec<Span<'static>> {
+        let bar_style = Style::default().fg(Color::DarkGray);
+        if let Some(depth) = self.blank.take() {
+            let depth = depth.min(self.quote_depth);
+            self.lines.push(Line::styled("│ ".repeat(depth), bar_style));
+        }
+        let mut prefix = vec![];
+        if self.quote_depth > 0 {
+            prefix.push(Span::styled("│ ".repeat(self.quote_depth), bar_style));
+        }
+        let indent: usize = self.lists.iter().map(|(_, width)| width).sum();
+        match self.item_marker.take() {
+            Some(marker) => {
+                let outer = indent - self.lists.last().map(|(_, width)| *width).unwrap_or_default();
+                prefix.push(Span::raw(" ".repeat(outer)));
+                prefix.push(Span::styled(marker, Style::default().fg(Color::LightCyan)));
+            },
+            None if indent > 0 => prefix.push(Span::raw(" ".repeat(indent))),
+            None => {},
+        }
+        prefix
+    }
+
+    /// End the line being built, if any.
+    fn flush(&mut self) {
+        if !self.spans.is_empty() {
+            self.lines.push(Line::from(std::mem::take(&mut self.spans)));
+        }
+    }
+
+    /// End a paragraph (or the like), to be followed by an empty line unless it is in a list.
+    fn end_block(&mut self) {
+        self.flush();
+        if self.lists.is_empty() && !self.lines.is_empty() {
+            self.blank = Some(self.quote_depth);
+        }
+    }
+
+    /// Add the code block that was read, highlighted if its language is known.
+    fn code_block(&mut self) {
+        let Some((language, code)) = self.code.take() else {
+            return;
+        };
+        let lines: Vec<String> = code.trim_end_matches('\n').split('\n').map(str::to_string).collect();
+        let highlighted = highlight_snippet(&language, lines.clone())
+            .unwrap_or_else(|| lines.into_iter().map(|line| Line::styled(line, Style::default().fg(Color::Yellow))).collect());
+        for line in highlighted {
+            let mut spans = self.prefix();
+            spans.push(Span::raw("    "));
+            spans.extend(line.spans);
+            self.lines.push(Line::from(spans));
+        }
+        self.end_block();
+    }
+
+    /// Add the table that was read, with aligned columns and a line below the header.
+    fn table(&mut self) {
+        let Some(table) = self.table.take() else {
+            return;
+        };
+        let columns = table.rows.iter().map(Vec::len).max().unwrap_or_default();
+        let widths: Vec<usize> = (0..columns)
+            .map(|i| table.rows.iter().filter_map(|row| row.get(i)).map(|cell| cell.chars().count()).max().unwrap_or_default())
+            .collect();
+        let border_style = Style::default().fg(Color::DarkGray);
+        for (i, row) in table.rows.iter().enumerate() {
+            let mut spans = self.prefix();
+            for (column, width) in widths.iter().enumerate() {
+                if column > 0 {
+                    spans.push(Span::styled(" │ ", border_style));
+                }
+                let cell = row.get(column).map(String::as_str).unwrap_or_default();
+                let cell = match table.alignments.get(column) {
+                    Some(Alignment::Right) => format!("{:>width$}", cell),
+                    Some(Alignment::Center) => format!("{:^width$}", cell),
+                    _ => format!("{:<width$}", cell),
+                };
+                let style = match i {
+                    0 => Style::default().add_modifier(Modifier::BOLD),
+                    _ => Style::default(),
+                };
+                spans.push(Span::styled(cell, style));
+            }
+            self.lines.push(Line::from(spans));
+            if i == 0 {
+                let mut spans = self.prefix();
+                let separator: Vec<String> = widths.iter().map(|width| "─".repeat(*width)).collect();
+                spans.push(Span::styled(separator.join("─┼─"), border_style));
+                self.lines.push(Line::from(spans));
+            }
+        }
+        self.end_block();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(text: &str) -> Vec<String> {
+        render_markdown(text).iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let lines = render_markdown("# Title\n\nSome *emphasis* and `code`,\nsee [docs](https://x.org).\n");
+        assert_eq!(vec!["Title", "", "Some emphasis and code,", "see docs (https://x.org)."],
+                   lines.iter().map(|line| line.to_string()).collect::<Vec<String>>());
+        assert!(lines[0].spans[0].style.add_modifier.contains(Modifier::UNDERLINED));
+        let emphasis = lines[2].spans.iter().find(|span| span.content == "emphasis").unwrap();
+        assert!(emphasis.style.add_modifier.contains(Modifier::ITALIC));
+        let code = lines[2].spans.iter().find(|span| span.content == "code").unwrap();
+        assert_eq!(Some(Color::Yellow), code.style.fg);
+    }
+
+    #[test]
+    fn test_render_blocks() {
+        assert_eq!(vec!["• one", "• two", "  1. nested", "     more", "  2. [x] done", "", "after"],
+                   render("- one\n- two\n  1. nested\n     more\n  2. [x] done\n\nafter"));
+        assert_eq!(vec!["│ quoted", "│ text", "│ ", "│ │ deeper"], render("> quoted\n> text\n>\n> > deeper\n"));
+        assert_eq!(vec!["    fn main() {}", "", "    plain"], render("```rust\nfn main() {}\n```\n\n    plain\n"));
+        assert_eq!(vec!["a    │ bb", "─────┼───", "long │  1"], render("| a | bb |\n|---|--:|\n| long | 1 |\n"));
+        assert_eq!(vec!["[image: logo]", "", "────────────────────────────────────────"], render("![logo](a.png)\n\n---\n"));
+    }
+}
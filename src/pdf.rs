@@ -0,0 +1,183 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::Result;
+use log::debug;
+use lopdf::{decode_text_string, Document, Object};
+
+use crate::worker::{Pending, Worker};
+
+/// PDFs bigger than this are not parsed, as they are read into memory as a whole.
+pub const MAX_PDF_BYTES: u64 = 64 * 1024 * 1024;
+/// How many pages of text are extracted.
+const MAX_PDF_PAGES: u32 = 5;
+/// How long extracting the text may take, as malformed PDFs may keep the parser busy.
+const PDF_TIMEOUT: Duration = Duration::from_secs(5);
+/// How many PDFs are parsed at the same time, including parsers stuck on malformed ones.
+const PDF_THREADS: usize = 2;
+
+/// The metadata and the text of the first pages of a PDF.
+#[derive(Debug)]
+pub struct PdfText {
+    pub version: String,
+    pub pages: usize,
+    pub lines: Vec<String>,
+}
+
+/// Recognize a PDF by its first bytes.
+pub(crate) fn is_pdf(path: &Path) -> bool {
+    let mut magic = [0; 5];
+    File::open(path).and_then(|mut file| file.read_exact(&mut magic)).is_ok() && magic == *b"%PDF-"
+}
+
+/// Extract the text of the PDF at `path` in a worker thread. The pending text is an error if
+/// it took longer than `PDF_TIMEOUT`; dropping it skips the PDF, unless parsing it started already.
+pub(crate) fn load_pdf(path: &Path) -> Pending<PdfText> {
+    static WORKER: OnceLock<Worker> = OnceLock::new();
+    let path = path.to_path_buf();
+    WORKER.get_or_init(|| Worker::new("pdf", PDF_THREADS).with_timeout(PDF_TIMEOUT)).run(move || {
+        let result = read_pdf(&path);
+        if let Err(e) = &result {
+            debug!("unable to read {}: {}", path.display(), e);
+        }
+        result
+    })
+}
+
+fn read_pdf(path: &Path) -> Result<PdfText> {
+    let document = Document::load(path)?;
+    let pages = document.get_pages();
+    let mut lines = vec![];
+    for (key, label) in [(b"Title".as_slice(), "Title"), (b"Author", "Author"), (b"Subject", "Subject"),
+                         (b"Creator", "Creator"), (b"Producer", "Producer")] {
+        if let Some(value) = info_entry(&document, key) {
+            lines.push(format!("{}: {}", label, value));
+        }
+    }
+    if document.is_encrypted() {
+        lines.push("Encrypted: yes".to_string());
+    }
+    for &page in pages.keys().take(MAX_PDF_PAGES as usize) {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.push(format!("─── page {} ───", page));
+        match document.extract_text(&[page]) {
+            Ok(text) => {
+                // blank lines come in runs, between text objects
+                let mut blank = false;
+                for line in text.lines().map(str::trim_end) {
+                    if !(line.is_empty() && blank) {
+                        lines.push(line.to_string());
+                    }
+                    blank = line.is_empty();
+                }
+            },
+            Err(e) => lines.push(format!("unable to extract the text: {}", e)),
+        }
+    }
+    if pages.len() > MAX_PDF_PAGES as usize {
+        lines.push(String::new());
+        lines.push(format!("({} more pages)", pages.len() - MAX_PDF_PAGES as usize));
+    }
+    Ok(PdfText {
+        version: document.version.clone(),
+        pages: pages.len(),
+        lines,
+    })
+}
+
+/// A non-empty text entry of the document information dictionary.
+fn info_entry(document: &Document, key: &[u8]) -> Option<String> {
+    let (_, info) = document.dereference(document.trailer.get(b"Info").ok()?).ok()?;
+    let Object::Dictionary(info) = info else {
+        return None;
+    };
+    let (_, value) = document.dereference(info.get(key).ok()?).ok()?;
+    let value = decode_text_string(value).ok()?;
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::content::{Content, Operation};
+    use lopdf::{dictionary, Stream};
+
+    /// Write a PDF with a title and a line of text on each page.
+    fn write_pdf(path: &Path, title: &str, pages: &[&str]) {
+        let mut document = Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+        let font_id = document.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+        });
+        let resources_id = document.add_object(dictionary! {
+            "Font" => dictionary! { "F1" => font_id },
+        });
+        let kids: Vec<Object> = pages
+            .iter()
+            .map(|text| {
+                let content = Content {
+                    operations: vec![
+                        Operation::new("BT", vec![]),
+                        Operation::new("Tf", vec!["F1".into(), 12.into()]),
+                        Operation::new("Td", vec![100.into(), 600.into()]),
+                        Operation::new("Tj", vec![Object::string_literal(*text)]),
+                        Operation::new("ET", vec![]),
+                    ],
+                };
+                let content_id = document.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+                document.add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "Contents" => content_id,
+                }).into()
+            })
+            .collect();
+        document.objects.insert(pages_id, Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => pages.len() as i64,
+            "Resources" => resources_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        }));
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        let info_id = document.add_object(dictionary! {
+            "Title" => Object::string_literal(title),
+            "Producer" => Object::string_literal("lsls tests"),
+        });
+        document.trailer.set("Root", catalog_id);
+        document.trailer.set("Info", info_id);
+        document.save(path).unwrap();
+    }
+
+    #[test]
+    fn test_read_pdf() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("a.pdf");
+        let pages: Vec<String> = (1..=7).map(|i| format!("Text of page {}", i)).collect();
+        write_pdf(&path, "A title", &pages.iter().map(String::as_str).collect::<Vec<&str>>());
+        assert!(is_pdf(&path));
+
+        let pdf = load_pdf(&path).recv().unwrap();
+        assert_eq!(("1.5", 7), (pdf.version.as_str(), pdf.pages));
+        assert_eq!(vec!["Title: A title", "Producer: lsls tests", "", "─── page 1 ───", "Text of page 1"], pdf.lines[..5]);
+        assert!(pdf.lines.contains(&"Text of page 5".to_string()));
+        assert!(!pdf.lines.contains(&"Text of page 6".to_string()));
+        assert_eq!("(2 more pages)", pdf.lines.last().unwrap());
+
+        let path = tmp.path().join("broken.pdf");
+        std::fs::write(&path, "%PDF-1.4\nnot really\n").unwrap();
+        assert!(is_pdf(&path));
+        assert!(load_pdf(&path).recv().is_err());
+    }
+}
@@ -0,0 +1,596 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Cursor};
+use std::path::Path;
+
+use image::ImageFormat;
+use log::debug;
+use ratatui::text::Line;
+use regex::Regex;
+
+use crate::archive::{load_entries, load_member, ArchiveEntry, ArchiveFormat};
+use crate::hexdump::{hex_dump, hex_rows};
+use crate::highlight::{Highlighter, SyntaxState};
+use crate::markdown::render_markdown;
+use crate::pdf::{load_pdf, PdfText};
+use crate::structured::{pretty_print, read_table, DataFormat, DataTable};
+use crate::thumbnail::{load_thumbnail, Thumbnail};
+use crate::worker::Pending;
+
+/// Lines of a text file are read in chunks of this many as they are scrolled into view.
+const CHUNK_LINES: usize = 500;
+/// Previews stop growing at this many lines, e.g. when jumping to the end of a huge log.
+const MAX_PREVIEW_LINES: usize = 200_000;
+/// How many rows of a hex dump are read at a time, around the visible ones.
+const HEX_WINDOW_ROWS: usize = 256;
+/// How many rows of a CSV file are shown at most.
+const MAX_TABLE_ROWS: usize = 10_000;
+
+/// The part of a previewed text file which hasn't been read yet.
+struct TextSource {
+    reader: Box<dyn BufRead>,
+    /// To highlight the following lines like the ones read so far.
+    syntax: Option<SyntaxState>,
+}
+
+impl fmt::Debug for TextSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TextSource").field("syntax", &self.syntax).finish_non_exhaustive()
+    }
+}
+
+/// A binary file shown as a hex dump. Only the rows around the visible ones are read.
+#[derive(Debug)]
+struct HexSource {
+    file: File,
+    rows: usize,
+}
+
+/// A search within the preview.
+#[derive(Debug)]
+pub struct PreviewSearch {
+    /// What the user typed, for display.
+    pub pattern: String,
+    regex: Regex,
+}
+
+/// What the preview pane shows, and how far it is scrolled.
+#[derive(Debug)]
+pub struct Preview {
+    pub lines: Vec<Line<'static>>,
+    /// The index of `lines[0]`; hex dumps only keep the rows around the visible ones.
+    pub window_start: usize,
+    /// Replaces "Preview" as the title of the preview pane.
+    pub title: Option<String>,
+    /// A line to highlight (e.g. a search hit).
+    pub highlight: Option<usize>,
+    /// The line number of the first line, if the preview shows the lines of a file.
+    pub first_line: Option<usize>,
+    /// The index of the topmost visible line.
+    pub scroll: usize,
+    /// Wrap long lines rather than cutting them off.
+    pub wrap: bool,
+    pub line_numbers: bool,
+    /// Show Markdown files rendered, rather than as text.
+    pub render_markdown: bool,
+    pub search: Option<PreviewSearch>,
+    /// A CSV or TSV file, drawn as a table; `lines` are its rows as text, to search them.
+    pub table: Option<DataTable>,
+    source: Option<TextSource>,
+    hex: Option<HexSource>,
+    /// An image being decoded in a worker thread.
+    image: Option<Pending<Thumbnail>>,
+    /// A PDF whose text is being extracted in a worker thread.
+    pdf: Option<Pending<PdfText>>,
+    /// The first entries of an archive, being read in a worker thread.
+    archive: Option<Pending<(Vec<ArchiveEntry>, bool)>>,
+    /// A member of an archive being browsed (its path in there), being read in a worker thread.
+    member: Option<(String, Pending<Vec<u8>>)>,
+}
+
+impl Default for Preview {
+    fn default() -> Self {
+        Self {
+            lines: vec![],
+            window_start: 0,
+            title: None,
+            highlight: None,
+            first_line: None,
+            scroll: 0,
+            wrap: false,
+            line_numbers: true,
+            render_markdown: true,
+            search: None,
+            table: None,
+            source: None,
+            hex: None,
+            image: None,
+            pdf: None,
+            archive: None,
+            member: None,
+        }
+    }
+}
+
+impl Preview {
+    /// Forget what is shown, but keep the view settings (wrapping, line numbers, rendering).
+    pub(crate) fn clear(&mut self) {
+        self.lines.clear();
+        self.window_start = 0;
+        self.title = None;
+        self.highlight = None;
+        self.first_line = None;
+        self.scroll = 0;
+        self.search = None;
+        self.table = None;
+        self.source = None;
+        self.hex = None;
+        self.image = None;
+        self.pdf = None;
+        self.archive = None;
+        self.member = None;
+    }
+
+    /// Show the first `count` lines of the text file at `path`; the rest is read when it is
+    /// scrolled to.
+    pub(crate) fn load_text(&mut self, path: &Path, mime_type: &str, count: usize, highlighter: &mut Highlighter) -> io::Result<()> {
+        self.load_reader(path, mime_type, Box::new(BufReader::new(File::open(path)?)), count, highlighter);
+        Ok(())
+    }
+
+    /// Show the first `count` lines read from `reader`, e.g. a decompressed stream; `path`
+    /// picks the syntax to highlight them with.
+    pub(crate) fn load_reader(&mut self, path: &Path, mime_type: &str, mut reader: Box<dyn BufRead>, count: usize, highlighter: &mut Highlighter) {
+        let lines = read_lines(&mut reader, count);
+        let complete = lines.len() < count;
+        let (lines, syntax) = highlighter.highlight(path, mime_type, lines);
+        self.lines = lines;
+        self.first_line = Some(1);
+        if !complete {
+            self.source = Some(TextSource { reader, syntax });
+        }
+    }
+
+    /// Show the JSON, YAML or TOML file at `path` pretty-printed. If it is invalid, it is shown
+    /// as it is, scrolled to the line of the error; `height` rows are visible.
+    pub(crate) fn load_data(&mut self, path: &Path, format: DataFormat, mime_type: &str, count: usize, height: usize,
+                            highlighter: &mut Highlighter) -> io::Result<()> {
+        let text = String::from_utf8_lossy(&fs::read(path)?).to_string();
+        match pretty_print(&text, format) {
+            Ok(pretty) => {
+                self.load_reader(path, mime_type, Box::new(Cursor::new(pretty.into_bytes())), count, highlighter);
+                // the lines don't match those of the file anymore
+                self.first_line = None;
+                self.title = Some(format!("{} (pretty-printed)", format));
+            },
+            Err(e) => {
+                self.load_text(path, mime_type, count, highlighter)?;
+                self.title = Some(format!("{} - invalid: {}", format, e));
+                if let Some(line) = e.line {
+                    self.go_to_line(line.saturating_sub(1), height);
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Show the Markdown file at `path` rendered.
+    pub(crate) fn load_markdown(&mut self, path: &Path) -> io::Result<()> {
+        let text = String::from_utf8_lossy(&fs::read(path)?).to_string();
+        self.lines = render_markdown(&text);
+        self.title = Some("Markdown - rendered".to_string());
+        Ok(())
+    }
+
+    /// Show the CSV or TSV file at `path` as a table.
+    pub(crate) fn load_table(&mut self, path: &Path, format: DataFormat) -> anyhow::Result<()> {
+        let table = read_table(path, format, MAX_TABLE_ROWS)?;
+        self.lines = table.rows.iter().map(|row| Line::from(row.join("  "))).collect();
+        let more = if table.more { "+" } else { "" };
+        self.title = Some(format!("{} - {} columns, {}{} rows", format, table.widths.len(), table.rows.len(), more));
+        self.table = Some(table);
+        Ok(())
+    }
+
+    /// Show the binary file at `path` as a hex dump; `height` rows are visible.
+    pub(crate) fn load_hex(&mut self, path: &Path, height: usize) -> io::Result<()> {
+        let file = File::open(path)?;
+        let rows = hex_rows(file.metadata()?.len());
+        self.hex = Some(HexSource { file, rows });
+        self.load_window(height);
+        Ok(())
+    }
+
+    /// Show the image at `path` as a thumbnail of up to `cols` x `rows` cells, once it is decoded.
+    pub(crate) fn load_image(&mut self, path: &Path, format: ImageFormat, cols: u16, rows: u16) {
+        self.image = Some(load_thumbnail(path, format, cols, rows));
+        self.lines = vec![Line::from("decoding...")];
+    }
+
+    /// Pick up the thumbnail of the image being decoded. Returns `true` if the preview changed.
+    pub(crate) fn poll_image(&mut self) -> bool {
+        let Some(pending) = self.image.as_ref() else {
+            return false;
+        };
+        match pending.try_recv() {
+            Some(Ok(thumbnail)) => {
+                let title = self.title.take().unwrap_or("Image".to_string());
+                self.title = Some(format!("{} - {}x{}, {}", title, thumbnail.width, thumbnail.height, thumbnail.color_type));
+                self.lines = thumbnail.lines;
+            },
+            Some(Err(e)) => self.lines = vec![Line::from(format!("unable to decode the image: {}", e))],
+            None => return false,
+        }
+        self.image = None;
+        true
+    }
+
+    /// Show the text of the PDF at `path`, once it is extracted.
+    pub(crate) fn load_pdf(&mut self, path: &Path) {
+        self.pdf = Some(load_pdf(path));
+        self.lines = vec![Line::from("extracting text...")];
+    }
+
+    /// Pick up the text of the PDF being read. Returns `true` if the preview changed.
+    pub(crate) fn poll_pdf(&mut self) -> bool {
+        let Some(pending) = self.pdf.as_ref() else {
+            return false;
+        };
+        match pending.try_recv() {
+            Some(Ok(pdf)) => {
+                let title = self.title.take().unwrap_or("PDF".to_string());
+                self.title = Some(format!("{} - version {}, {} pages", title, pdf.version, pdf.pages));
+                self.lines = pdf.lines.into_iter().map(Line::from).collect();
+            },
+            Some(Err(e)) => self.lines = vec![Line::from(format!("unable to read the PDF: {}", e))],
+            None => return false,
+        }
+        self.pdf = None;
+        true
+    }
+
+    /// Show the first `count` entries of the archive at `path`, once they are read.
+    pub(crate) fn load_archive(&mut self, path: &Path, format: ArchiveFormat, count: usize) {
+        self.archive = Some(load_entries(path, format, count));
+        self.title = Some(format!("Archive - {} (<ENTER> to browse)", format));
+        self.lines = vec![Line::from("reading...")];
+    }
+
+    /// Pick up the entries of the archive being read. Returns `true` if the preview changed.
+    pub(crate) fn poll_archive(&mut self) -> bool {
+        let Some(pending) = self.archive.as_ref() else {
+            return false;
+        };
+        match pending.try_recv() {
+            Some(Ok((entries, more))) => {
+                self.lines = entries.iter().map(|e| Line::from(e.summary())).collect();
+                if more {
+                    self.lines.push("...".into());
+                }
+            },
+            Some(Err(e)) => self.lines = vec![Line::from(format!("unable to read the archive: {}", e))],
+            None => return false,
+        }
+        self.archive = None;
+        true
+    }
+
+    /// Show up to `limit` bytes of the member at `member_path` of the archive at `path`, once
+    /// they are read.
+    pub(crate) fn load_member(&mut self, path: &Path, format: ArchiveFormat, member_path: &str, limit: u64) {
+        self.member = Some((member_path.to_string(), load_member(path, format, member_path, limit)));
+        self.title = Some(member_path.to_string());
+        self.lines = vec![Line::from("reading...")];
+    }
+
+    /// Pick up the member being read: the first `count` lines of text, highlighted, or else
+    /// `rows` rows of a hex dump. Returns `true` if the preview changed.
+    pub(crate) fn poll_member(&mut self, count: usize, rows: usize, highlighter: &mut Highlighter) -> bool {
+        let Some((member_path, pending)) = self.member.as_ref() else {
+            return false;
+        };
+        match pending.try_recv() {
+            Some(Ok(contents)) => {
+                let mime_type = tree_magic_mini::from_u8(&contents);
+                if is_text_mime(mime_type) {
+                    let lines: Vec<String> = String::from_utf8_lossy(&contents)
+                        .lines()
+                        .take(count)
+                        .map(str::to_string)
+                        .collect();
+                    self.lines = highlighter.highlight(Path::new(member_path), mime_type, lines).0;
+                    self.first_line = Some(1);
+                } else {
+                    self.lines = hex_dump(&mut Cursor::new(contents), 0, rows).unwrap_or_default();
+                }
+                self.title = Some(format!("{} ({})", member_path, mime_type));
+            },
+            Some(Err(e)) => self.lines = vec![Line::from(format!("unable to read {}: {}", member_path, e))],
+            None => return false,
+        }
+        self.member = None;
+        true
+    }
+
+    /// The number of lines (or hex dump rows), including those which haven't been read.
+    pub(crate) fn line_count(&self) -> usize {
+        match &self.hex {
+            Some(hex) => hex.rows,
+            None => self.lines.len(),
+        }
+    }
+
+    /// The lines to show in `height` rows, with their index.
+    pub(crate) fn visible_lines(&self, height: usize) -> impl Iterator<Item = (usize, &Line<'static>)> {
+        self.lines
+            .iter()
+            .enumerate()
+            .skip(self.scroll.saturating_sub(self.window_start))
+            .take(height)
+            .map(|(i, line)| (i + self.window_start, line))
+    }
+
+    /// Read the rows of a hex dump around the visible ones, unless they were read already.
+    fn load_window(&mut self, height: usize) {
+        let Some(hex) = self.hex.as_mut() else {
+            return;
+        };
+        let end = (self.scroll + height).min(hex.rows);
+        if self.scroll >= self.window_start && end <= self.window_start + self.lines.len() && !self.lines.is_empty() {
+            return;
+        }
+        let start = self.scroll.saturating_sub(HEX_WINDOW_ROWS / 2);
+        match hex_dump(&mut hex.file, start, HEX_WINDOW_ROWS.max(height * 2)) {
+            Ok(lines) => {
+                self.window_start = start;
+                self.lines = lines;
+            },
+            Err(e) => debug!("unable to read hex dump rows: {}", e),
+        }
+    }
+
+    /// Whether the previewed file goes on beyond the lines read so far.
+    pub(crate) fn has_more(&self) -> bool {
+        self.source.is_some()
+    }
+
+    /// Read on until there are `count` lines, or the file (or `MAX_PREVIEW_LINES`) ends.
+    fn load_until(&mut self, count: usize) {
+        let count = count.min(MAX_PREVIEW_LINES);
+        while self.lines.len() < count {
+            let Some(source) = self.source.as_mut() else {
+                return;
+            };
+            let lines = read_lines(&mut source.reader, CHUNK_LINES);
+            let done = lines.len() < CHUNK_LINES || self.lines.len() + lines.len() >= MAX_PREVIEW_LINES;
+            match source.syntax.as_mut() {
+                Some(syntax) => self.lines.extend(syntax.highlight(lines)),
+                None => self.lines.extend(lines.into_iter().map(Line::from)),
+            }
+            if done {
+                self.source = None;
+            }
+        }
+    }
+
+    /// Scroll down by `rows` lines, or up if negative. `height` is the number of visible rows.
+    pub(crate) fn scroll_by(&mut self, rows: isize, height: usize) {
+        let scroll = self.scroll.saturating_add_signed(rows);
+        self.load_until(scroll + height);
+        self.scroll = scroll.min(self.max_scroll(height));
+        self.load_window(height);
+    }
+
+    pub(crate) fn scroll_to_top(&mut self, height: usize) {
+        self.scroll = 0;
+        self.load_window(height);
+    }
+
+    pub(crate) fn scroll_to_bottom(&mut self, height: usize) {
+        self.load_until(usize::MAX);
+        self.scroll = self.line_count().saturating_sub(height);
+        self.load_window(height);
+    }
+
+    fn max_scroll(&self, height: usize) -> usize {
+        match self.wrap {
+            // wrapped lines may take up all the rows
+            true => self.line_count().saturating_sub(1),
+            false => self.line_count().saturating_sub(height),
+        }
+    }
+
+    /// Search for `regex`, going to the first match from the top visible line on. Returns
+    /// `false` if nothing matches.
+    pub(crate) fn search(&mut self, pattern: &str, regex: Regex, height: usize) -> bool {
+        self.search = Some(PreviewSearch { pattern: pattern.to_string(), regex });
+        self.highlight = None;
+        self.go_to_match(self.scroll, true, height)
+    }
+
+    /// Go to the next (or previous) match of the search, wrapping around at the end of the
+    /// file. Returns `false` if nothing matches.
+    pub(crate) fn next_match(&mut self, forward: bool, height: usize) -> bool {
+        let start = match (self.highlight, forward) {
+            (Some(line), true) => line + 1,
+            (Some(line), false) => line,
+            (None, _) => self.scroll,
+        };
+        self.go_to_match(start, forward, height)
+    }
+
+    /// Highlight the first matching line at or after `start` (before it, if not `forward`),
+    /// and scroll it into view.
+    fn go_to_match(&mut self, start: usize, forward: bool, height: usize) -> bool {
+        let Some(search) = self.search.as_ref() else {
+            return false;
+        };
+        if self.hex.is_some() {
+            // the rows aren't all read, and bytes aren't lines anyway
+            return false;
+        }
+        let regex = search.regex.clone();
+        let is_match = |line: &Line| regex.is_match(&line.to_string());
+        let found = match forward {
+            true => {
+                let mut found = None;
+                let mut i = start;
+                loop {
+                    if i >= self.lines.len() {
+                        if !self.has_more() {
+                            break;
+                        }
+                        self.load_until(i + CHUNK_LINES);
+                        continue;
+                    }
+                    if is_match(&self.lines[i]) {
+                        found = Some(i);
+                        break;
+                    }
+                    i += 1;
+                }
+                found.or_else(|| self.lines.iter().take(start).position(is_match))
+            },
+            false => {
+                let before = self.lines.iter().take(start).rposition(is_match);
+                before.or_else(|| {
+                    self.load_until(usize::MAX);
+                    self.lines.iter().skip(start).rposition(is_match).map(|i| i + start)
+                })
+            },
+        };
+        let Some(line) = found else {
+            return false;
+        };
+        self.go_to_line(line, height);
+        true
+    }
+
+    /// Highlight the line at `index`, and scroll it into view.
+    fn go_to_line(&mut self, index: usize, height: usize) {
+        self.load_until(index + height);
+        self.highlight = Some(index);
+        if index < self.scroll || index >= self.scroll + height {
+            self.scroll = index.saturating_sub(height / 2).min(self.max_scroll(height));
+        }
+    }
+}
+
+/// Whether a file of this mime type can be searched (and previewed) as text.
+pub(crate) fn is_text_mime(mime_type: &str) -> bool {
+    mime_type.contains("text")
+        || mime_type.ends_with("json")
+        || mime_type.ends_with("xml")
+        || mime_type.ends_with("javascript")
+        || mime_type.ends_with("x-shellscript")
+}
+
+/// Read up to `count` lines; fewer means the end of the file was reached (or it is unreadable).
+fn read_lines(reader: &mut impl BufRead, count: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut buf = vec![];
+    while lines.len() < count {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {},
+        }
+        let line = String::from_utf8_lossy(&buf);
+        lines.push(line.trim_end_matches(['\n', '\r']).to_string());
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn numbered_file(dir: &Path, count: usize) -> std::path::PathBuf {
+        let path = dir.join("numbers.txt");
+        let text: String = (1..=count).map(|i| format!("line {}\n", i)).collect();
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_scroll_loads_lazily() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = numbered_file(tmp.path(), 2000);
+        let mut preview = Preview::default();
+        preview.load_text(&path, "text/plain", 50, &mut Highlighter::default()).unwrap();
+        assert_eq!(50, preview.lines.len());
+        assert!(preview.has_more());
+
+        preview.scroll_by(40, 20);
+        assert_eq!(40, preview.scroll);
+        assert_eq!(550, preview.lines.len());
+        preview.scroll_by(-100, 20);
+        assert_eq!(0, preview.scroll);
+
+        preview.scroll_to_bottom(20);
+        assert_eq!(2000, preview.lines.len());
+        assert!(!preview.has_more());
+        assert_eq!(1980, preview.scroll);
+        assert_eq!("line 2000", preview.lines[1999].to_string());
+        preview.scroll_by(10, 20);
+        assert_eq!(1980, preview.scroll);
+    }
+
+    #[test]
+    fn test_search() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = numbered_file(tmp.path(), 2000);
+        let mut preview = Preview::default();
+        preview.load_text(&path, "text/plain", 50, &mut Highlighter::default()).unwrap();
+
+        // matches beyond what was read are found
+        assert!(preview.search("line 1500", Regex::new("^line 1500$").unwrap(), 20));
+        assert_eq!(Some(1499), preview.highlight);
+        assert_eq!(1489, preview.scroll);
+
+        assert!(preview.search("99", Regex::new("99$").unwrap(), 20));
+        assert_eq!(Some(1498), preview.highlight);
+        assert_eq!(1489, preview.scroll);
+        assert!(preview.next_match(true, 20));
+        assert_eq!(Some(1598), preview.highlight);
+        assert_eq!(1588, preview.scroll);
+        assert!(preview.next_match(false, 20));
+        assert!(preview.next_match(false, 20));
+        assert_eq!(Some(1398), preview.highlight);
+        preview.scroll_to_top(20);
+        preview.highlight = None;
+        // wraps around to the end
+        assert!(preview.next_match(false, 20));
+        assert_eq!(Some(1998), preview.highlight);
+        assert!(preview.next_match(true, 20));
+        assert_eq!(Some(98), preview.highlight);
+
+        assert!(!preview.search("nope", Regex::new("nope").unwrap(), 20));
+        assert_eq!(None, preview.highlight);
+    }
+
+    #[test]
+    fn test_hex_paging() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("data.bin");
+        fs::write(&path, vec![0u8; 16 * 1000 + 5]).unwrap();
+        let mut preview = Preview::default();
+        preview.load_hex(&path, 20).unwrap();
+        assert_eq!(1001, preview.line_count());
+        assert_eq!(HEX_WINDOW_ROWS, preview.lines.len());
+
+        preview.scroll_by(500, 20);
+        assert_eq!(500, preview.scroll);
+        let (index, line) = preview.visible_lines(20).next().unwrap();
+        assert_eq!(500, index);
+        assert!(line.to_string().starts_with("00001f40 "));
+        assert!(preview.window_start <= 500 && preview.lines.len() <= HEX_WINDOW_ROWS);
+
+        preview.scroll_to_bottom(20);
+        assert_eq!(981, preview.scroll);
+        let (index, line) = preview.visible_lines(20).last().unwrap();
+        assert_eq!(1000, index);
+        assert!(line.to_string().starts_with("00003e80  00 00 00 00 00    "));
+    }
+}
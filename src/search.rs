@@ -0,0 +1,246 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Receiver, TryRecvError};
+use std::thread;
+
+use log::debug;
+use ratatui::widgets::ListState;
+use regex::Regex;
+
+use crate::preview::is_text_mime;
+use crate::walk::walk_files;
+
+/// The search stops once this many matching lines were found.
+const MAX_SEARCH_HITS: usize = 10_000;
+/// Matching lines are cut off after this many chars.
+const MAX_HIT_CHARS: usize = 500;
+/// How many paths the walker may queue up for the search threads.
+const SEARCH_QUEUE_SIZE: usize = 1024;
+
+/// A line matching the search pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    /// Relative to the search root.
+    pub path: PathBuf,
+    /// 1-based.
+    pub line_number: usize,
+    pub text: String,
+}
+
+/// A search for a pattern in the contents of all files below `root`. Files are read by worker
+/// threads; hits are collected by `poll()` on every tick.
+#[derive(Debug)]
+pub struct ContentSearch {
+    pub root: PathBuf,
+    pub pattern: String,
+    pub hits: Vec<SearchHit>,
+    pub state: ListState,
+    pub running: bool,
+    /// The search stopped at `MAX_SEARCH_HITS`.
+    pub truncated: bool,
+    files_searched: Arc<AtomicUsize>,
+    rx: Option<Receiver<Vec<SearchHit>>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl ContentSearch {
+    /// Start searching for `regex`. `pattern` is what the user typed, for display.
+    pub(crate) fn start(root: PathBuf, pattern: &str, regex: Regex, show_hidden: bool, respect_ignore: bool) -> Self {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let files_searched = Arc::new(AtomicUsize::new(0));
+        let (path_tx, path_rx) = sync_channel::<PathBuf>(SEARCH_QUEUE_SIZE);
+        let path_rx = Arc::new(Mutex::new(path_rx));
+        let (hit_tx, hit_rx) = channel::<Vec<SearchHit>>();
+
+        let walk_root = root.clone();
+        let walk_cancel = cancel.clone();
+        thread::spawn(move || {
+            walk_files(&walk_root, show_hidden, respect_ignore, &walk_cancel, &mut |path| path_tx.send(path.to_path_buf()).is_ok());
+        });
+
+        let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        for _ in 0..threads {
+            let (root, regex, cancel) = (root.clone(), regex.clone(), cancel.clone());
+            let (path_rx, hit_tx, files_searched) = (path_rx.clone(), hit_tx.clone(), files_searched.clone());
+            thread::spawn(move || {
+                loop {
+                    let path = match path_rx.lock() {
+                        Ok(rx) => rx.recv(),
+                        Err(_) => break,
+                    };
+                    let Ok(path) = path else {
+                        // the walk is over
+                        break;
+                    };
+                    if cancel.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let hits = search_file(&root, &path, &regex);
+                    files_searched.fetch_add(1, Ordering::Relaxed);
+                    if !hits.is_empty() && hit_tx.send(hits).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        Self {
+            root,
+            pattern: pattern.to_string(),
+            hits: vec![],
+            state: ListState::default(),
+            running: true,
+            truncated: false,
+            files_searched,
+            rx: Some(hit_rx),
+            cancel,
+        }
+    }
+
+    /// Collect the hits found since the last call. Returns `true` if there were any.
+    pub(crate) fn poll(&mut self) -> bool {
+        let mut received = false;
+        while let Some(rx) = self.rx.as_mut() {
+            match rx.try_recv() {
+                Ok(hits) => {
+                    let room = MAX_SEARCH_HITS - self.hits.len();
+                    if hits.len() > room {
+                        self.truncated = true;
+                        self.cancel.store(true, Ordering::Relaxed);
+                        self.rx = None;
+                        self.running = false;
+                    }
+                    self.hits.extend(hits.into_iter().take(room));
+                    received = true;
+                },
+                Err(TryRecvError::Empty) => {
+                    break;
+                },
+                Err(TryRecvError::Disconnected) => {
+                    debug!("search for {} done", self.pattern);
+                    self.rx = None;
+                    self.running = false;
+                }
+            }
+        }
+        if received && self.state.selected().is_none() {
+            self.state.select(Some(0));
+        }
+        received
+    }
+
+    pub(crate) fn files_searched(&self) -> usize {
+        self.files_searched.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn selected_hit(&self) -> Option<&SearchHit> {
+        self.state.selected().and_then(|i| self.hits.get(i))
+    }
+}
+
+impl Drop for ContentSearch {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The lines of a text file matching `regex`. Binary files have no hits.
+fn search_file(root: &Path, rel_path: &Path, regex: &Regex) -> Vec<SearchHit> {
+    let path = root.join(rel_path);
+    // symlinks are followed here, but only to regular files
+    match fs::metadata(&path) {
+        Ok(meta) if meta.is_file() && meta.len() > 0 => {},
+        _ => return vec![],
+    }
+    match tree_magic_mini::from_filepath(&path) {
+        Some(mime_type) if is_text_mime(mime_type) => {},
+        _ => return vec![],
+    }
+    let Ok(file) = File::open(&path) else {
+        return vec![];
+    };
+    let mut reader = BufReader::new(file);
+    let mut hits = vec![];
+    let mut buf = vec![];
+    let mut line_number = 0;
+    loop {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {},
+        }
+        line_number += 1;
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim_end_matches(['\n', '\r']);
+        if regex.is_match(line) {
+            hits.push(SearchHit {
+                path: rel_path.to_path_buf(),
+                line_number,
+                text: line.chars().take(MAX_HIT_CHARS).collect(),
+            });
+        }
+    }
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_search_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("a.txt"), "one\ntwo\r\nthree two\n").unwrap();
+        fs::write(tmp.path().join("b.bin"), b"\x7fELF\x02\x01\x01\x00\x00two\x00\x00").unwrap();
+
+        let regex = Regex::new("two").unwrap();
+        let hits = search_file(tmp.path(), Path::new("a.txt"), &regex);
+        assert_eq!(vec![2, 3], hits.iter().map(|h| h.line_number).collect::<Vec<usize>>());
+        assert_eq!("two", hits[0].text);
+        assert!(search_file(tmp.path(), Path::new("b.bin"), &regex).is_empty());
+    }
+
+    fn wait_until_done(search: &mut ContentSearch) {
+        let start = Instant::now();
+        while search.running && start.elapsed() < Duration::from_secs(5) {
+            search.poll();
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_content_search() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join("sub")).unwrap();
+        fs::write(tmp.path().join("a.txt"), "needle\n").unwrap();
+        fs::write(tmp.path().join("sub/b.txt"), "hay\nneedle\n").unwrap();
+        fs::write(tmp.path().join("c.txt"), "hay\n").unwrap();
+
+        let mut search = ContentSearch::start(tmp.path().to_path_buf(), "needle", Regex::new("needle").unwrap(), false, false);
+        wait_until_done(&mut search);
+        assert_eq!(3, search.files_searched());
+        let mut found: Vec<(String, usize)> = search.hits
+            .iter()
+            .map(|h| (h.path.to_string_lossy().to_string(), h.line_number))
+            .collect();
+        found.sort();
+        assert_eq!(vec![("a.txt".to_string(), 1), ("sub/b.txt".to_string(), 2)], found);
+        assert!(search.selected_hit().is_some());
+    }
+
+    #[test]
+    fn test_search_limit() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("a.txt"), "needle\n".repeat(MAX_SEARCH_HITS)).unwrap();
+
+        let mut search = ContentSearch::start(tmp.path().to_path_buf(), "needle", Regex::new("needle").unwrap(), false, false);
+        wait_until_done(&mut search);
+        // all hits fit, nothing was cut off
+        assert_eq!(MAX_SEARCH_HITS, search.hits.len());
+        assert!(!search.truncated);
+    }
+}
@@ -0,0 +1,197 @@
+use std::fmt;
+use std::path::Path;
+
+use anyhow::Result;
+use yaml_rust2::{YamlEmitter, YamlLoader};
+
+/// Data files larger than this are previewed as plain text, as they are parsed as a whole.
+pub const MAX_STRUCTURED_BYTES: u64 = 1024 * 1024;
+/// Table cells are cut off after this many chars.
+const MAX_COLUMN_WIDTH: usize = 40;
+
+/// Formats of data files which are previewed validated and pretty-printed, or as a table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataFormat {
+    Json,
+    Yaml,
+    Toml,
+    Csv,
+    Tsv,
+}
+
+impl DataFormat {
+    /// Recognize a data file by its extension.
+    pub(crate) fn detect(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "json" => Some(DataFormat::Json),
+            "yaml" | "yml" => Some(DataFormat::Yaml),
+            "toml" => Some(DataFormat::Toml),
+            "csv" => Some(DataFormat::Csv),
+            "tsv" => Some(DataFormat::Tsv),
+            _ => None,
+        }
+    }
+
+    /// Whether the format is shown as a table, rather than pretty-printed.
+    pub(crate) fn is_table(&self) -> bool {
+        matches!(self, DataFormat::Csv | DataFormat::Tsv)
+    }
+}
+
+impl fmt::Display for DataFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let output = match self {
+            DataFormat::Json => "JSON",
+            DataFormat::Yaml => "YAML",
+            DataFormat::Toml => "TOML",
+            DataFormat::Csv => "CSV",
+            DataFormat::Tsv => "TSV",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+/// Why a data file is invalid.
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    /// The line of the error (1-based), if known.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Validate `text`, and pretty-print it in the same format. Tables come back as they are.
+pub(crate) fn pretty_print(text: &str, format: DataFormat) -> Result<String, ParseError> {
+    let unknown_line = |e: &dyn fmt::Display| ParseError { line: None, message: e.to_string() };
+    match format {
+        DataFormat::Json => {
+            let value: serde_json::Value = serde_json::from_str(text)
+                .map_err(|e| ParseError { line: Some(e.line()), message: e.to_string() })?;
+            serde_json::to_string_pretty(&value).map_err(|e| unknown_line(&e))
+        },
+        DataFormat::Yaml => {
+            // a stream of documents, separated by `---`
+            let values = YamlLoader::load_from_str(text)
+                .map_err(|e| ParseError { line: Some(e.marker().line()), message: e.to_string() })?;
+            let mut documents = vec![];
+            for value in &values {
+                let mut document = String::new();
+                YamlEmitter::new(&mut document).dump(value).map_err(|e| unknown_line(&e))?;
+                // the emitter starts every document with a `---` of its own
+                let document = document.strip_prefix("---\n").unwrap_or(&document);
+                documents.push(format!("{}\n", document));
+            }
+            Ok(documents.join("---\n"))
+        },
+        DataFormat::Toml => {
+            let table: toml::Table = toml::from_str(text).map_err(|e| {
+                let line = e.span().map(|span| text[..span.start].matches('\n').count() + 1);
+                let message = match line {
+                    Some(line) => format!("{} at line {}", e.message().trim_end(), line),
+                    None => e.message().trim_end().to_string(),
+                };
+                ParseError { line, message }
+            })?;
+            toml::to_string_pretty(&table).map_err(|e| unknown_line(&e))
+        },
+        DataFormat::Csv | DataFormat::Tsv => Ok(text.to_string()),
+    }
+}
+
+/// The start of a CSV or TSV file.
+#[derive(Debug, Default, PartialEq)]
+pub struct DataTable {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    /// The widths of the columns in chars, up to `MAX_COLUMN_WIDTH`.
+    pub widths: Vec<usize>,
+    /// There are more rows than were read.
+    pub more: bool,
+}
+
+/// Read the header and up to `limit` rows of a CSV or TSV file. Rows may have more or fewer
+/// fields than the header.
+pub(crate) fn read_table(path: &Path, format: DataFormat, limit: usize) -> Result<DataTable> {
+    let delimiter = match format {
+        DataFormat::Tsv => b'\t',
+        _ => b',',
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_path(path)?;
+    let fields = |record: &csv::ByteRecord| -> Vec<String> {
+        record.iter().map(|field| String::from_utf8_lossy(field).to_string()).collect()
+    };
+    let mut table = DataTable {
+        header: fields(reader.byte_headers()?),
+        ..Default::default()
+    };
+    for record in reader.byte_records() {
+        if table.rows.len() == limit {
+            table.more = true;
+            break;
+        }
+        table.rows.push(fields(&record?));
+    }
+    for row in std::iter::once(&table.header).chain(&table.rows) {
+        for (i, field) in row.iter().enumerate() {
+            let width = field.chars().count().min(MAX_COLUMN_WIDTH);
+            match table.widths.get_mut(i) {
+                Some(max) => *max = (*max).max(width),
+                None => table.widths.push(width),
+            }
+        }
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_pretty_print() {
+        assert_eq!(Some(DataFormat::Yaml), DataFormat::detect(Path::new("a/b.YML")));
+        assert_eq!(None, DataFormat::detect(Path::new("Cargo.lock")));
+
+        // keys keep their order
+        assert_eq!("{\n  \"b\": 1,\n  \"a\": [\n    true\n  ]\n}",
+                   pretty_print("{\"b\":1,\"a\":[true]}", DataFormat::Json).unwrap());
+        let error = pretty_print("{\n  \"a\": 1,\n  \"b\" 2\n}", DataFormat::Json).unwrap_err();
+        assert_eq!(Some(3), error.line);
+
+        assert_eq!("a: 1\nb:\n  - x\n---\nc: ~\n", pretty_print("a: 1\nb: [x]\n---\nc:\n", DataFormat::Yaml).unwrap());
+        let error = pretty_print("a: 1\nb: [x\n", DataFormat::Yaml).unwrap_err();
+        assert!(error.line.is_some());
+
+        assert_eq!("z = 1\n\n[a]\nb = \"c\"\n", pretty_print("z=1\n[a]\nb='c'", DataFormat::Toml).unwrap());
+        let error = pretty_print("a = 1\n\nb = \n", DataFormat::Toml).unwrap_err();
+        assert_eq!(Some(3), error.line);
+        assert!(error.message.ends_with("at line 3"));
+    }
+
+    #[test]
+    fn test_read_table() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("a.csv");
+        fs::write(&path, "name,size\n\"b, c\",1\nlonger name,22,extra\n").unwrap();
+        let table = read_table(&path, DataFormat::Csv, 10).unwrap();
+        assert_eq!(vec!["name", "size"], table.header);
+        assert_eq!(vec!["b, c", "1"], table.rows[0]);
+        assert_eq!(vec![11, 4, 5], table.widths);
+        assert!(!table.more);
+        assert!(read_table(&path, DataFormat::Csv, 1).unwrap().more);
+
+        let path = tmp.path().join("a.tsv");
+        fs::write(&path, "a\tb\n1,2\t3\n").unwrap();
+        assert_eq!(vec!["1,2", "3"], read_table(&path, DataFormat::Tsv, 10).unwrap().rows[0]);
+    }
+}
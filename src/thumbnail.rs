@@ -0,0 +1,203 @@
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::Result;
+use image::imageops::FilterType;
+use image::{ColorType, DynamicImage, ImageFormat, ImageReader, Rgba};
+use log::debug;
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+
+use crate::worker::{Pending, Worker};
+
+/// Pixels this transparent (or more) are left blank.
+const MIN_ALPHA: u8 = 128;
+
+/// The image formats which are previewed as thumbnails.
+const THUMBNAIL_FORMATS: [ImageFormat; 5] = [ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::Gif, ImageFormat::WebP, ImageFormat::Bmp];
+
+/// An image scaled down to fit the preview, two pixels per cell.
+#[derive(Debug)]
+pub struct Thumbnail {
+    pub width: u32,
+    pub height: u32,
+    pub color_type: String,
+    pub lines: Vec<Line<'static>>,
+}
+
+/// Recognize an image which can be previewed by its first bytes.
+pub(crate) fn image_format(path: &Path) -> Option<ImageFormat> {
+    let mut header = vec![];
+    File::open(path).ok()?.take(32).read_to_end(&mut header).ok()?;
+    image::guess_format(&header)
+        .ok()
+        .filter(|format| THUMBNAIL_FORMATS.contains(format))
+}
+
+/// How many images are decoded at the same time.
+const DECODE_THREADS: usize = 2;
+
+/// Decode the image at `path` in a worker thread, to fit `cols` x `rows` cells. Dropping the
+/// pending thumbnail skips the image, unless decoding it started already.
+pub(crate) fn load_thumbnail(path: &Path, format: ImageFormat, cols: u16, rows: u16) -> Pending<Thumbnail> {
+    static WORKER: OnceLock<Worker> = OnceLock::new();
+    let path = path.to_path_buf();
+    let truecolor = supports_truecolor();
+    WORKER.get_or_init(|| Worker::new("thumbnail", DECODE_THREADS)).run(move || {
+        let result = thumbnail(&path, format, cols, rows, truecolor);
+        if let Err(e) = &result {
+            debug!("unable to decode {}: {}", path.display(), e);
+        }
+        result
+    })
+}
+
+fn thumbnail(path: &Path, format: ImageFormat, cols: u16, rows: u16, truecolor: bool) -> Result<Thumbnail> {
+    let mut reader = ImageReader::open(path)?;
+    reader.set_format(format);
+    let mut image = reader.decode()?;
+    let (width, height, color_type) = (image.width(), image.height(), image.color());
+    // cells are about twice as high as wide, so two pixels per cell keep the aspect ratio
+    let (max_width, max_height) = (u32::from(cols).max(1), u32::from(rows).max(1) * 2);
+    if width > max_width || height > max_height {
+        image = image.resize(max_width, max_height, FilterType::Triangle);
+    }
+    Ok(Thumbnail {
+        width,
+        height,
+        color_type: color_type_name(color_type),
+        lines: half_blocks(&image, truecolor),
+    })
+}
+
+/// Whether the terminal says it can show 24-bit colors.
+fn supports_truecolor() -> bool {
+    env::var("COLORTERM").is_ok_and(|value| value == "truecolor" || value == "24bit")
+}
+
+/// Render each pair of pixel rows as a row of `▀`, with the upper pixel as the foreground and
+/// the lower one as the background.
+fn half_blocks(image: &DynamicImage, truecolor: bool) -> Vec<Line<'static>> {
+    let image = image.to_rgba8();
+    let color = |pixel: &Rgba<u8>| -> Option<Color> {
+        let [r, g, b, a] = pixel.0;
+        match (a >= MIN_ALPHA, truecolor) {
+            (false, _) => None,
+            (true, true) => Some(Color::Rgb(r, g, b)),
+            (true, false) => Some(Color::Indexed(ansi256(r, g, b))),
+        }
+    };
+    (0..image.height())
+        .step_by(2)
+        .map(|y| {
+            let spans: Vec<Span> = (0..image.width())
+                .map(|x| {
+                    let upper = color(image.get_pixel(x, y));
+                    let lower = (y + 1 < image.height()).then(|| color(image.get_pixel(x, y + 1))).flatten();
+                    match (upper, lower) {
+                        (Some(upper), Some(lower)) => Span::styled("▀", Style::default().fg(upper).bg(lower)),
+                        (Some(upper), None) => Span::styled("▀", Style::default().fg(upper)),
+                        (None, Some(lower)) => Span::styled("▄", Style::default().fg(lower)),
+                        (None, None) => Span::raw(" "),
+                    }
+                })
+                .collect();
+            Line::from(spans)
+        })
+        .collect()
+}
+
+/// The closest color of the 256-color palette: the 6x6x6 cube from index 16, or the gray ramp
+/// from index 232.
+fn ansi256(r: u8, g: u8, b: u8) -> u8 {
+    const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let cube_index = |v: u8| match v {
+        0..=47 => 0,
+        48..=114 => 1,
+        _ => (v - 35) / 40,
+    };
+    let (ri, gi, bi) = (cube_index(r), cube_index(g), cube_index(b));
+    let cube = (CUBE_LEVELS[ri as usize], CUBE_LEVELS[gi as usize], CUBE_LEVELS[bi as usize]);
+
+    let average = (u16::from(r) + u16::from(g) + u16::from(b)) / 3;
+    let gray_index = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray_level = 8 + gray_index * 10;
+
+    let distance = |(cr, cg, cb): (u8, u8, u8)| -> i32 {
+        let (dr, dg, db) = (i32::from(r) - i32::from(cr), i32::from(g) - i32::from(cg), i32::from(b) - i32::from(cb));
+        dr * dr + dg * dg + db * db
+    };
+    match distance((gray_level, gray_level, gray_level)) < distance(cube) {
+        true => 232 + gray_index,
+        false => 16 + 36 * ri + 6 * gi + bi,
+    }
+}
+
+fn color_type_name(color_type: ColorType) -> String {
+    let name = match color_type {
+        ColorType::L8 => "grayscale",
+        ColorType::La8 => "grayscale + alpha",
+        ColorType::Rgb8 => "RGB",
+        ColorType::Rgba8 => "RGBA",
+        ColorType::L16 => "grayscale, 16 bit",
+        ColorType::La16 => "grayscale + alpha, 16 bit",
+        ColorType::Rgb16 => "RGB, 16 bit",
+        ColorType::Rgba16 => "RGBA, 16 bit",
+        ColorType::Rgb32F => "RGB, float",
+        ColorType::Rgba32F => "RGBA, float",
+        _ => return format!("{:?}", color_type),
+    };
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    #[test]
+    fn test_ansi256() {
+        assert_eq!(16, ansi256(0, 0, 0));
+        assert_eq!(231, ansi256(255, 255, 255));
+        assert_eq!(196, ansi256(255, 0, 0));
+        assert_eq!(244, ansi256(128, 128, 128));
+        assert_eq!(208, ansi256(255, 135, 0));
+    }
+
+    #[test]
+    fn test_thumbnail() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("a.png");
+        let mut image = RgbaImage::new(3, 3);
+        image.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        image.put_pixel(0, 1, Rgba([0, 0, 255, 255]));
+        image.put_pixel(1, 1, Rgba([0, 255, 0, 255]));
+        image.put_pixel(2, 2, Rgba([255, 255, 255, 255]));
+        image.save(&path).unwrap();
+        let text = tmp.path().join("a.txt");
+        std::fs::write(&text, "not an image").unwrap();
+        assert_eq!(Some(ImageFormat::Png), image_format(&path));
+        assert_eq!(None, image_format(&text));
+
+        let full = thumbnail(&path, ImageFormat::Png, 80, 24, true).unwrap();
+        assert_eq!((3, 3), (full.width, full.height));
+        assert_eq!("RGBA", full.color_type);
+        assert_eq!(vec!["▀▄ ", "  ▀"], full.lines.iter().map(|l| l.to_string()).collect::<Vec<String>>());
+        let style = full.lines[0].spans[0].style;
+        assert_eq!((Some(Color::Rgb(255, 0, 0)), Some(Color::Rgb(0, 0, 255))), (style.fg, style.bg));
+        assert_eq!(Some(Color::Rgb(0, 255, 0)), full.lines[0].spans[1].style.fg);
+
+        // scaled down to fit, two pixels per row
+        let small = thumbnail(&path, ImageFormat::Png, 2, 1, false).unwrap();
+        assert_eq!(1, small.lines.len());
+        assert_eq!(2, small.lines[0].spans.len());
+        assert!(matches!(small.lines[0].spans[0].style.fg, Some(Color::Indexed(_))));
+
+        let received = load_thumbnail(&path, ImageFormat::Png, 80, 24).recv().unwrap();
+        assert_eq!(2, received.lines.len());
+        assert!(thumbnail(&text, ImageFormat::Png, 80, 24, true).is_err());
+    }
+}
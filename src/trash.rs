@@ -0,0 +1,486 @@
+use std::{env, fs};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+#[cfg(target_os = "linux")]
+use std::os::linux::fs::MetadataExt;
+#[cfg(target_os = "macos")]
+use std::os::macos::fs::MetadataExt;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveDateTime};
+use fs_extra::dir::CopyOptions;
+use log::{debug, info};
+use ratatui::widgets::TableState;
+use serde::{Deserialize, Serialize};
+
+const TRASH_INFO_EXT: &str = "trashinfo";
+const TRASH_DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// A trash directory, as described by the freedesktop.org trash specification.
+/// It contains a `files/` and an `info/` subdirectory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrashDir {
+    pub path: PathBuf,
+    /// The top directory of the mount for per-mount trash directories (paths in the
+    /// `.trashinfo` files are relative to it). `None` for the home trash.
+    pub topdir: Option<PathBuf>,
+}
+
+impl TrashDir {
+    fn files_dir(&self) -> PathBuf {
+        self.path.join("files")
+    }
+
+    fn info_dir(&self) -> PathBuf {
+        self.path.join("info")
+    }
+
+    fn create(&self) -> Result<()> {
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true).mode(0o700);
+        builder.create(self.files_dir())?;
+        builder.create(self.info_dir())?;
+        Ok(())
+    }
+}
+
+/// An item which lives in a trash directory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrashItem {
+    pub trash_dir: TrashDir,
+    /// The name of the item in `files/` (and of its `.trashinfo` file in `info/`).
+    pub name: String,
+    pub original_path: PathBuf,
+    pub deleted: Option<NaiveDateTime>,
+}
+
+impl TrashItem {
+    pub fn trashed_path(&self) -> PathBuf {
+        self.trash_dir.files_dir().join(&self.name)
+    }
+
+    fn info_path(&self) -> PathBuf {
+        self.trash_dir.info_dir().join(format!("{}.{}", self.name, TRASH_INFO_EXT))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Trash {
+    /// The home trash, usually `$XDG_DATA_HOME/Trash`.
+    pub home: PathBuf,
+    uid: u32,
+}
+
+impl Trash {
+    /// The trash of the current user.
+    pub(crate) fn new() -> Result<Self> {
+        let data_home = match env::var_os("XDG_DATA_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => {
+                let home = env::var_os("HOME")
+                    .ok_or_else(|| anyhow!("unable to locate home directory"))?;
+                Path::new(&home).join(".local/share")
+            }
+        };
+        Ok(Self::with_home(data_home.join("Trash")))
+    }
+
+    /// A trash with an explicit home trash directory.
+    pub(crate) fn with_home(home: PathBuf) -> Self {
+        Self {
+            home,
+            uid: users::get_current_uid(),
+        }
+    }
+
+    fn home_trash(&self) -> TrashDir {
+        TrashDir { path: self.home.clone(), topdir: None }
+    }
+
+    /// Move `path` into the trash. Items on the same filesystem as the home trash go there;
+    /// other items go into the trash directory at the top of their mount.
+    pub(crate) fn trash(&self, path: &Path) -> Result<TrashItem> {
+        let path = absolute(path)?;
+        fs::symlink_metadata(&path)?;
+        let home_dev = device_of(&self.home)?;
+        let path_dev = device_of(&path)?;
+
+        if home_dev != path_dev {
+            let topdir = mount_topdir(&path)?;
+            match self.topdir_trash(&topdir) {
+                Ok(trash_dir) => return self.move_into(&path, &trash_dir),
+                Err(e) => info!("no trash at {}: {}; using home trash", topdir.display(), e),
+            }
+        }
+        self.move_into(&path, &self.home_trash())
+    }
+
+    /// The trash directory for the mount at `topdir`: `$topdir/.Trash/$uid` if the admin created
+    /// a (sticky, non-symlink) `.Trash` directory, otherwise `$topdir/.Trash-$uid`.
+    pub(crate) fn topdir_trash(&self, topdir: &Path) -> Result<TrashDir> {
+        let shared = topdir.join(".Trash");
+        if let Ok(meta) = fs::symlink_metadata(&shared) {
+            let sticky = meta.permissions().mode() & 0o1000 != 0;
+            if meta.is_dir() && sticky {
+                let trash_dir = TrashDir { path: shared.join(self.uid.to_string()), topdir: Some(topdir.to_path_buf()) };
+                if trash_dir.create().is_ok() {
+                    return Ok(trash_dir);
+                }
+            }
+        }
+        let trash_dir = TrashDir {
+            path: topdir.join(format!(".Trash-{}", self.uid)),
+            topdir: Some(topdir.to_path_buf()),
+        };
+        trash_dir.create()?;
+        Ok(trash_dir)
+    }
+
+    fn move_into(&self, path: &Path, trash_dir: &TrashDir) -> Result<TrashItem> {
+        trash_dir.create()?;
+        let file_name = path.file_name()
+            .ok_or_else(|| anyhow!("invalid file name"))?
+            .to_string_lossy()
+            .to_string();
+
+        // the info file is created first (exclusively), which reserves the name
+        let original_path = match &trash_dir.topdir {
+            Some(topdir) => path.strip_prefix(topdir).unwrap_or(path).to_path_buf(),
+            None => path.to_path_buf(),
+        };
+        let deleted = Local::now().naive_local();
+        let mut n = 1;
+        let item = loop {
+            let name = match n {
+                1 => file_name.clone(),
+                _ => format!("{}.{}", file_name, n),
+            };
+            let item = TrashItem {
+                trash_dir: trash_dir.clone(),
+                name,
+                original_path: path.to_path_buf(),
+                deleted: Some(deleted),
+            };
+            let file = OpenOptions::new().write(true).create_new(true).open(item.info_path());
+            match file {
+                Ok(mut file) => {
+                    write!(file, "[Trash Info]\nPath={}\nDeletionDate={}\n",
+                           percent_encode(&original_path),
+                           deleted.format(TRASH_DATE_FORMAT))?;
+                    if fs::symlink_metadata(item.trashed_path()).is_err() {
+                        break item;
+                    }
+                    // a stale file without an info file; don't touch it
+                    fs::remove_file(item.info_path())?;
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {},
+                Err(e) => return Err(e.into()),
+            }
+            n += 1;
+        };
+
+        if let Err(e) = move_path(path, &item.trashed_path()) {
+            let _result = fs::remove_file(item.info_path());
+            return Err(e);
+        }
+        debug!("trashed {} as {}", path.display(), item.trashed_path().display());
+        Ok(item)
+    }
+
+    /// Every trash directory of the current user which exists: the home trash, plus
+    /// `.Trash/$uid` and `.Trash-$uid` on each mounted filesystem.
+    pub(crate) fn trash_dirs(&self) -> Vec<TrashDir> {
+        let mut dirs = vec![self.home_trash()];
+        for topdir in mount_points() {
+            for path in [topdir.join(".Trash").join(self.uid.to_string()), topdir.join(format!(".Trash-{}", self.uid))] {
+                if path.join("info").is_dir() && !dirs.iter().any(|d| d.path == path) {
+                    dirs.push(TrashDir { path, topdir: Some(topdir.clone()) });
+                }
+            }
+        }
+        dirs
+    }
+
+    /// All items in all trash directories, most recently deleted first.
+    pub(crate) fn list(&self) -> Vec<TrashItem> {
+        let mut items: Vec<TrashItem> = self.trash_dirs()
+            .iter()
+            .flat_map(list_trash_dir)
+            .collect();
+        items.sort_by_key(|item| std::cmp::Reverse(item.deleted));
+        items
+    }
+
+    /// Move a trashed item back to where it came from.
+    pub(crate) fn restore(&self, item: &TrashItem) -> Result<PathBuf> {
+        if fs::symlink_metadata(&item.original_path).is_ok() {
+            return Err(anyhow!("{} already exists", item.original_path.display()));
+        }
+        if let Some(parent) = item.original_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        move_path(&item.trashed_path(), &item.original_path)?;
+        fs::remove_file(item.info_path())?;
+        Ok(item.original_path.clone())
+    }
+
+    /// Permanently delete a trashed item.
+    pub(crate) fn purge(&self, item: &TrashItem) -> Result<()> {
+        let trashed_path = item.trashed_path();
+        match fs::symlink_metadata(&trashed_path) {
+            Ok(meta) if meta.is_dir() => fs::remove_dir_all(&trashed_path)?,
+            Ok(_) => fs::remove_file(&trashed_path)?,
+            Err(_) => {},
+        }
+        fs::remove_file(item.info_path())?;
+        Ok(())
+    }
+}
+
+/// State of the trash popup.
+#[derive(Debug, Default)]
+pub struct TrashView {
+    pub items: Vec<TrashItem>,
+    pub state: TableState,
+    pub message: Option<String>,
+}
+
+impl TrashView {
+    pub(crate) fn reload(&mut self, trash: &Trash) {
+        self.items = trash.list();
+        let selected = match self.state.selected() {
+            _ if self.items.is_empty() => None,
+            Some(i) => Some(i.min(self.items.len() - 1)),
+            None => Some(0),
+        };
+        self.state.select(selected);
+    }
+
+    pub(crate) fn selected_item(&self) -> Option<&TrashItem> {
+        self.state.selected().and_then(|i| self.items.get(i))
+    }
+}
+
+fn list_trash_dir(trash_dir: &TrashDir) -> Vec<TrashItem> {
+    let Ok(entries) = fs::read_dir(trash_dir.info_dir()) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension()? != TRASH_INFO_EXT {
+                return None;
+            }
+            let name = path.file_stem()?.to_string_lossy().to_string();
+            let contents = fs::read_to_string(&path).ok()?;
+            let (original_path, deleted) = parse_trash_info(&contents)?;
+            let original_path = match &trash_dir.topdir {
+                Some(topdir) if original_path.is_relative() => topdir.join(original_path),
+                _ => original_path,
+            };
+            Some(TrashItem { trash_dir: trash_dir.clone(), name, original_path, deleted })
+        })
+        .collect()
+}
+
+/// Parse the `Path` and `DeletionDate` keys of a `.trashinfo` file.
+fn parse_trash_info(contents: &str) -> Option<(PathBuf, Option<NaiveDateTime>)> {
+    let mut in_section = false;
+    let mut path = None;
+    let mut deleted = None;
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_section = line == "[Trash Info]";
+            continue;
+        }
+        if !in_section {
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("Path=") {
+            path = Some(percent_decode(value));
+        } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+            deleted = NaiveDateTime::parse_from_str(value, TRASH_DATE_FORMAT).ok();
+        }
+    }
+    path.map(|p| (p, deleted))
+}
+
+fn percent_encode(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &b in path.as_os_str().as_bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(format!("%{:02X}", b).as_str());
+        }
+    }
+    encoded
+}
+
+fn percent_decode(value: &str) -> PathBuf {
+    let bytes = value.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    PathBuf::from(std::ffi::OsStr::from_bytes(&decoded))
+}
+
+fn absolute(path: &Path) -> Result<PathBuf> {
+    // canonicalize the parent only; the item itself may be a symlink
+    let file_name = path.file_name().ok_or_else(|| anyhow!("invalid file name"))?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.canonicalize()?,
+        _ => env::current_dir()?,
+    };
+    Ok(parent.join(file_name))
+}
+
+/// The device of `path`, or of its nearest existing ancestor.
+fn device_of(path: &Path) -> Result<u64> {
+    for ancestor in path.ancestors() {
+        if let Ok(meta) = fs::symlink_metadata(ancestor) {
+            return Ok(meta.st_dev());
+        }
+    }
+    Err(anyhow!("unable to stat {}", path.display()))
+}
+
+/// The top directory of the mount which contains `path`.
+fn mount_topdir(path: &Path) -> Result<PathBuf> {
+    let dev = device_of(path)?;
+    let mut topdir = path.parent().unwrap_or(path).to_path_buf();
+    for ancestor in path.ancestors().skip(1) {
+        match fs::metadata(ancestor) {
+            Ok(meta) if meta.st_dev() == dev => topdir = ancestor.to_path_buf(),
+            _ => break,
+        }
+    }
+    Ok(topdir)
+}
+
+#[cfg(target_os = "linux")]
+fn mount_points() -> Vec<PathBuf> {
+    let Ok(mounts) = fs::read_to_string("/proc/mounts") else {
+        return vec![];
+    };
+    mounts
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        // spaces etc. are octal-escaped in /proc/mounts
+        .map(|mount| PathBuf::from(mount.replace("\\040", " ")))
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn mount_points() -> Vec<PathBuf> {
+    vec![]
+}
+
+/// Rename `from` to `to`, falling back to copy + delete across filesystems.
+pub(crate) fn move_path(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    let meta = fs::symlink_metadata(from)?;
+    if meta.is_dir() {
+        fs_extra::dir::copy(from, to, &CopyOptions::new().copy_inside(true))?;
+        fs::remove_dir_all(from)?;
+    } else {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trash_list_restore() {
+        let tmp = tempfile::tempdir().unwrap();
+        let trash = Trash::with_home(tmp.path().join("Trash"));
+        let file = tmp.path().join("my file.txt");
+        fs::write(&file, "hello").unwrap();
+
+        let item = trash.trash(&file).unwrap();
+        assert!(!file.exists());
+        assert_eq!("hello", fs::read_to_string(item.trashed_path()).unwrap());
+        let info = fs::read_to_string(item.info_path()).unwrap();
+        assert!(info.starts_with("[Trash Info]\nPath="));
+        assert!(info.contains("my%20file.txt"));
+
+        // trashing a second item with the same name must not clobber the first
+        fs::write(&file, "again").unwrap();
+        let item2 = trash.trash(&file).unwrap();
+        assert_eq!("my file.txt.2", item2.name);
+
+        // ignore per-mount trash directories of the machine running the tests
+        let home_items = |trash: &Trash| -> Vec<TrashItem> {
+            trash.list().into_iter().filter(|i| i.trash_dir.topdir.is_none()).collect()
+        };
+        let items = home_items(&trash);
+        assert_eq!(2, items.len());
+        assert!(items.iter().all(|i| i.original_path == file && i.deleted.is_some()));
+
+        trash.restore(&item).unwrap();
+        assert_eq!("hello", fs::read_to_string(&file).unwrap());
+        // the original path is taken now
+        assert!(trash.restore(&item2).is_err());
+
+        trash.purge(&item2).unwrap();
+        assert!(home_items(&trash).is_empty());
+        assert!(!item2.trashed_path().exists());
+    }
+
+    #[test]
+    fn test_topdir_trash() {
+        let tmp = tempfile::tempdir().unwrap();
+        let trash = Trash::with_home(tmp.path().join("Trash"));
+        let topdir = tmp.path().join("mnt");
+        fs::create_dir(&topdir).unwrap();
+
+        // without a sticky `.Trash`, `.Trash-$uid` is used
+        let trash_dir = trash.topdir_trash(&topdir).unwrap();
+        assert_eq!(topdir.join(format!(".Trash-{}", trash.uid)), trash_dir.path);
+        assert!(trash_dir.info_dir().is_dir());
+
+        let shared = topdir.join(".Trash");
+        fs::create_dir(&shared).unwrap();
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o1777)).unwrap();
+        let trash_dir = trash.topdir_trash(&topdir).unwrap();
+        assert_eq!(shared.join(trash.uid.to_string()), trash_dir.path);
+
+        // paths are stored relative to the topdir
+        let file = topdir.join("a.txt");
+        fs::write(&file, "hello").unwrap();
+        let item = trash.move_into(&file, &trash_dir).unwrap();
+        let info = fs::read_to_string(item.info_path()).unwrap();
+        assert!(info.contains("\nPath=a.txt\n"));
+        let items = list_trash_dir(&trash_dir);
+        assert_eq!(file, items[0].original_path);
+    }
+
+    #[test]
+    fn test_percent_encoding() {
+        let path = Path::new("/tmp/a b/100%.txt");
+        assert_eq!("/tmp/a%20b/100%25.txt", percent_encode(path));
+        assert_eq!(path, percent_decode(percent_encode(path).as_str()));
+    }
+}
@@ -0,0 +1,89 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use ignore::WalkBuilder;
+use log::debug;
+
+/// Walk the tree below `root` depth-first in name order, calling `visit` with the path
+/// (relative to `root`) of every non-directory entry. Symlinks are reported but never
+/// followed. With `respect_ignore`, entries matched by `.gitignore` / `.ignore` rules are
+/// skipped. Stops early when `cancel` is set or `visit` returns `false`.
+pub(crate) fn walk_files(
+    root: &Path,
+    show_hidden: bool,
+    respect_ignore: bool,
+    cancel: &AtomicBool,
+    visit: &mut dyn FnMut(&Path) -> bool,
+) {
+    let walker = WalkBuilder::new(root)
+        .standard_filters(respect_ignore)
+        .hidden(!show_hidden)
+        .follow_links(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+    for entry in walker {
+        if cancel.load(Ordering::Relaxed) {
+            debug!("walk of {} cancelled", root.display());
+            return;
+        }
+        let Ok(entry) = entry else {
+            continue;
+        };
+        if entry.file_type().is_none_or(|t| t.is_dir()) {
+            continue;
+        }
+        let Ok(rel_path) = entry.path().strip_prefix(root) else {
+            continue;
+        };
+        if !visit(rel_path) {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn walk(root: &Path, show_hidden: bool, respect_ignore: bool) -> Vec<String> {
+        let mut found = vec![];
+        walk_files(root, show_hidden, respect_ignore, &AtomicBool::new(false), &mut |path| {
+            found.push(path.to_string_lossy().to_string());
+            true
+        });
+        found
+    }
+
+    #[test]
+    fn test_walk_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join("a/b")).unwrap();
+        fs::create_dir_all(tmp.path().join(".git")).unwrap();
+        for name in ["z.txt", "a/1.txt", "a/b/2.txt", ".git/config", ".env"] {
+            fs::write(tmp.path().join(name), "").unwrap();
+        }
+        assert_eq!(vec!["a/1.txt", "a/b/2.txt", "z.txt"], walk(tmp.path(), false, false));
+
+        let mut count = 0;
+        walk_files(tmp.path(), true, false, &AtomicBool::new(false), &mut |_| {
+            count += 1;
+            count < 2
+        });
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn test_walk_respects_ignore_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join(".git")).unwrap();
+        fs::create_dir_all(tmp.path().join("target/debug")).unwrap();
+        fs::write(tmp.path().join(".gitignore"), "target/\n").unwrap();
+        fs::write(tmp.path().join(".ignore"), "*.tmp\n").unwrap();
+        for name in ["main.rs", "x.tmp", "target/debug/out"] {
+            fs::write(tmp.path().join(name), "").unwrap();
+        }
+        assert_eq!(vec!["main.rs"], walk(tmp.path(), false, true));
+        assert_eq!(vec!["main.rs", "target/debug/out", "x.tmp"], walk(tmp.path(), false, false));
+    }
+}
@@ -0,0 +1,189 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use log::debug;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Threads which run the jobs of one kind of preview (e.g. decoding images) in the order they
+/// were given. Threads are started as needed, up to `max_threads`, and then reused. A job whose
+/// `Pending` result was dropped before it started (the preview moved on to another file) is
+/// skipped.
+pub(crate) struct Worker {
+    name: &'static str,
+    max_threads: usize,
+    /// Jobs which take longer are reported as failed. As threads can't be stopped, the job keeps
+    /// its thread busy until it is done, and the next jobs go to the other threads.
+    timeout: Option<Duration>,
+    tx: Sender<Job>,
+    rx: Arc<Mutex<Receiver<Job>>>,
+    threads: AtomicUsize,
+    /// Threads waiting for a job.
+    idle: Arc<AtomicUsize>,
+}
+
+/// The result of a job given to a `Worker`.
+#[derive(Debug)]
+pub struct Pending<T> {
+    rx: Receiver<Result<T>>,
+    /// When the job was given, for the timeout of the worker; also counts the time spent waiting
+    /// for a thread, in case all of them are stuck.
+    given: Instant,
+    timeout: Option<Duration>,
+    /// The job only runs while this is around.
+    _wanted: Arc<()>,
+}
+
+impl Worker {
+    pub(crate) fn new(name: &'static str, max_threads: usize) -> Self {
+        let (tx, rx) = channel();
+        Self {
+            name,
+            max_threads,
+            timeout: None,
+            tx,
+            rx: Arc::new(Mutex::new(rx)),
+            threads: AtomicUsize::new(0),
+            idle: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub(crate) fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Run `job` in one of the threads.
+    pub(crate) fn run<T, F>(&self, job: F) -> Pending<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T> + Send + 'static,
+    {
+        let (tx, rx) = channel();
+        let wanted = Arc::new(());
+        let weak: Weak<()> = Arc::downgrade(&wanted);
+        let name = self.name;
+        let job: Job = Box::new(move || {
+            if weak.strong_count() == 0 {
+                debug!("{} job skipped, its result is no longer wanted", name);
+                return;
+            }
+            let _result = tx.send(job());
+        });
+        // the receiving end lives as long as the worker
+        let _result = self.tx.send(job);
+        if self.idle.load(Ordering::Relaxed) == 0 && self.threads.load(Ordering::Relaxed) < self.max_threads {
+            self.spawn();
+        }
+        Pending { rx, given: Instant::now(), timeout: self.timeout, _wanted: wanted }
+    }
+
+    fn spawn(&self) {
+        let number = self.threads.fetch_add(1, Ordering::Relaxed) + 1;
+        debug!("starting {} worker thread {}", self.name, number);
+        let (rx, idle) = (self.rx.clone(), self.idle.clone());
+        thread::spawn(move || {
+            loop {
+                idle.fetch_add(1, Ordering::Relaxed);
+                let job = match rx.lock() {
+                    Ok(rx) => rx.recv(),
+                    Err(_) => break,
+                };
+                idle.fetch_sub(1, Ordering::Relaxed);
+                let Ok(job) = job else {
+                    break;
+                };
+                // a job which panics drops its sender, which is reported as a crash
+                let _result = panic::catch_unwind(AssertUnwindSafe(job));
+            }
+        });
+    }
+}
+
+impl<T> Pending<T> {
+    /// The result, if the job is done or ran out of time.
+    pub(crate) fn try_recv(&self) -> Option<Result<T>> {
+        match self.rx.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => match self.timeout {
+                Some(timeout) if self.given.elapsed() >= timeout => Some(Err(timed_out(timeout))),
+                _ => None,
+            },
+            Err(TryRecvError::Disconnected) => Some(Err(anyhow!("the worker crashed"))),
+        }
+    }
+
+    /// Wait for the result.
+    #[cfg(test)]
+    pub(crate) fn recv(&self) -> Result<T> {
+        use std::sync::mpsc::RecvTimeoutError;
+
+        let Some(timeout) = self.timeout else {
+            return self.rx.recv().unwrap_or_else(|_| Err(anyhow!("the worker crashed")));
+        };
+        match self.rx.recv_timeout(timeout.saturating_sub(self.given.elapsed())) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => Err(timed_out(timeout)),
+            Err(RecvTimeoutError::Disconnected) => Err(anyhow!("the worker crashed")),
+        }
+    }
+}
+
+fn timed_out(timeout: Duration) -> anyhow::Error {
+    anyhow!("timed out after {} seconds", timeout.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_worker() {
+        let worker = Worker::new("test", 2);
+        let (started_tx, started_rx) = channel();
+        let (go_tx, go_rx) = channel::<()>();
+        let go_rx = Arc::new(Mutex::new(go_rx));
+        let mut blocked = vec![];
+        for _ in 0..2 {
+            // another thread takes over while the first one is busy
+            let (started_tx, go_rx) = (started_tx.clone(), go_rx.clone());
+            blocked.push(worker.run(move || {
+                started_tx.send(()).unwrap();
+                Ok(go_rx.lock().unwrap().recv().is_ok())
+            }));
+            started_rx.recv().unwrap();
+        }
+
+        // queued behind the busy threads, and no longer wanted when its turn comes
+        let (ran_tx, ran_rx) = channel();
+        drop(worker.run(move || Ok(ran_tx.send(()).is_ok())));
+        for _ in 0..2 {
+            go_tx.send(()).unwrap();
+        }
+        assert!(ran_rx.recv().is_err());
+        assert!(blocked.iter().all(|pending| pending.recv().unwrap()));
+
+        assert_eq!(3, worker.run(|| Ok(3)).recv().unwrap());
+        assert!(worker.run(|| -> Result<()> { panic!("a broken file") }).recv().is_err());
+        assert_eq!(2, worker.threads.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_worker_timeout() {
+        let worker = Worker::new("test", 1).with_timeout(Duration::from_secs(1));
+        let (go_tx, go_rx) = channel::<()>();
+        let stuck = worker.run(move || Ok(go_rx.recv().is_ok()));
+        let queued = worker.run(|| Ok(1));
+        assert!(stuck.try_recv().is_none());
+        assert!(stuck.recv().unwrap_err().to_string().starts_with("timed out"));
+        assert!(queued.try_recv().unwrap().is_err());
+
+        go_tx.send(()).unwrap();
+        assert_eq!(2, worker.run(|| Ok(2)).recv().unwrap());
+    }
+}